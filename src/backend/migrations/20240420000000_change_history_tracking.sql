@@ -0,0 +1,29 @@
+-- 列名をアプリケーション側のモデルに合わせる
+ALTER TABLE elements RENAME COLUMN type TO element_type;
+ALTER TABLE views RENAME COLUMN type TO view_type;
+ALTER TABLE element_relationships RENAME COLUMN type TO relationship_type;
+
+-- 変更履歴テーブルの再作成
+-- 削除された要素の履歴も保持するため、elementsへの外部キーを外す
+CREATE TABLE change_history_new (
+    id TEXT PRIMARY KEY,
+    project_id TEXT NOT NULL,
+    element_id TEXT NOT NULL,
+    change_type TEXT NOT NULL,
+    old_value JSON,
+    new_value JSON,
+    timestamp TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    user_id TEXT NOT NULL,
+    FOREIGN KEY (project_id) REFERENCES projects(id) ON DELETE CASCADE
+);
+
+INSERT INTO change_history_new
+SELECT id, project_id, element_id, change_type, old_value, new_value, timestamp, user_id
+FROM change_history;
+
+DROP TABLE change_history;
+ALTER TABLE change_history_new RENAME TO change_history;
+
+CREATE INDEX idx_history_project ON change_history(project_id);
+CREATE INDEX idx_history_element ON change_history(element_id);
+CREATE INDEX idx_history_timestamp ON change_history(timestamp);
@@ -0,0 +1,5 @@
+-- 取り消し(undo)/再実行(redo)の状態管理
+-- applied: 適用中, undone: 取り消し済み, discarded: 新しい変更によりredo不可
+ALTER TABLE change_history ADD COLUMN undo_status TEXT NOT NULL DEFAULT 'applied';
+
+CREATE INDEX idx_history_user ON change_history(project_id, user_id, undo_status);
@@ -0,0 +1,3 @@
+-- 楽観的排他制御のためのバージョン列
+ALTER TABLE element_relationships ADD COLUMN version INTEGER NOT NULL DEFAULT 1;
+ALTER TABLE views ADD COLUMN version INTEGER NOT NULL DEFAULT 1;
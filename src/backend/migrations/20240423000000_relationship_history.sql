@@ -0,0 +1,12 @@
+-- 関係性をプロジェクト単位で扱うためのプロジェクトID
+ALTER TABLE element_relationships ADD COLUMN project_id TEXT REFERENCES projects(id) ON DELETE CASCADE;
+
+UPDATE element_relationships
+SET project_id = (
+    SELECT elements.project_id FROM elements WHERE elements.id = element_relationships.source_id
+);
+
+CREATE INDEX idx_relationships_project ON element_relationships(project_id);
+
+-- 変更履歴の対象種別（element / relationship）
+ALTER TABLE change_history ADD COLUMN entity_type TEXT NOT NULL DEFAULT 'element';
@@ -0,0 +1,17 @@
+-- 名前付きスナップショット（ベースライン）テーブル
+CREATE TABLE project_snapshots (
+    id TEXT PRIMARY KEY,
+    project_id TEXT NOT NULL,
+    label TEXT NOT NULL,
+    description TEXT,
+    project_version INTEGER NOT NULL,
+    elements JSON NOT NULL,
+    relationships JSON NOT NULL,
+    views JSON NOT NULL,
+    created_by TEXT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (project_id) REFERENCES projects(id) ON DELETE CASCADE,
+    UNIQUE (project_id, label)
+);
+
+CREATE INDEX idx_snapshots_project ON project_snapshots(project_id);
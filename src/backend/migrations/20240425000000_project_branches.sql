@@ -0,0 +1,19 @@
+-- プロジェクトのブランチ（フォーク）管理テーブル
+-- base_*: フォーク時点の親プロジェクトの状態（3-wayマージの基準）
+-- *_map: 親プロジェクトのID → ブランチ側のID
+CREATE TABLE project_branches (
+    project_id TEXT PRIMARY KEY,
+    parent_project_id TEXT NOT NULL,
+    base_elements JSON NOT NULL,
+    base_relationships JSON NOT NULL,
+    element_map JSON NOT NULL,
+    relationship_map JSON NOT NULL,
+    status TEXT NOT NULL DEFAULT 'open',
+    created_by TEXT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    merged_at TIMESTAMP,
+    FOREIGN KEY (project_id) REFERENCES projects(id) ON DELETE CASCADE,
+    FOREIGN KEY (parent_project_id) REFERENCES projects(id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_branches_parent ON project_branches(parent_project_id);
@@ -0,0 +1,9 @@
+-- プロジェクトごとの伝播ルール（未登録のプロジェクトは標準ルールを使用）
+CREATE TABLE propagation_rules (
+    project_id TEXT PRIMARY KEY,
+    rules JSON NOT NULL,
+    version INTEGER NOT NULL DEFAULT 1,
+    updated_by TEXT NOT NULL,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (project_id) REFERENCES projects(id) ON DELETE CASCADE
+);
@@ -0,0 +1,15 @@
+-- プロジェクトのメンバーと権限
+-- role: owner / editor / structural_editor / viewer
+-- メンバーでないユーザーはプロジェクトにアクセスできない
+-- 既存プロジェクトのオーナーは起動時に INITIAL_PROJECT_OWNER で登録する
+CREATE TABLE project_members (
+    project_id TEXT NOT NULL,
+    user_id TEXT NOT NULL,
+    role TEXT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (project_id, user_id),
+    FOREIGN KEY (project_id) REFERENCES projects(id) ON DELETE CASCADE,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_members_user ON project_members(user_id);
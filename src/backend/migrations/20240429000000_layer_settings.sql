@@ -0,0 +1,13 @@
+-- レイヤーごとのロック・表示設定
+-- allowed_users: ロック中でも編集できるユーザーIDの一覧（空なら全員に適用）
+CREATE TABLE layer_settings (
+    project_id TEXT NOT NULL,
+    name TEXT NOT NULL,
+    locked BOOLEAN NOT NULL DEFAULT FALSE,
+    visible BOOLEAN NOT NULL DEFAULT TRUE,
+    allowed_users JSON NOT NULL DEFAULT '[]',
+    updated_by TEXT NOT NULL,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (project_id, name),
+    FOREIGN KEY (project_id) REFERENCES projects(id) ON DELETE CASCADE
+);
@@ -0,0 +1,32 @@
+-- プロジェクトの変更イベント（追記のみ・変更の正とする記録）
+-- change_history と WebSocket の通知はこの記録から導出する
+-- seq: プロジェクト内で単調増加する通し番号（再接続時の再送の起点に使う）
+-- プロジェクト削除後も記録を残すため、projectsへの外部キーは持たない
+CREATE TABLE events (
+    id TEXT PRIMARY KEY,
+    project_id TEXT NOT NULL,
+    seq INTEGER NOT NULL,
+    entity_type TEXT NOT NULL,
+    entity_id TEXT NOT NULL,
+    change_type TEXT NOT NULL,
+    actor TEXT NOT NULL,
+    payload JSON NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE (project_id, seq)
+);
+
+CREATE INDEX idx_events_entity ON events(project_id, entity_type, entity_id);
+
+-- 既存の変更履歴をイベントとして取り込む（履歴IDをイベントIDとして引き継ぐ）
+INSERT INTO events (id, project_id, seq, entity_type, entity_id, change_type, actor, payload, created_at)
+SELECT
+    id,
+    project_id,
+    ROW_NUMBER() OVER (PARTITION BY project_id ORDER BY timestamp, rowid),
+    entity_type,
+    element_id,
+    change_type,
+    user_id,
+    json_object('before', json(old_value), 'after', json(new_value)),
+    timestamp
+FROM change_history;
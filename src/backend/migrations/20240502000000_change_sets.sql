@@ -0,0 +1,6 @@
+-- 一括操作などでまとめて適用した変更のグループ
+ALTER TABLE events ADD COLUMN change_set_id TEXT;
+ALTER TABLE change_history ADD COLUMN change_set_id TEXT;
+
+CREATE INDEX idx_events_change_set ON events(change_set_id);
+CREATE INDEX idx_change_history_change_set ON change_history(change_set_id);
@@ -0,0 +1,18 @@
+-- ユーザーの操作単位でまとめた変更のグループ（説明と実行者）
+CREATE TABLE change_sets (
+    id TEXT PRIMARY KEY,
+    project_id TEXT NOT NULL,
+    description TEXT NOT NULL,
+    actor TEXT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (project_id) REFERENCES projects(id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_change_sets_project ON change_sets(project_id);
+
+-- 既存の一括操作のグループを取り込む
+INSERT INTO change_sets (id, project_id, description, actor, created_at)
+SELECT change_set_id, project_id, 'Batch of ' || COUNT(*) || ' changes', MIN(user_id), MIN(timestamp)
+FROM change_history
+WHERE change_set_id IS NOT NULL
+GROUP BY change_set_id, project_id;
@@ -0,0 +1,5 @@
+-- 要素一覧の絞り込み・並び替え・ページ送り用
+CREATE INDEX idx_elements_project_created ON elements(project_id, created_at, id);
+CREATE INDEX idx_elements_project_updated ON elements(project_id, updated_at, id);
+CREATE INDEX idx_elements_project_type ON elements(project_id, element_type, id);
+CREATE INDEX idx_elements_project_layer ON elements(project_id, json_extract(properties, '$.common.layer'));
@@ -0,0 +1,17 @@
+-- 要素の外接矩形の空間インデックス（R*Tree）
+-- R*Tree のキーは整数のため、要素IDとの対応を別のテーブルで持つ
+CREATE TABLE element_bounds_keys (
+    id INTEGER PRIMARY KEY,
+    element_id TEXT NOT NULL UNIQUE REFERENCES elements(id) ON DELETE CASCADE,
+    project_id TEXT NOT NULL
+);
+
+CREATE VIRTUAL TABLE element_bounds USING rtree(id, min_x, max_x, min_y, max_y);
+
+-- 要素の削除（プロジェクト削除による連鎖削除を含む）に合わせて外接矩形も削除する
+CREATE TRIGGER element_bounds_keys_delete AFTER DELETE ON element_bounds_keys
+BEGIN
+    DELETE FROM element_bounds WHERE id = old.id;
+END;
+
+-- 既存の要素の外接矩形は起動時に登録する（db::index_missing_element_bounds）
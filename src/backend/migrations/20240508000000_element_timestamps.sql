@@ -0,0 +1,15 @@
+-- 要素の日時を、UTC・小数部9桁の固定幅の RFC 3339 形式にそろえる
+-- CURRENT_TIMESTAMP 形式や、小数部の末尾の0を省いた形式が混在すると、文字列の並び順が時刻の順にならなかった
+UPDATE elements
+SET updated_at = strftime('%Y-%m-%dT%H:%M:%S', updated_at) || '.' || substr(
+        CASE
+            WHEN instr(updated_at, '.') > 0 THEN rtrim(substr(updated_at, instr(updated_at, '.') + 1), 'Z')
+            ELSE ''
+        END || '000000000', 1, 9) || 'Z';
+
+UPDATE elements
+SET created_at = strftime('%Y-%m-%dT%H:%M:%S', created_at) || '.' || substr(
+        CASE
+            WHEN instr(created_at, '.') > 0 THEN rtrim(substr(created_at, instr(created_at, '.') + 1), 'Z')
+            ELSE ''
+        END || '000000000', 1, 9) || 'Z';
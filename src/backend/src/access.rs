@@ -0,0 +1,87 @@
+use serde_json::Value as JsonValue;
+use sqlx::SqlitePool;
+
+use crate::{
+    auth::AuthUser,
+    db,
+    error::{AppError, Result},
+    models::{
+        element::{Element, UpdateElement},
+        member::{Permission, ProjectRole},
+    },
+};
+
+// プロジェクトでのユーザーの権限を確認し、役割を返す（メンバーでなければ拒否する）
+pub async fn authorize(
+    pool: &SqlitePool,
+    project_id: &str,
+    user: &AuthUser,
+    permission: Permission,
+) -> Result<ProjectRole> {
+    let role = match db::get_member(pool, project_id, &user.id).await? {
+        Some(member) => member.role,
+        None => {
+            db::get_project(pool, project_id).await?;
+            return Err(AppError::Forbidden(format!(
+                "Not a member of project {}",
+                project_id
+            )));
+        }
+    };
+
+    if !role.allows(permission) {
+        return Err(AppError::Forbidden(format!(
+            "Role {} is not allowed to perform this operation",
+            role.as_str()
+        )));
+    }
+    Ok(role)
+}
+
+// 要素の更新が構造プロパティのみの変更か
+pub fn structural_only(before: &Element, data: &UpdateElement) -> Result<bool> {
+    if data.element_type.as_ref().is_some_and(|t| *t != before.element_type)
+        || data.geometry.as_ref().is_some_and(|g| *g != before.geometry)
+        || data
+            .metadata
+            .as_ref()
+            .map(serde_json::to_value)
+            .transpose()?
+            .is_some_and(|m| m != before.metadata)
+    {
+        return Ok(false);
+    }
+
+    let Some(properties) = &data.properties else {
+        return Ok(true);
+    };
+    Ok(without_structural(serde_json::to_value(properties)?)
+        == without_structural(before.properties.clone()))
+}
+
+// 構造プロパティと、形状からサーバーで計算する値を除いたプロパティ
+// null と未設定は区別しない
+fn without_structural(mut properties: JsonValue) -> JsonValue {
+    if let Some(map) = properties.as_object_mut() {
+        map.remove("structural");
+        if let Some(floor_plan) = map.get_mut("floorPlan").and_then(JsonValue::as_object_mut) {
+            for key in ["area", "perimeter", "centroid"] {
+                floor_plan.remove(key);
+            }
+        }
+    }
+    strip_nulls(properties)
+}
+
+fn strip_nulls(value: JsonValue) -> JsonValue {
+    match value {
+        JsonValue::Object(map) => JsonValue::Object(
+            map.into_iter()
+                .filter(|(_, v)| !v.is_null())
+                .map(|(k, v)| (k, strip_nulls(v)))
+                .filter(|(_, v)| !v.as_object().is_some_and(|m| m.is_empty()))
+                .collect(),
+        ),
+        other => other,
+    }
+}
@@ -0,0 +1,172 @@
+use argon2::{
+    password_hash::{
+        rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier,
+        SaltString,
+    },
+    Argon2,
+};
+use axum::{
+    async_trait,
+    extract::{FromRef, FromRequestParts, Query},
+    http::{header, request::Parts},
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use std::sync::OnceLock;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use time::{Duration, OffsetDateTime};
+
+use crate::{
+    db,
+    error::{AppError, Result},
+    AppState,
+};
+
+// トークンの有効期間
+const TOKEN_TTL: Duration = Duration::hours(12);
+
+pub fn hash_password(password: &str) -> Result<String> {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|e| AppError::Internal(format!("Failed to hash password: {}", e)))
+}
+
+// 存在しないユーザーのログインでも照合の時間をかけるためのハッシュ
+pub fn dummy_password_hash() -> &'static str {
+    static HASH: OnceLock<String> = OnceLock::new();
+    HASH.get_or_init(|| hash_password("dummy-password").unwrap_or_default())
+}
+
+pub fn verify_password(password: &str, password_hash: &str) -> bool {
+    PasswordHash::new(password_hash)
+        .map(|hash| {
+            Argon2::default()
+                .verify_password(password.as_bytes(), &hash)
+                .is_ok()
+        })
+        .unwrap_or(false)
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Claims {
+    sub: String,
+    exp: i64,
+}
+
+// HMAC-SHA256で署名したトークンの発行と検証
+// 形式: base64url(クレームJSON).base64url(署名)
+pub struct TokenSigner {
+    secret: Vec<u8>,
+}
+
+impl TokenSigner {
+    // トークンの署名鍵は AUTH_SECRET から読む（未設定・空の場合は起動しない）
+    pub fn from_env() -> std::result::Result<Self, String> {
+        match std::env::var("AUTH_SECRET") {
+            Ok(secret) if !secret.is_empty() => Ok(Self {
+                secret: secret.into_bytes(),
+            }),
+            _ => Err("AUTH_SECRET must be set to sign authentication tokens".to_string()),
+        }
+    }
+
+    pub fn issue(&self, user_id: &str) -> Result<(String, OffsetDateTime)> {
+        let expires_at = OffsetDateTime::now_utc() + TOKEN_TTL;
+        let claims = Claims {
+            sub: user_id.to_string(),
+            exp: expires_at.unix_timestamp(),
+        };
+        let payload = URL_SAFE_NO_PAD.encode(serde_json::to_vec(&claims)?);
+        let signature = URL_SAFE_NO_PAD.encode(self.mac(&payload).finalize().into_bytes());
+        Ok((format!("{}.{}", payload, signature), expires_at))
+    }
+
+    // 署名と有効期限を確認し、ユーザーIDを返す
+    pub fn verify(&self, token: &str) -> Result<String> {
+        let invalid = || AppError::Unauthorized("Invalid token".to_string());
+
+        let (payload, signature) = token.split_once('.').ok_or_else(invalid)?;
+        let signature = URL_SAFE_NO_PAD.decode(signature).map_err(|_| invalid())?;
+        self.mac(payload)
+            .verify_slice(&signature)
+            .map_err(|_| invalid())?;
+
+        let claims: Claims = URL_SAFE_NO_PAD
+            .decode(payload)
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .ok_or_else(invalid)?;
+        if claims.exp < OffsetDateTime::now_utc().unix_timestamp() {
+            return Err(AppError::Unauthorized("Token has expired".to_string()));
+        }
+        Ok(claims.sub)
+    }
+
+    fn mac(&self, payload: &str) -> Hmac<Sha256> {
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(&self.secret).expect("HMAC accepts any key length");
+        mac.update(payload.as_bytes());
+        mac
+    }
+}
+
+// 認証済みのユーザー
+// Authorization: Bearer <token> で指定する（WebSocketの接続要求に限り ?token=<token> も受け付ける）
+#[derive(Debug, Clone)]
+pub struct AuthUser {
+    pub id: String,
+    pub username: String,
+}
+
+#[derive(Deserialize)]
+struct TokenQuery {
+    token: Option<String>,
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for AuthUser
+where
+    S: Send + Sync,
+    AppState: FromRef<S>,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self> {
+        let state = AppState::from_ref(state);
+
+        let bearer = parts
+            .headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .map(|token| token.trim().to_string());
+        // ブラウザのWebSocketはヘッダーを付けられないため、接続要求のみクエリで受け取る
+        // （通常のリクエストでは、トークンがアクセスログに残らないよう受け付けない）
+        let upgrade = parts
+            .headers
+            .get(header::UPGRADE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.eq_ignore_ascii_case("websocket"));
+        let token = match bearer {
+            Some(token) => token,
+            None if upgrade => Query::<TokenQuery>::try_from_uri(&parts.uri)
+                .ok()
+                .and_then(|Query(query)| query.token)
+                .ok_or_else(|| AppError::Unauthorized("Missing bearer token".to_string()))?,
+            None => return Err(AppError::Unauthorized("Missing bearer token".to_string())),
+        };
+
+        let user_id = state.auth.verify(&token)?;
+        let user = db::get_user(&state.db, &user_id)
+            .await
+            .map_err(|_| AppError::Unauthorized("Unknown user".to_string()))?;
+
+        Ok(AuthUser {
+            id: user.id,
+            username: user.username,
+        })
+    }
+}
@@ -0,0 +1,444 @@
+use sqlx::SqliteConnection;
+
+use crate::{
+    db,
+    error::Result,
+    hosting,
+    models::{
+        clash::{Clash, ClashKind},
+        element::{
+            project_onto_polyline, Element, Point, ELEMENT_TYPE_OPENING, ELEMENT_TYPE_ROOM,
+            ELEMENT_TYPE_WALL,
+        },
+        relationship::{Relationship, RELATIONSHIP_TYPE_HOSTS},
+        spatial::Bounds,
+    },
+};
+
+// 重なりとみなす面積・距離の許容誤差
+const OVERLAP_TOLERANCE: f64 = 1e-6;
+
+// プロジェクト全体の干渉を検出する
+pub async fn detect_clashes(conn: &mut SqliteConnection, project_id: &str) -> Result<Vec<Clash>> {
+    let elements = db::list_elements(&mut *conn, project_id).await?;
+    let relationships = db::list_relationships(&mut *conn, project_id).await?;
+
+    // 外接矩形を x 方向に並べ、範囲が重なる組だけを詳しく調べる
+    let mut solids: Vec<(Bounds, &Element)> = elements
+        .iter()
+        .filter(|e| e.element_type == ELEMENT_TYPE_WALL || e.element_type == ELEMENT_TYPE_ROOM)
+        .map(|e| (Bounds::of(&e.geometry), e))
+        .collect();
+    solids.sort_by(|a, b| a.0.min_x.total_cmp(&b.0.min_x));
+
+    let mut clashes = Vec::new();
+    for (i, (bounds, a)) in solids.iter().enumerate() {
+        for (other, b) in solids[i + 1..].iter().take_while(|(o, _)| o.min_x <= bounds.max_x) {
+            if other.min_y <= bounds.max_y && other.max_y >= bounds.min_y {
+                clashes.extend(overlap_clash(a, b));
+            }
+        }
+    }
+
+    for relationship in relationships.iter().filter(|r| r.relationship_type == RELATIONSHIP_TYPE_HOSTS) {
+        let wall = elements.iter().find(|e| e.id == relationship.source_id);
+        let opening = elements.iter().find(|e| e.id == relationship.target_id);
+        if let (Some(wall), Some(opening)) = (wall, opening) {
+            clashes.extend(opening_clash(wall, opening));
+        }
+    }
+
+    Ok(clashes)
+}
+
+// 指定した要素が関わる干渉を検出する（要素の更新時の差分検出）
+pub async fn detect_element_clashes(
+    conn: &mut SqliteConnection,
+    element: &Element,
+) -> Result<Vec<Clash>> {
+    let mut clashes = Vec::new();
+
+    if element.element_type == ELEMENT_TYPE_WALL || element.element_type == ELEMENT_TYPE_ROOM {
+        let bounds = Bounds::of(&element.geometry);
+        let candidates = db::list_elements_in_bounds(&mut *conn, &element.project_id, &bounds).await?;
+        for other in candidates.iter().filter(|e| e.id != element.id) {
+            clashes.extend(overlap_clash(element, other));
+        }
+    }
+
+    let relationships = db::list_element_relationships(&mut *conn, &element.project_id, &element.id).await?;
+    for relationship in relationships
+        .iter()
+        .filter(|r| r.relationship_type == RELATIONSHIP_TYPE_HOSTS)
+    {
+        clashes.extend(hosting_clash(conn, element, relationship).await?);
+    }
+
+    Ok(clashes)
+}
+
+async fn hosting_clash(
+    conn: &mut SqliteConnection,
+    element: &Element,
+    relationship: &Relationship,
+) -> Result<Option<Clash>> {
+    let (wall_id, opening_id) = (&relationship.source_id, &relationship.target_id);
+    Ok(if element.id == *wall_id {
+        let opening = db::get_element(&mut *conn, &element.project_id, opening_id).await?;
+        opening_clash(element, &opening)
+    } else {
+        let wall = db::get_element(&mut *conn, &element.project_id, wall_id).await?;
+        opening_clash(&wall, element)
+    })
+}
+
+// 同じ種類の要素（壁どうし・部屋どうし）の重なり
+fn overlap_clash(a: &Element, b: &Element) -> Option<Clash> {
+    if a.element_type != b.element_type {
+        return None;
+    }
+    let kind = match a.element_type.as_str() {
+        ELEMENT_TYPE_WALL => ClashKind::WallOverlap,
+        ELEMENT_TYPE_ROOM => ClashKind::RoomOverlap,
+        _ => return None,
+    };
+
+    let (pieces_a, pieces_b) = (convex_pieces(a), convex_pieces(b));
+    let mut overlap: Vec<Vec<Point>> = pieces_a
+        .iter()
+        .flat_map(|p| pieces_b.iter().map(move |q| clip_convex(p, q)))
+        .filter(|piece| signed_area(piece).abs() > OVERLAP_TOLERANCE)
+        .collect();
+
+    // 壁の端部どうしの接合（L字・T字）による重なりは干渉としない
+    if kind == ClashKind::WallOverlap {
+        let joints = wall_joints(a, b);
+        overlap.retain(|piece| !joints.iter().any(|j| piece.iter().all(|p| within(*p, j.0, j.1))));
+    }
+    if overlap.is_empty() {
+        return None;
+    }
+
+    let overlap_area = overlap.iter().map(|piece| signed_area(piece).abs()).sum();
+    Some(Clash {
+        kind,
+        element_ids: [a.id.clone(), b.id.clone()],
+        overlap,
+        overlap_area,
+    })
+}
+
+// 開口部が保持している壁の上にない
+fn opening_clash(wall: &Element, opening: &Element) -> Option<Clash> {
+    if opening.element_type != ELEMENT_TYPE_OPENING || hosting::is_on_wall(wall, opening) {
+        return None;
+    }
+    Some(Clash {
+        kind: ClashKind::OpeningOutsideHost,
+        element_ids: [opening.id.clone(), wall.id.clone()],
+        overlap: Vec::new(),
+        overlap_area: 0.0,
+    })
+}
+
+// 要素の形状を凸多角形に分ける（壁は芯線の区間ごとの矩形、部屋は三角形分割）
+fn convex_pieces(element: &Element) -> Vec<Vec<Point>> {
+    if element.element_type == ELEMENT_TYPE_WALL {
+        let Some((axis, thickness)) = element.geometry.wall_axis() else {
+            return Vec::new();
+        };
+        return axis
+            .windows(2)
+            .filter_map(|segment| segment_rect(segment[0], segment[1], thickness))
+            .collect();
+    }
+
+    let Some(mut outline) = element.geometry.outline() else {
+        return Vec::new();
+    };
+    if signed_area(&outline) < 0.0 {
+        outline.reverse();
+    }
+    if is_convex(&outline) {
+        vec![outline]
+    } else {
+        triangulate(outline)
+    }
+}
+
+// 壁芯の区間を壁厚分ふくらませた矩形（反時計回り）
+fn segment_rect(a: Point, b: Point, thickness: f64) -> Option<Vec<Point>> {
+    let length = (b.x - a.x).hypot(b.y - a.y);
+    if length <= 0.0 {
+        return None;
+    }
+    let (nx, ny) = (-(b.y - a.y) / length * thickness / 2.0, (b.x - a.x) / length * thickness / 2.0);
+    Some(vec![
+        Point { x: a.x - nx, y: a.y - ny },
+        Point { x: b.x - nx, y: b.y - ny },
+        Point { x: b.x + nx, y: b.y + ny },
+        Point { x: a.x + nx, y: a.y + ny },
+    ])
+}
+
+// 一方の壁の端点が他方の壁の中にある位置と、接合部とみなす半径
+fn wall_joints(a: &Element, b: &Element) -> Vec<(Point, f64)> {
+    let (Some((axis_a, thickness_a)), Some((axis_b, thickness_b))) =
+        (a.geometry.wall_axis(), b.geometry.wall_axis())
+    else {
+        return Vec::new();
+    };
+    let radius = (thickness_a + thickness_b) / 2.0;
+
+    let ends = |axis: &[Point]| [axis.first().copied(), axis.last().copied()];
+    let mut joints = Vec::new();
+    for (axis, other, other_thickness) in [(&axis_a, &axis_b, thickness_b), (&axis_b, &axis_a, thickness_a)] {
+        for end in ends(axis).into_iter().flatten() {
+            let (distance, _) = project_onto_polyline(other, end);
+            if distance <= other_thickness / 2.0 + OVERLAP_TOLERANCE {
+                joints.push((end, radius));
+            }
+        }
+    }
+    joints
+}
+
+fn within(p: Point, center: Point, radius: f64) -> bool {
+    (p.x - center.x).hypot(p.y - center.y) <= radius + OVERLAP_TOLERANCE
+}
+
+// 凸多角形どうしの共通部分（Sutherland–Hodgman 法、どちらも反時計回り）
+fn clip_convex(subject: &[Point], clip: &[Point]) -> Vec<Point> {
+    let mut output = subject.to_vec();
+    for (i, &c1) in clip.iter().enumerate() {
+        let c2 = clip[(i + 1) % clip.len()];
+        let side = |p: Point| (c2.x - c1.x) * (p.y - c1.y) - (c2.y - c1.y) * (p.x - c1.x);
+        let input = std::mem::take(&mut output);
+        for (j, &current) in input.iter().enumerate() {
+            let previous = input[(j + input.len() - 1) % input.len()];
+            let (sc, sp) = (side(current), side(previous));
+            if sc >= 0.0 {
+                if sp < 0.0 {
+                    output.push(intersection(previous, current, sp, sc));
+                }
+                output.push(current);
+            } else if sp >= 0.0 {
+                output.push(intersection(previous, current, sp, sc));
+            }
+        }
+        if output.is_empty() {
+            break;
+        }
+    }
+    output
+}
+
+fn intersection(a: Point, b: Point, side_a: f64, side_b: f64) -> Point {
+    let t = side_a / (side_a - side_b);
+    Point {
+        x: a.x + (b.x - a.x) * t,
+        y: a.y + (b.y - a.y) * t,
+    }
+}
+
+fn signed_area(points: &[Point]) -> f64 {
+    points
+        .iter()
+        .zip(points.iter().cycle().skip(1))
+        .map(|(a, b)| a.x * b.y - b.x * a.y)
+        .sum::<f64>()
+        / 2.0
+}
+
+fn cross(o: Point, a: Point, b: Point) -> f64 {
+    (a.x - o.x) * (b.y - o.y) - (a.y - o.y) * (b.x - o.x)
+}
+
+// 反時計回りの多角形が凸か
+fn is_convex(points: &[Point]) -> bool {
+    let n = points.len();
+    (0..n).all(|i| cross(points[i], points[(i + 1) % n], points[(i + 2) % n]) >= 0.0)
+}
+
+// 一直線上に並ぶ頂点と重複した頂点を取り除く（耳の判定で面積 0 の角を残さないため）
+fn drop_collinear(points: Vec<Point>) -> Vec<Point> {
+    let flat = |a: Point, b: Point, c: Point| cross(a, b, c).abs() <= OVERLAP_TOLERANCE;
+    let mut kept: Vec<Point> = Vec::with_capacity(points.len());
+    for p in points {
+        while kept.len() >= 2 && flat(kept[kept.len() - 2], kept[kept.len() - 1], p) {
+            kept.pop();
+        }
+        kept.push(p);
+    }
+    // 始点をまたぐ角
+    while kept.len() > 3 {
+        let n = kept.len();
+        if flat(kept[n - 2], kept[n - 1], kept[0]) {
+            kept.pop();
+        } else if flat(kept[n - 1], kept[0], kept[1]) {
+            kept.remove(0);
+        } else {
+            break;
+        }
+    }
+    kept
+}
+
+// 反時計回りの単純多角形の三角形分割（耳刈り取り法）
+fn triangulate(points: Vec<Point>) -> Vec<Vec<Point>> {
+    let mut points = drop_collinear(points);
+    let mut triangles = Vec::new();
+    while points.len() > 3 {
+        let n = points.len();
+        let ear = (0..n).find(|&i| {
+            let (a, b, c) = (points[(i + n - 1) % n], points[i], points[(i + 1) % n]);
+            cross(a, b, c) > 0.0
+                && points.iter().all(|&p| {
+                    p == a || p == b || p == c
+                        || cross(a, b, p) < 0.0
+                        || cross(b, c, p) < 0.0
+                        || cross(c, a, p) < 0.0
+                })
+        });
+        // 自己交差などで耳が見つからない場合は残りをそのまま扱う
+        let Some(i) = ear else {
+            break;
+        };
+        triangles.push(vec![points[(i + n - 1) % n], points[i], points[(i + 1) % n]]);
+        points.remove(i);
+    }
+    triangles.push(points);
+    triangles
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::element::Geometry;
+    use serde_json::json;
+    use time::OffsetDateTime;
+
+    fn points(coords: &[(f64, f64)]) -> Vec<Point> {
+        coords.iter().map(|&(x, y)| Point { x, y }).collect()
+    }
+
+    fn element(id: &str, element_type: &str, geometry: Geometry) -> Element {
+        Element {
+            id: id.to_string(),
+            project_id: "p1".to_string(),
+            element_type: element_type.to_string(),
+            geometry,
+            properties: json!({}),
+            metadata: json!({}),
+            version: 1,
+            created_at: OffsetDateTime::UNIX_EPOCH,
+            updated_at: OffsetDateTime::UNIX_EPOCH,
+        }
+    }
+
+    fn wall(id: &str, coords: &[(f64, f64)]) -> Element {
+        let geometry = Geometry::Polyline { points: points(coords), thickness: 0.2 };
+        element(id, ELEMENT_TYPE_WALL, geometry)
+    }
+
+    fn room(id: &str, coords: &[(f64, f64)]) -> Element {
+        element(id, ELEMENT_TYPE_ROOM, Geometry::Polygon { points: points(coords) })
+    }
+
+    fn area(pieces: &[Vec<Point>]) -> f64 {
+        pieces.iter().map(|piece| signed_area(piece).abs()).sum()
+    }
+
+    // 一辺に余分な頂点を持つL字形（面積 12）
+    const L_SHAPE: [(f64, f64); 7] =
+        [(0.0, 0.0), (2.0, 0.0), (4.0, 0.0), (4.0, 2.0), (2.0, 2.0), (2.0, 4.0), (0.0, 4.0)];
+
+    #[test]
+    fn clips_convex_polygons() {
+        let a = points(&[(0.0, 0.0), (2.0, 0.0), (2.0, 2.0), (0.0, 2.0)]);
+        let b = points(&[(1.0, 1.0), (3.0, 1.0), (3.0, 3.0), (1.0, 3.0)]);
+        assert!((signed_area(&clip_convex(&a, &b)) - 1.0).abs() < 1e-9);
+
+        let inner = points(&[(0.5, 0.5), (1.0, 0.5), (1.0, 1.0)]);
+        assert!((signed_area(&clip_convex(&inner, &a)) - 0.125).abs() < 1e-9);
+
+        let apart = points(&[(5.0, 5.0), (6.0, 5.0), (6.0, 6.0), (5.0, 6.0)]);
+        assert!(signed_area(&clip_convex(&a, &apart)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn triangulates_concave_polygons_with_collinear_vertices() {
+        let triangles = triangulate(points(&L_SHAPE));
+        assert_eq!(triangles.len(), 4);
+        assert!(triangles.iter().all(|t| t.len() == 3 && signed_area(t) > 0.0));
+        assert!((area(&triangles) - 12.0).abs() < 1e-9);
+
+        // 始点が一直線上にある場合
+        let rotated = points(&[(2.0, 0.0), (4.0, 0.0), (4.0, 2.0), (2.0, 2.0), (2.0, 4.0), (0.0, 4.0), (0.0, 0.0)]);
+        let triangles = triangulate(rotated);
+        assert_eq!(triangles.len(), 4);
+        assert!((area(&triangles) - 12.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn finds_wall_joints() {
+        let base = wall("a", &[(0.0, 0.0), (10.0, 0.0)]);
+        let corner = wall("b", &[(10.0, 0.0), (10.0, 5.0)]);
+        assert_eq!(wall_joints(&base, &corner).len(), 2);
+
+        let tee = wall("c", &[(5.0, 0.0), (5.0, 5.0)]);
+        let joints = wall_joints(&base, &tee);
+        assert_eq!(joints.len(), 1);
+        assert_eq!(joints[0].0, Point { x: 5.0, y: 0.0 });
+        assert!((joints[0].1 - 0.2).abs() < 1e-9);
+
+        let crossing = wall("d", &[(5.0, -5.0), (5.0, 5.0)]);
+        assert!(wall_joints(&base, &crossing).is_empty());
+    }
+
+    #[test]
+    fn ignores_l_and_t_wall_joints() {
+        let base = wall("a", &[(0.0, 0.0), (10.0, 0.0)]);
+        assert!(overlap_clash(&base, &wall("b", &[(10.0, 0.0), (10.0, 5.0)])).is_none());
+        assert!(overlap_clash(&base, &wall("c", &[(5.0, 0.0), (5.0, 5.0)])).is_none());
+        assert!(overlap_clash(&base, &wall("d", &[(0.0, 1.0), (10.0, 1.0)])).is_none());
+    }
+
+    #[test]
+    fn detects_crossing_walls() {
+        let base = wall("a", &[(0.0, 0.0), (10.0, 0.0)]);
+        let clash = overlap_clash(&base, &wall("b", &[(5.0, -5.0), (5.0, 5.0)])).unwrap();
+        assert_eq!(clash.kind, ClashKind::WallOverlap);
+        assert_eq!(clash.element_ids, ["a".to_string(), "b".to_string()]);
+        assert!((clash.overlap_area - 0.04).abs() < 1e-9);
+
+        // 並行して重なる壁
+        let clash = overlap_clash(&base, &wall("c", &[(2.0, 0.1), (8.0, 0.1)])).unwrap();
+        assert!((clash.overlap_area - 0.6).abs() < 1e-9);
+    }
+
+    #[test]
+    fn detects_overlapping_concave_rooms() {
+        let l_room = room("a", &L_SHAPE);
+
+        // L字の欠けた部分にある部屋は重ならない
+        let notch = room("b", &[(3.0, 3.0), (4.0, 3.0), (4.0, 4.0), (3.0, 4.0)]);
+        assert!(overlap_clash(&l_room, &notch).is_none());
+
+        let square = room("c", &[(1.0, 1.0), (3.0, 1.0), (3.0, 3.0), (1.0, 3.0)]);
+        let clash = overlap_clash(&l_room, &square).unwrap();
+        assert_eq!(clash.kind, ClashKind::RoomOverlap);
+        assert!((clash.overlap_area - 3.0).abs() < 1e-9);
+
+        // 凹形どうし（時計回りの輪郭も扱う）
+        let mirrored = room(
+            "d",
+            &[(1.0, 1.0), (1.0, 5.0), (5.0, 5.0), (5.0, 3.0), (3.0, 3.0), (3.0, 1.0)],
+        );
+        let clash = overlap_clash(&l_room, &mirrored).unwrap();
+        assert!((clash.overlap_area - 4.0).abs() < 1e-9);
+
+        // 種類の異なる要素は比べない
+        assert!(overlap_clash(&l_room, &wall("e", &[(0.0, 1.0), (4.0, 1.0)])).is_none());
+    }
+}
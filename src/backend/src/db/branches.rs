@@ -0,0 +1,119 @@
+use sqlx::{sqlite::SqliteRow, Executor, Row, Sqlite, SqliteConnection, SqlitePool};
+use serde_json::Value as JsonValue;
+
+use crate::{
+    error::{AppError, Result},
+    models::branch::{Branch, BranchInfo, BRANCH_STATUS_MERGED},
+};
+
+use super::projects::get_project;
+
+fn branch_from_row(row: &SqliteRow) -> Result<Branch> {
+    Ok(Branch {
+        project_id: row.get("project_id"),
+        parent_project_id: row.get("parent_project_id"),
+        base_elements: serde_json::from_value(row.get::<JsonValue, _>("base_elements"))?,
+        base_relationships: serde_json::from_value(row.get::<JsonValue, _>("base_relationships"))?,
+        element_map: serde_json::from_value(row.get::<JsonValue, _>("element_map"))?,
+        relationship_map: serde_json::from_value(row.get::<JsonValue, _>("relationship_map"))?,
+        status: row.get("status"),
+        created_by: row.get("created_by"),
+        created_at: row.get("created_at"),
+        merged_at: row.get("merged_at"),
+    })
+}
+
+pub async fn list_branches(pool: &SqlitePool, parent_project_id: &str) -> Result<Vec<BranchInfo>> {
+    let rows = sqlx::query(
+        r#"
+        SELECT project_id, parent_project_id, status, created_by, created_at, merged_at
+        FROM project_branches
+        WHERE parent_project_id = ?
+        ORDER BY created_at ASC
+        "#
+    )
+    .bind(parent_project_id)
+    .fetch_all(pool)
+    .await
+    .map_err(AppError::Database)?;
+
+    let mut branches = Vec::with_capacity(rows.len());
+    for row in &rows {
+        let project_id: String = row.get("project_id");
+        branches.push(BranchInfo {
+            project: get_project(pool, &project_id).await?,
+            parent_project_id: row.get("parent_project_id"),
+            status: row.get("status"),
+            created_by: row.get("created_by"),
+            created_at: row.get("created_at"),
+            merged_at: row.get("merged_at"),
+        });
+    }
+
+    Ok(branches)
+}
+
+pub async fn get_branch<'e, E>(executor: E, project_id: &str) -> Result<Branch>
+where
+    E: Executor<'e, Database = Sqlite>,
+{
+    let row = sqlx::query(
+        r#"
+        SELECT project_id, parent_project_id, base_elements, base_relationships,
+               element_map, relationship_map, status, created_by, created_at, merged_at
+        FROM project_branches
+        WHERE project_id = ?
+        "#
+    )
+    .bind(project_id)
+    .fetch_optional(executor)
+    .await
+    .map_err(AppError::Database)?
+    .ok_or_else(|| AppError::NotFound(format!("Project is not a branch: {}", project_id)))?;
+
+    branch_from_row(&row)
+}
+
+pub async fn create_branch(conn: &mut SqliteConnection, branch: &Branch) -> Result<()> {
+    sqlx::query(
+        r#"
+        INSERT INTO project_branches (
+            project_id, parent_project_id, base_elements, base_relationships,
+            element_map, relationship_map, status, created_by, created_at, merged_at
+        )
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+        "#
+    )
+    .bind(&branch.project_id)
+    .bind(&branch.parent_project_id)
+    .bind(serde_json::to_value(&branch.base_elements)?)
+    .bind(serde_json::to_value(&branch.base_relationships)?)
+    .bind(serde_json::to_value(&branch.element_map)?)
+    .bind(serde_json::to_value(&branch.relationship_map)?)
+    .bind(&branch.status)
+    .bind(&branch.created_by)
+    .bind(branch.created_at)
+    .bind(branch.merged_at)
+    .execute(conn)
+    .await
+    .map_err(AppError::Database)?;
+
+    Ok(())
+}
+
+pub async fn mark_branch_merged(conn: &mut SqliteConnection, project_id: &str) -> Result<()> {
+    sqlx::query(
+        r#"
+        UPDATE project_branches
+        SET status = ?, merged_at = CURRENT_TIMESTAMP
+        WHERE project_id = ?
+        "#
+    )
+    .bind(BRANCH_STATUS_MERGED)
+    .bind(project_id)
+    .execute(conn)
+    .await
+    .map_err(AppError::Database)?;
+
+    Ok(())
+}
@@ -0,0 +1,124 @@
+use sqlx::{sqlite::SqliteRow, Executor, Row, Sqlite, SqliteConnection};
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+use crate::{
+    error::{AppError, Result},
+    models::{change_set::ChangeSet, event::Event},
+};
+
+fn change_set_from_row(row: &SqliteRow) -> ChangeSet {
+    ChangeSet {
+        id: row.get("id"),
+        project_id: row.get("project_id"),
+        description: row.get("description"),
+        actor: row.get("actor"),
+        created_at: row.get("created_at"),
+    }
+}
+
+// 同一トランザクションで記録したイベントと、そこから導出した履歴を一つの変更グループにまとめる
+pub async fn create_change_set(
+    conn: &mut SqliteConnection,
+    project_id: &str,
+    description: &str,
+    actor: &str,
+    events: &mut [Event],
+) -> Result<ChangeSet> {
+    let change_set = ChangeSet {
+        id: Uuid::new_v4().to_string(),
+        project_id: project_id.to_string(),
+        description: description.to_string(),
+        actor: actor.to_string(),
+        created_at: OffsetDateTime::now_utc(),
+    };
+
+    sqlx::query(
+        r#"
+        INSERT INTO change_sets (id, project_id, description, actor, created_at)
+        VALUES (?, ?, ?, ?, ?)
+        "#
+    )
+    .bind(&change_set.id)
+    .bind(&change_set.project_id)
+    .bind(&change_set.description)
+    .bind(&change_set.actor)
+    .bind(change_set.created_at)
+    .execute(&mut *conn)
+    .await
+    .map_err(AppError::Database)?;
+
+    let ids = serde_json::to_value(events.iter().map(|e| &e.id).collect::<Vec<_>>())?;
+
+    sqlx::query(
+        r#"
+        UPDATE events
+        SET change_set_id = ?
+        WHERE id IN (SELECT value FROM json_each(?))
+        "#
+    )
+    .bind(&change_set.id)
+    .bind(&ids)
+    .execute(&mut *conn)
+    .await
+    .map_err(AppError::Database)?;
+
+    sqlx::query(
+        r#"
+        UPDATE change_history
+        SET change_set_id = ?
+        WHERE id IN (SELECT value FROM json_each(?))
+        "#
+    )
+    .bind(&change_set.id)
+    .bind(&ids)
+    .execute(&mut *conn)
+    .await
+    .map_err(AppError::Database)?;
+
+    for event in events.iter_mut() {
+        event.change_set_id = Some(change_set.id.clone());
+    }
+
+    Ok(change_set)
+}
+
+pub async fn get_change_set<'e, E>(executor: E, change_set_id: &str) -> Result<ChangeSet>
+where
+    E: Executor<'e, Database = Sqlite>,
+{
+    let row = sqlx::query(
+        r#"
+        SELECT id, project_id, description, actor, created_at
+        FROM change_sets
+        WHERE id = ?
+        "#
+    )
+    .bind(change_set_id)
+    .fetch_optional(executor)
+    .await
+    .map_err(AppError::Database)?
+    .ok_or_else(|| AppError::NotFound(format!("Change set not found: {}", change_set_id)))?;
+
+    Ok(change_set_from_row(&row))
+}
+
+// 指定したグループの情報をまとめて取得する
+pub async fn list_change_sets<'e, E>(executor: E, change_set_ids: &[String]) -> Result<Vec<ChangeSet>>
+where
+    E: Executor<'e, Database = Sqlite>,
+{
+    let rows = sqlx::query(
+        r#"
+        SELECT id, project_id, description, actor, created_at
+        FROM change_sets
+        WHERE id IN (SELECT value FROM json_each(?))
+        "#
+    )
+    .bind(serde_json::to_value(change_set_ids)?)
+    .fetch_all(executor)
+    .await
+    .map_err(AppError::Database)?;
+
+    Ok(rows.iter().map(change_set_from_row).collect())
+}
@@ -1,12 +1,51 @@
-use sqlx::{Row, SqlitePool};
+use sqlx::{sqlite::SqliteRow, types::Json, Executor, QueryBuilder, Row, Sqlite, SqliteConnection};
 use serde_json::Value as JsonValue;
+use time::{OffsetDateTime, UtcOffset};
 
 use crate::{
     error::{AppError, Result},
-    models::element::{CreateElement, Element, UpdateElement},
+    models::{
+        element::{CreateElement, Element, UpdateElement},
+        element_query::{ElementCursor, ElementQuery, PropertyPredicate},
+    },
 };
 
-pub async fn list_elements(pool: &SqlitePool, project_id: &str) -> Result<Vec<Element>> {
+use super::{layers::get_layer, spatial::index_element_bounds};
+
+// 文字列の並び順が時刻の順になるよう、UTC・小数部9桁の固定幅で記録する
+// （sqlx の RFC 3339 形式は小数部の末尾の0を省くため、同じ秒の中で順序が崩れる）
+fn stored_timestamp(at: OffsetDateTime) -> String {
+    let at = at.to_offset(UtcOffset::UTC);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:09}Z",
+        at.year(),
+        u8::from(at.month()),
+        at.day(),
+        at.hour(),
+        at.minute(),
+        at.second(),
+        at.nanosecond()
+    )
+}
+
+pub(super) fn element_from_row(row: &SqliteRow) -> Result<Element> {
+    Ok(Element {
+        id: row.get("id"),
+        project_id: row.get("project_id"),
+        element_type: row.get("element_type"),
+        geometry: serde_json::from_value(row.get::<JsonValue, _>("geometry"))?,
+        properties: row.get::<JsonValue, _>("properties"),
+        metadata: row.get::<JsonValue, _>("metadata"),
+        version: row.get::<i64, _>("version") as i32,
+        created_at: row.get("created_at"),
+        updated_at: row.get("updated_at"),
+    })
+}
+
+pub async fn list_elements<'e, E>(executor: E, project_id: &str) -> Result<Vec<Element>>
+where
+    E: Executor<'e, Database = Sqlite>,
+{
     let rows = sqlx::query(
         r#"
         SELECT id, project_id, element_type, geometry, properties, metadata, version, created_at, updated_at
@@ -16,29 +55,136 @@ pub async fn list_elements(pool: &SqlitePool, project_id: &str) -> Result<Vec<El
         "#
     )
     .bind(project_id)
-    .fetch_all(pool)
+    .fetch_all(executor)
     .await
     .map_err(AppError::Database)?;
 
     let elements = rows
         .iter()
-        .map(|row| Element {
-            id: row.get("id"),
-            project_id: row.get("project_id"),
-            element_type: row.get("element_type"),
-            geometry: row.get::<JsonValue, _>("geometry"),
-            properties: row.get::<JsonValue, _>("properties"),
-            metadata: row.get::<JsonValue, _>("metadata"),
-            version: row.get::<i64, _>("version") as i32,
-            created_at: row.get("created_at"),
-            updated_at: row.get("updated_at"),
-        })
-        .collect();
+        .map(element_from_row)
+        .collect::<Result<Vec<_>>>()?;
 
     Ok(elements)
 }
 
-pub async fn get_element(pool: &SqlitePool, project_id: &str, element_id: &str) -> Result<Element> {
+// 条件に合う要素を指定した順に返す
+// 件数を指定した場合は、続きがあれば次のページのカーソルも返す
+pub async fn query_elements<'e, E>(
+    executor: E,
+    project_id: &str,
+    query: &ElementQuery,
+) -> Result<(Vec<Element>, Option<ElementCursor>)>
+where
+    E: Executor<'e, Database = Sqlite>,
+{
+    // カーソルに使う値（時刻は記録された文字列のまま扱う）
+    let sort_column = query.sort.column();
+    let sort_key = if query.sort.is_numeric() {
+        sort_column.to_string()
+    } else {
+        format!("CAST({} AS TEXT)", sort_column)
+    };
+    let mut builder = QueryBuilder::<Sqlite>::new(format!(
+        "SELECT id, project_id, element_type, geometry, properties, metadata, version, created_at, updated_at, {} AS sort_key FROM elements WHERE project_id = ",
+        sort_key
+    ));
+    builder.push_bind(project_id);
+
+    if !query.element_types.is_empty() {
+        builder.push(" AND element_type IN (");
+        let mut types = builder.separated(", ");
+        for element_type in &query.element_types {
+            types.push_bind(element_type);
+        }
+        builder.push(")");
+    }
+    if let Some(layer) = &query.layer {
+        builder.push(" AND json_extract(properties, '$.common.layer') = ");
+        builder.push_bind(layer);
+    }
+    if let Some(visible) = query.visible {
+        builder.push(" AND json_extract(properties, '$.common.visible') = ");
+        builder.push_bind(visible);
+    }
+    for predicate in &query.properties {
+        push_property_predicate(&mut builder, predicate);
+    }
+
+    // 前のページの最後の要素より後ろ（並び順の値が同じ場合はIDで比較する）
+    let (direction, compare) = if query.descending { ("DESC", "<") } else { ("ASC", ">") };
+    if let Some(cursor) = &query.cursor {
+        builder.push(format!(" AND ({}, id) {} (", sort_column, compare));
+        match cursor.key.as_i64() {
+            Some(key) => builder.push_bind(key),
+            None => builder.push_bind(cursor.key.as_str().unwrap_or_default().to_string()),
+        };
+        builder.push(", ");
+        builder.push_bind(&cursor.id);
+        builder.push(")");
+    }
+
+    builder.push(format!(" ORDER BY {0} {1}, id {1}", sort_column, direction));
+    // 続きの有無を確かめるため1件多く取得する
+    if let Some(limit) = query.limit {
+        builder.push(" LIMIT ");
+        builder.push_bind(limit + 1);
+    }
+
+    let mut rows = builder
+        .build()
+        .fetch_all(executor)
+        .await
+        .map_err(AppError::Database)?;
+
+    let next_cursor = match query.limit {
+        Some(limit) if rows.len() as i64 > limit => {
+            rows.truncate(limit as usize);
+            rows.last().map(|row| ElementCursor {
+                key: if query.sort.is_numeric() {
+                    JsonValue::from(row.get::<i64, _>("sort_key"))
+                } else {
+                    JsonValue::from(row.get::<String, _>("sort_key"))
+                },
+                id: row.get("id"),
+            })
+        }
+        _ => None,
+    };
+
+    let elements = rows
+        .iter()
+        .map(element_from_row)
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok((elements, next_cursor))
+}
+
+// 保存されている値の JSON の型に合わせて比較する（文字列の "101" は数値の 101 と一致しない）
+fn push_property_predicate<'q>(builder: &mut QueryBuilder<'q, Sqlite>, predicate: &'q PropertyPredicate) {
+    let op = predicate.op.sql();
+    builder.push(" AND CASE json_type(properties, ");
+    builder.push_bind(&predicate.path);
+    builder.push(") WHEN 'text' THEN json_extract(properties, ");
+    builder.push_bind(&predicate.path);
+    builder.push(format!(") {} ", op));
+    builder.push_bind(&predicate.value);
+    for numeric in ["integer", "real"] {
+        builder.push(format!(" WHEN '{}' THEN json_extract(properties, ", numeric));
+        builder.push_bind(&predicate.path);
+        builder.push(format!(") {} ", op));
+        builder.push_bind(predicate.number());
+    }
+    for (boolean, stored) in [("true", 1), ("false", 0)] {
+        builder.push(format!(" WHEN '{}' THEN {} {} ", boolean, stored, op));
+        builder.push_bind(predicate.boolean());
+    }
+    builder.push(" END");
+}
+
+pub async fn get_element<'e, E>(executor: E, project_id: &str, element_id: &str) -> Result<Element>
+where
+    E: Executor<'e, Database = Sqlite>,
+{
     let row = sqlx::query(
         r#"
         SELECT id, project_id, element_type, geometry, properties, metadata, version, created_at, updated_at
@@ -48,33 +194,74 @@ pub async fn get_element(pool: &SqlitePool, project_id: &str, element_id: &str)
     )
     .bind(project_id)
     .bind(element_id)
-    .fetch_optional(pool)
+    .fetch_optional(executor)
     .await
     .map_err(AppError::Database)?
     .ok_or_else(|| AppError::NotFound(format!("Element not found: {}", element_id)))?;
 
-    Ok(Element {
-        id: row.get("id"),
-        project_id: row.get("project_id"),
-        element_type: row.get("element_type"),
-        geometry: row.get::<JsonValue, _>("geometry"),
-        properties: row.get::<JsonValue, _>("properties"),
-        metadata: row.get::<JsonValue, _>("metadata"),
-        version: row.get::<i64, _>("version") as i32,
-        created_at: row.get("created_at"),
-        updated_at: row.get("updated_at"),
-    })
+    element_from_row(&row)
+}
+
+// 指定したIDのうち、プロジェクトに存在しない要素のIDを返す
+pub async fn missing_element_ids<'e, E>(
+    executor: E,
+    project_id: &str,
+    element_ids: &[String],
+) -> Result<Vec<String>>
+where
+    E: Executor<'e, Database = Sqlite>,
+{
+    if element_ids.is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut builder = QueryBuilder::<Sqlite>::new("SELECT id FROM elements WHERE project_id = ");
+    builder.push_bind(project_id);
+    builder.push(" AND id IN (");
+    let mut ids = builder.separated(", ");
+    for id in element_ids {
+        ids.push_bind(id);
+    }
+    builder.push(")");
+
+    let found: Vec<String> = builder
+        .build_query_scalar()
+        .fetch_all(executor)
+        .await
+        .map_err(AppError::Database)?;
+    Ok(element_ids
+        .iter()
+        .filter(|id| !found.contains(id))
+        .cloned()
+        .collect())
 }
 
-pub async fn create_element(pool: &SqlitePool, project_id: &str, data: CreateElement) -> Result<Element> {
-    let element = Element::new(
+pub async fn create_element(
+    conn: &mut SqliteConnection,
+    project_id: &str,
+    data: CreateElement,
+    user_id: &str,
+) -> Result<Element> {
+    data.geometry.validate().map_err(AppError::InvalidRequest)?;
+
+    let mut element = Element::new(
         project_id.to_string(),
         data.element_type,
         data.geometry,
         data.properties,
         data.metadata,
     );
+    element
+        .apply_room_measurements(true)
+        .map_err(AppError::InvalidRequest)?;
+    ensure_layer_unlocked(conn, project_id, element.layer(), user_id).await?;
+
+    insert_element(conn, &element).await?;
 
+    Ok(element)
+}
+
+// 要素をそのままの内容・バージョンで挿入する（プロジェクトの複製など）
+pub async fn insert_element(conn: &mut SqliteConnection, element: &Element) -> Result<()> {
     sqlx::query(
         r#"
         INSERT INTO elements (
@@ -87,33 +274,44 @@ pub async fn create_element(pool: &SqlitePool, project_id: &str, data: CreateEle
     .bind(&element.id)
     .bind(&element.project_id)
     .bind(&element.element_type)
-    .bind(&element.geometry)
+    .bind(Json(&element.geometry))
     .bind(&element.properties)
     .bind(&element.metadata)
     .bind(element.version as i64)
-    .bind(element.created_at)
-    .bind(element.updated_at)
-    .execute(pool)
+    .bind(stored_timestamp(element.created_at))
+    .bind(stored_timestamp(element.updated_at))
+    .execute(&mut *conn)
     .await
     .map_err(AppError::Database)?;
 
-    Ok(element)
+    index_element_bounds(conn, element).await?;
+
+    Ok(())
 }
 
 pub async fn update_element(
-    pool: &SqlitePool,
+    conn: &mut SqliteConnection,
     project_id: &str,
     element_id: &str,
     data: UpdateElement,
+    expected_version: Option<i32>,
+    user_id: &str,
 ) -> Result<Element> {
-    let mut element = get_element(pool, project_id, element_id).await?;
+    let before = get_element(&mut *conn, project_id, element_id).await?;
+    let mut element = before.clone();
 
+    // 取得した内容を送り返した場合など、面積を保存済みの値から変えていなければ計算値で上書きする
+    let area_changed = data
+        .properties
+        .as_ref()
+        .and_then(|p| p.floor_plan.as_ref()?.area)
+        .is_some_and(|area| before.properties["floorPlan"]["area"].as_f64() != Some(area));
     if let Some(element_type) = data.element_type {
         element.element_type = element_type;
     }
     if let Some(geometry) = data.geometry {
-        element.geometry = serde_json::to_value(geometry)
-            .map_err(|e| AppError::InvalidRequest(format!("Invalid geometry format: {}", e)))?;
+        geometry.validate().map_err(AppError::InvalidRequest)?;
+        element.geometry = geometry;
     }
     if let Some(properties) = data.properties {
         element.properties = serde_json::to_value(properties)
@@ -124,28 +322,59 @@ pub async fn update_element(
             .map_err(|e| AppError::InvalidRequest(format!("Invalid metadata format: {}", e)))?;
     }
 
-    sqlx::query(
+    element
+        .apply_room_measurements(area_changed)
+        .map_err(AppError::InvalidRequest)?;
+    ensure_element_unlocked(conn, &before, Some(&element), user_id).await?;
+
+    let result = sqlx::query(
         r#"
         UPDATE elements
         SET element_type = ?, geometry = ?, properties = ?, metadata = ?,
-            version = version + 1, updated_at = CURRENT_TIMESTAMP
-        WHERE project_id = ? AND id = ?
+            version = version + 1, updated_at = ?
+        WHERE project_id = ? AND id = ? AND version = COALESCE(?, version)
         "#
     )
     .bind(&element.element_type)
-    .bind(&element.geometry)
+    .bind(Json(&element.geometry))
     .bind(&element.properties)
     .bind(&element.metadata)
+    .bind(stored_timestamp(OffsetDateTime::now_utc()))
     .bind(project_id)
     .bind(element_id)
-    .execute(pool)
+    .bind(expected_version.map(i64::from))
+    .execute(&mut *conn)
     .await
     .map_err(AppError::Database)?;
 
-    get_element(pool, project_id, element_id).await
+    if result.rows_affected() == 0 {
+        let current = get_element(&mut *conn, project_id, element_id).await?;
+        return Err(AppError::VersionConflict {
+            message: format!(
+                "Element {} has been modified (expected version {}, current version {})",
+                element_id,
+                expected_version.unwrap_or_default(),
+                current.version
+            ),
+            current: serde_json::to_value(current)?,
+        });
+    }
+
+    let element = get_element(&mut *conn, project_id, element_id).await?;
+    index_element_bounds(conn, &element).await?;
+
+    Ok(element)
 }
 
-pub async fn delete_element(pool: &SqlitePool, project_id: &str, element_id: &str) -> Result<()> {
+pub async fn delete_element(
+    conn: &mut SqliteConnection,
+    project_id: &str,
+    element_id: &str,
+    user_id: &str,
+) -> Result<()> {
+    let element = get_element(&mut *conn, project_id, element_id).await?;
+    ensure_element_unlocked(conn, &element, None, user_id).await?;
+
     let result = sqlx::query(
         r#"
         DELETE FROM elements
@@ -154,7 +383,7 @@ pub async fn delete_element(pool: &SqlitePool, project_id: &str, element_id: &st
     )
     .bind(project_id)
     .bind(element_id)
-    .execute(pool)
+    .execute(conn)
     .await
     .map_err(AppError::Database)?;
 
@@ -163,4 +392,148 @@ pub async fn delete_element(pool: &SqlitePool, project_id: &str, element_id: &st
     }
 
     Ok(())
-} 
\ No newline at end of file
+}
+
+// 履歴から要素の状態を復元する（削除済みであれば再作成する）
+pub async fn restore_element(
+    conn: &mut SqliteConnection,
+    element: &Element,
+    user_id: &str,
+) -> Result<Element> {
+    match get_element(&mut *conn, &element.project_id, &element.id).await {
+        Ok(current) => ensure_element_unlocked(conn, &current, Some(element), user_id).await?,
+        Err(AppError::NotFound(_)) => {
+            ensure_layer_unlocked(conn, &element.project_id, element.layer(), user_id).await?
+        }
+        Err(e) => return Err(e),
+    }
+
+    sqlx::query(
+        r#"
+        INSERT INTO elements (
+            id, project_id, element_type, geometry, properties, metadata,
+            version, created_at, updated_at
+        )
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+        ON CONFLICT(id) DO UPDATE SET
+            element_type = excluded.element_type,
+            geometry = excluded.geometry,
+            properties = excluded.properties,
+            metadata = excluded.metadata,
+            version = elements.version + 1,
+            updated_at = excluded.updated_at
+        WHERE elements.project_id = excluded.project_id
+        "#
+    )
+    .bind(&element.id)
+    .bind(&element.project_id)
+    .bind(&element.element_type)
+    .bind(Json(&element.geometry))
+    .bind(&element.properties)
+    .bind(&element.metadata)
+    .bind(element.version as i64 + 1)
+    .bind(stored_timestamp(element.created_at))
+    .bind(stored_timestamp(OffsetDateTime::now_utc()))
+    .execute(&mut *conn)
+    .await
+    .map_err(AppError::Database)?;
+
+    let restored = get_element(&mut *conn, &element.project_id, &element.id).await?;
+    index_element_bounds(conn, &restored).await?;
+
+    Ok(restored)
+}
+
+// ロック中の要素とロックされたレイヤー上の要素の変更を拒否する（許可ユーザーは除く）
+// ロック中の要素は、ロックを解除するだけの変更のみ受け付ける
+async fn ensure_element_unlocked(
+    conn: &mut SqliteConnection,
+    before: &Element,
+    after: Option<&Element>,
+    user_id: &str,
+) -> Result<()> {
+    if before.lock_blocks(user_id) {
+        let unlocking = after.is_some_and(|after| {
+            after.properties["common"]["locked"] != JsonValue::Bool(true)
+                && before.properties["common"]["allowedUsers"]
+                    .as_array()
+                    .is_none_or(|users| users.is_empty())
+                && before.same_content_except_lock(after)
+        });
+        if !unlocking {
+            return Err(AppError::Conflict(format!("Element is locked: {}", before.id)));
+        }
+    }
+    ensure_layer_unlocked(conn, &before.project_id, before.layer(), user_id).await?;
+    if let Some(after) = after.filter(|after| after.layer() != before.layer()) {
+        ensure_layer_unlocked(conn, &before.project_id, after.layer(), user_id).await?;
+    }
+    Ok(())
+}
+
+// ロックされたレイヤー上の要素の変更を拒否する（許可ユーザーは除く）
+async fn ensure_layer_unlocked(
+    conn: &mut SqliteConnection,
+    project_id: &str,
+    layer: Option<&str>,
+    user_id: &str,
+) -> Result<()> {
+    let Some(name) = layer else {
+        return Ok(());
+    };
+    if get_layer(&mut *conn, project_id, name).await?.blocks(user_id) {
+        return Err(AppError::Conflict(format!("Layer is locked: {}", name)));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{element::Geometry, element_query::ElementSortField, project::CreateProject};
+    use serde_json::json;
+    use sqlx::sqlite::SqlitePoolOptions;
+    use time::Duration;
+
+    #[tokio::test]
+    async fn sorts_by_updated_at_within_a_second() {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        sqlx::migrate!("./migrations").run(&pool).await.unwrap();
+        let project = crate::db::create_project(
+            &pool,
+            CreateProject { name: "p".to_string(), description: None },
+        )
+        .await
+        .unwrap();
+
+        // sqlx の RFC 3339 形式では "...:00Z" が "...:00.5Z" より、"...:00.1Z" が "...:00.12Z" より後に並ぶ
+        let second = OffsetDateTime::UNIX_EPOCH + Duration::days(19_845);
+        let offsets = [500, 0, 120, 100, 999];
+        let mut conn = pool.acquire().await.unwrap();
+        for (i, millis) in offsets.iter().enumerate() {
+            let at = second + Duration::milliseconds(*millis);
+            let element = Element {
+                id: format!("e{}", i),
+                project_id: project.id.clone(),
+                element_type: "wall".to_string(),
+                geometry: Geometry::Rect { x: 0.0, y: 0.0, width: 1.0, height: 1.0 },
+                properties: json!({}),
+                metadata: json!({}),
+                version: 1,
+                created_at: at,
+                updated_at: at,
+            };
+            insert_element(&mut conn, &element).await.unwrap();
+        }
+
+        let query = ElementQuery { sort: ElementSortField::UpdatedAt, ..Default::default() };
+        let (elements, _) = query_elements(&mut *conn, &project.id, &query).await.unwrap();
+        let sorted: Vec<&str> = elements.iter().map(|e| e.id.as_str()).collect();
+        assert_eq!(sorted, ["e1", "e3", "e2", "e0", "e4"]);
+        assert!(elements.windows(2).all(|w| w[0].updated_at < w[1].updated_at));
+    }
+}
@@ -0,0 +1,116 @@
+use sqlx::{sqlite::SqliteRow, Executor, Row, Sqlite, SqliteConnection};
+use serde_json::Value as JsonValue;
+use time::OffsetDateTime;
+
+use crate::{
+    error::{AppError, Result},
+    models::event::{Event, NewEvent},
+};
+
+fn event_from_row(row: &SqliteRow) -> Event {
+    Event {
+        id: row.get("id"),
+        project_id: row.get("project_id"),
+        seq: row.get("seq"),
+        entity_type: row.get("entity_type"),
+        entity_id: row.get("entity_id"),
+        change_type: row.get("change_type"),
+        actor: row.get("actor"),
+        payload: row.get::<JsonValue, _>("payload"),
+        change_set_id: row.get("change_set_id"),
+        created_at: row.get("created_at"),
+    }
+}
+
+// イベントを追記し、通し番号を割り当てる
+pub async fn append_event(conn: &mut SqliteConnection, event: NewEvent) -> Result<Event> {
+    let created_at = OffsetDateTime::now_utc();
+    let row = sqlx::query(
+        r#"
+        INSERT INTO events (
+            id, project_id, seq, entity_type, entity_id, change_type, actor, payload, created_at
+        )
+        VALUES (
+            ?,
+            ?,
+            (SELECT COALESCE(MAX(seq), 0) + 1 FROM events WHERE project_id = ?),
+            ?, ?, ?, ?, ?, ?
+        )
+        RETURNING seq
+        "#
+    )
+    .bind(&event.id)
+    .bind(&event.project_id)
+    .bind(&event.project_id)
+    .bind(&event.entity_type)
+    .bind(&event.entity_id)
+    .bind(&event.change_type)
+    .bind(&event.actor)
+    .bind(&event.payload)
+    .bind(created_at)
+    .fetch_one(conn)
+    .await
+    .map_err(AppError::Database)?;
+
+    Ok(Event {
+        id: event.id,
+        project_id: event.project_id,
+        seq: row.get("seq"),
+        entity_type: event.entity_type,
+        entity_id: event.entity_id,
+        change_type: event.change_type,
+        actor: event.actor,
+        payload: event.payload,
+        change_set_id: None,
+        created_at,
+    })
+}
+
+// 指定した番号より後のイベントを古い順に返す
+pub async fn list_events_since<'e, E>(
+    executor: E,
+    project_id: &str,
+    since: i64,
+    limit: i64,
+) -> Result<Vec<Event>>
+where
+    E: Executor<'e, Database = Sqlite>,
+{
+    let rows = sqlx::query(
+        r#"
+        SELECT id, project_id, seq, entity_type, entity_id, change_type, actor, payload, change_set_id, created_at
+        FROM events
+        WHERE project_id = ? AND seq > ?
+        ORDER BY seq
+        LIMIT ?
+        "#
+    )
+    .bind(project_id)
+    .bind(since)
+    .bind(limit)
+    .fetch_all(executor)
+    .await
+    .map_err(AppError::Database)?;
+
+    Ok(rows.iter().map(event_from_row).collect())
+}
+
+// 最新の通し番号（記録がなければ 0）
+pub async fn latest_event_seq<'e, E>(executor: E, project_id: &str) -> Result<i64>
+where
+    E: Executor<'e, Database = Sqlite>,
+{
+    let row = sqlx::query(
+        r#"
+        SELECT COALESCE(MAX(seq), 0) AS seq
+        FROM events
+        WHERE project_id = ?
+        "#
+    )
+    .bind(project_id)
+    .fetch_one(executor)
+    .await
+    .map_err(AppError::Database)?;
+
+    Ok(row.get("seq"))
+}
@@ -1,11 +1,42 @@
-use sqlx::{Row, SqlitePool};
+use std::collections::HashMap;
+
+use sqlx::{sqlite::SqliteRow, Executor, Row, Sqlite, SqliteConnection, SqlitePool};
+use serde::de::DeserializeOwned;
 use serde_json::Value as JsonValue;
+use time::OffsetDateTime;
 
 use crate::{
     error::{AppError, Result},
-    models::history::History,
+    models::history::{
+        History, CHANGE_CREATE, CHANGE_DELETE, CHANGE_UPDATE, ENTITY_ELEMENT,
+        ENTITY_RELATIONSHIP, UNDO_STATUS_APPLIED, UNDO_STATUS_DISCARDED, UNDO_STATUS_UNDONE,
+    },
+    models::change_set::{ChangeSet, HistoryGroup},
+    models::event::{Event, NewEvent},
+    models::snapshot::ProjectState,
+};
+
+use super::{
+    change_sets::list_change_sets, elements::list_elements, events::append_event,
+    relationships::list_relationships,
 };
 
+fn history_from_row(row: &SqliteRow) -> History {
+    History {
+        id: row.get("id"),
+        project_id: row.get("project_id"),
+        element_id: row.get("element_id"),
+        entity_type: row.get("entity_type"),
+        change_type: row.get("change_type"),
+        old_value: row.get::<Option<JsonValue>, _>("old_value"),
+        new_value: row.get::<Option<JsonValue>, _>("new_value"),
+        timestamp: row.get("timestamp"),
+        user_id: row.get("user_id"),
+        undo_status: row.get("undo_status"),
+        change_set_id: row.get("change_set_id"),
+    }
+}
+
 pub async fn list_history(
     pool: &SqlitePool,
     project_id: &str,
@@ -17,10 +48,12 @@ pub async fn list_history(
     let rows = if let Some(element_id) = element_id {
         sqlx::query(
             r#"
-            SELECT id, project_id, element_id, change_type, old_value, new_value, timestamp, user_id
-            FROM change_history
-            WHERE project_id = ? AND element_id = ?
-            ORDER BY timestamp DESC
+            SELECT h.id, h.project_id, h.element_id, h.entity_type, h.change_type, h.old_value, h.new_value,
+                   h.timestamp, h.user_id, h.undo_status, h.change_set_id
+            FROM change_history h
+            LEFT JOIN events e ON e.id = h.id
+            WHERE h.project_id = ? AND h.element_id = ?
+            ORDER BY e.seq DESC, h.rowid DESC
             LIMIT ?
             "#
         )
@@ -32,10 +65,12 @@ pub async fn list_history(
     } else {
         sqlx::query(
             r#"
-            SELECT id, project_id, element_id, change_type, old_value, new_value, timestamp, user_id
-            FROM change_history
-            WHERE project_id = ?
-            ORDER BY timestamp DESC
+            SELECT h.id, h.project_id, h.element_id, h.entity_type, h.change_type, h.old_value, h.new_value,
+                   h.timestamp, h.user_id, h.undo_status, h.change_set_id
+            FROM change_history h
+            LEFT JOIN events e ON e.id = h.id
+            WHERE h.project_id = ?
+            ORDER BY e.seq DESC, h.rowid DESC
             LIMIT ?
             "#
         )
@@ -46,76 +81,422 @@ pub async fn list_history(
     }
     .map_err(AppError::Database)?;
 
-    let history = rows
-        .iter()
-        .map(|row| History {
-            id: row.get("id"),
-            project_id: row.get("project_id"),
-            element_id: row.get("element_id"),
-            change_type: row.get("change_type"),
-            old_value: row.get::<Option<JsonValue>, _>("old_value"),
-            new_value: row.get::<Option<JsonValue>, _>("new_value"),
-            timestamp: row.get("timestamp"),
-            user_id: row.get("user_id"),
-        })
-        .collect();
+    let history = rows.iter().map(history_from_row).collect();
 
     Ok(history)
 }
 
-pub async fn add_history_entry(
+// 変更グループ単位の履歴（新しい順）
+// 要素を指定した場合は、その要素の変更を含むグループのすべての変更を返す
+pub async fn list_history_groups(
     pool: &SqlitePool,
     project_id: &str,
+    element_id: Option<&str>,
+    limit: Option<i64>,
+) -> Result<Vec<HistoryGroup>> {
+    let limit = limit.unwrap_or(100);
+
+    let group_ids: Vec<String> = sqlx::query(
+        r#"
+        SELECT COALESCE(h.change_set_id, h.id) AS group_id,
+               MAX(e.seq) AS last_seq, MAX(h.rowid) AS last_rowid
+        FROM change_history h
+        LEFT JOIN events e ON e.id = h.id
+        WHERE h.project_id = ?
+        GROUP BY group_id
+        HAVING ? IS NULL OR SUM(h.element_id = ?) > 0
+        ORDER BY last_seq DESC, last_rowid DESC
+        LIMIT ?
+        "#
+    )
+    .bind(project_id)
+    .bind(element_id)
+    .bind(element_id)
+    .bind(limit)
+    .fetch_all(pool)
+    .await
+    .map_err(AppError::Database)?
+    .iter()
+    .map(|row| row.get("group_id"))
+    .collect();
+
+    let rows = sqlx::query(
+        r#"
+        SELECT h.id, h.project_id, h.element_id, h.entity_type, h.change_type, h.old_value, h.new_value,
+               h.timestamp, h.user_id, h.undo_status, h.change_set_id
+        FROM change_history h
+        LEFT JOIN events e ON e.id = h.id
+        WHERE h.project_id = ? AND COALESCE(h.change_set_id, h.id) IN (SELECT value FROM json_each(?))
+        ORDER BY e.seq ASC, h.rowid ASC
+        "#
+    )
+    .bind(project_id)
+    .bind(serde_json::to_value(&group_ids)?)
+    .fetch_all(pool)
+    .await
+    .map_err(AppError::Database)?;
+
+    let mut entries: HashMap<String, Vec<History>> = HashMap::new();
+    for history in rows.iter().map(history_from_row) {
+        let group_id = history.change_set_id.clone().unwrap_or_else(|| history.id.clone());
+        entries.entry(group_id).or_default().push(history);
+    }
+    let change_sets: HashMap<String, ChangeSet> = list_change_sets(pool, &group_ids)
+        .await?
+        .into_iter()
+        .map(|c| (c.id.clone(), c))
+        .collect();
+
+    let mut groups = Vec::with_capacity(group_ids.len());
+    for group_id in &group_ids {
+        let Some(entries) = entries.remove(group_id) else {
+            continue;
+        };
+        let last = &entries[entries.len() - 1];
+        let (description, actor) = match change_sets.get(group_id) {
+            Some(change_set) => (change_set.description.clone(), change_set.actor.clone()),
+            // グループのない変更は変更内容から説明を作る
+            None => (
+                format!("{} {}", last.change_type, last.entity_type),
+                last.user_id.clone(),
+            ),
+        };
+        groups.push(HistoryGroup {
+            change_set_id: last.change_set_id.clone(),
+            description,
+            actor,
+            timestamp: last.timestamp,
+            undo_status: last.undo_status.clone(),
+            entries,
+        });
+    }
+
+    Ok(groups)
+}
+
+// 要素の変更をイベントとして記録し、変更履歴を導出する
+pub async fn record_element_change(
+    conn: &mut SqliteConnection,
+    project_id: &str,
     element_id: &str,
     change_type: &str,
     old_value: Option<JsonValue>,
     new_value: Option<JsonValue>,
     user_id: &str,
-) -> Result<History> {
-    let history = History::new(
-        project_id.to_string(),
-        element_id.to_string(),
-        change_type.to_string(),
-        old_value,
-        new_value,
-        user_id.to_string(),
-    );
+) -> Result<Event> {
+    let event = NewEvent::new(project_id, ENTITY_ELEMENT, element_id, change_type, user_id)
+        .with_states(old_value, new_value);
+    record_change(conn, event).await
+}
+
+// 関係性の変更をイベントとして記録し、変更履歴を導出する
+pub async fn record_relationship_change(
+    conn: &mut SqliteConnection,
+    project_id: &str,
+    relationship_id: &str,
+    change_type: &str,
+    old_value: Option<JsonValue>,
+    new_value: Option<JsonValue>,
+    user_id: &str,
+) -> Result<Event> {
+    let event = NewEvent::new(project_id, ENTITY_RELATIONSHIP, relationship_id, change_type, user_id)
+        .with_states(old_value, new_value);
+    record_change(conn, event).await
+}
+
+async fn record_change(conn: &mut SqliteConnection, event: NewEvent) -> Result<Event> {
+    let event = append_event(&mut *conn, event).await?;
+    insert_history(conn, history_from_event(&event)).await?;
+    Ok(event)
+}
+
+// 履歴はイベントと同じIDと時刻を持つ
+fn history_from_event(event: &Event) -> History {
+    History {
+        id: event.id.clone(),
+        project_id: event.project_id.clone(),
+        element_id: event.entity_id.clone(),
+        entity_type: event.entity_type.clone(),
+        change_type: event.change_type.clone(),
+        old_value: event.before().cloned(),
+        new_value: event.after().cloned(),
+        timestamp: event.created_at,
+        user_id: event.actor.clone(),
+        undo_status: UNDO_STATUS_APPLIED.to_string(),
+        change_set_id: event.change_set_id.clone(),
+    }
+}
+
+async fn insert_history(conn: &mut SqliteConnection, history: History) -> Result<History> {
+    let change_type = history.change_type.as_str();
+
+    // 新しい変更を記録した時点で、そのユーザーのredo対象は破棄する
+    if [CHANGE_CREATE, CHANGE_UPDATE, CHANGE_DELETE].contains(&change_type) {
+        sqlx::query(
+            r#"
+            UPDATE change_history
+            SET undo_status = ?
+            WHERE project_id = ? AND user_id = ? AND undo_status = ?
+            "#
+        )
+        .bind(UNDO_STATUS_DISCARDED)
+        .bind(&history.project_id)
+        .bind(&history.user_id)
+        .bind(UNDO_STATUS_UNDONE)
+        .execute(&mut *conn)
+        .await
+        .map_err(AppError::Database)?;
+    }
 
     sqlx::query(
         r#"
         INSERT INTO change_history (
-            id, project_id, element_id, change_type,
-            old_value, new_value, timestamp, user_id
+            id, project_id, element_id, entity_type, change_type,
+            old_value, new_value, timestamp, user_id, undo_status, change_set_id
         )
-        VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
         "#
     )
     .bind(&history.id)
     .bind(&history.project_id)
     .bind(&history.element_id)
+    .bind(&history.entity_type)
     .bind(&history.change_type)
     .bind(&history.old_value)
     .bind(&history.new_value)
     .bind(history.timestamp)
     .bind(&history.user_id)
-    .execute(pool)
+    .bind(&history.undo_status)
+    .bind(&history.change_set_id)
+    .execute(conn)
     .await
     .map_err(AppError::Database)?;
 
     Ok(history)
 }
 
-pub async fn clear_history(pool: &SqlitePool, project_id: &str) -> Result<()> {
-    sqlx::query(
+// 取り消し可能な直近の変更（ユーザー単位）
+pub async fn find_undo_candidate(
+    conn: &mut SqliteConnection,
+    project_id: &str,
+    user_id: &str,
+) -> Result<Option<History>> {
+    let row = sqlx::query(
+        r#"
+        SELECT h.id, h.project_id, h.element_id, h.entity_type, h.change_type, h.old_value, h.new_value,
+               h.timestamp, h.user_id, h.undo_status, h.change_set_id
+        FROM change_history h
+        LEFT JOIN events e ON e.id = h.id
+        WHERE h.project_id = ? AND h.user_id = ? AND h.undo_status = ?
+          AND h.change_type IN (?, ?, ?)
+        ORDER BY e.seq DESC, h.rowid DESC
+        LIMIT 1
+        "#
+    )
+    .bind(project_id)
+    .bind(user_id)
+    .bind(UNDO_STATUS_APPLIED)
+    .bind(CHANGE_CREATE)
+    .bind(CHANGE_UPDATE)
+    .bind(CHANGE_DELETE)
+    .fetch_optional(conn)
+    .await
+    .map_err(AppError::Database)?;
+
+    Ok(row.as_ref().map(history_from_row))
+}
+
+// 再実行可能な変更（最後に取り消したもの＝取り消し済みの中で最も古いもの）
+pub async fn find_redo_candidate(
+    conn: &mut SqliteConnection,
+    project_id: &str,
+    user_id: &str,
+) -> Result<Option<History>> {
+    let row = sqlx::query(
         r#"
-        DELETE FROM change_history
-        WHERE project_id = ?
+        SELECT h.id, h.project_id, h.element_id, h.entity_type, h.change_type, h.old_value, h.new_value,
+               h.timestamp, h.user_id, h.undo_status, h.change_set_id
+        FROM change_history h
+        LEFT JOIN events e ON e.id = h.id
+        WHERE h.project_id = ? AND h.user_id = ? AND h.undo_status = ?
+        ORDER BY e.seq ASC, h.rowid ASC
+        LIMIT 1
         "#
     )
     .bind(project_id)
-    .execute(pool)
+    .bind(user_id)
+    .bind(UNDO_STATUS_UNDONE)
+    .fetch_optional(conn)
+    .await
+    .map_err(AppError::Database)?;
+
+    Ok(row.as_ref().map(history_from_row))
+}
+
+// 変更グループ内の指定した状態の変更（記録順）
+pub async fn list_change_set_history(
+    conn: &mut SqliteConnection,
+    change_set_id: &str,
+    undo_status: &str,
+) -> Result<Vec<History>> {
+    let rows = sqlx::query(
+        r#"
+        SELECT h.id, h.project_id, h.element_id, h.entity_type, h.change_type, h.old_value, h.new_value,
+               h.timestamp, h.user_id, h.undo_status, h.change_set_id
+        FROM change_history h
+        LEFT JOIN events e ON e.id = h.id
+        WHERE h.change_set_id = ? AND h.undo_status = ?
+        ORDER BY e.seq ASC, h.rowid ASC
+        "#
+    )
+    .bind(change_set_id)
+    .bind(undo_status)
+    .fetch_all(conn)
+    .await
+    .map_err(AppError::Database)?;
+
+    Ok(rows.iter().map(history_from_row).collect())
+}
+
+pub async fn set_undo_status(conn: &mut SqliteConnection, history_id: &str, status: &str) -> Result<()> {
+    sqlx::query(
+        r#"
+        UPDATE change_history
+        SET undo_status = ?
+        WHERE id = ?
+        "#
+    )
+    .bind(status)
+    .bind(history_id)
+    .execute(conn)
     .await
     .map_err(AppError::Database)?;
 
     Ok(())
-} 
\ No newline at end of file
+}
+
+// 変更履歴を時系列順に再生し、指定時点の要素・関係性を復元する
+pub async fn reconstruct_project_state(
+    pool: &SqlitePool,
+    project_id: &str,
+    at: OffsetDateTime,
+) -> Result<ProjectState> {
+    let rows = sqlx::query(
+        r#"
+        SELECT h.id, h.project_id, h.element_id, h.entity_type, h.change_type, h.old_value, h.new_value,
+               h.timestamp, h.user_id, h.undo_status, h.change_set_id
+        FROM change_history h
+        LEFT JOIN events e ON e.id = h.id
+        WHERE h.project_id = ?
+        ORDER BY e.seq ASC, h.rowid ASC
+        "#
+    )
+    .bind(project_id)
+    .fetch_all(pool)
+    .await
+    .map_err(AppError::Database)?;
+    let history: Vec<History> = rows.iter().map(history_from_row).collect();
+
+    let mut elements = replay_history(
+        &history,
+        ENTITY_ELEMENT,
+        list_elements(pool, project_id).await?,
+        |e| (e.id.as_str(), e.created_at),
+        at,
+    )?;
+    elements.sort_by_key(|e| e.created_at);
+
+    let mut relationships = replay_history(
+        &history,
+        ENTITY_RELATIONSHIP,
+        list_relationships(pool, project_id).await?,
+        |r| (r.id.as_str(), r.created_at),
+        at,
+    )?;
+    relationships.sort_by_key(|r| r.created_at);
+
+    Ok(ProjectState {
+        project_id: project_id.to_string(),
+        at,
+        elements,
+        relationships,
+    })
+}
+
+// 対象ごとに、指定時点以前の最後の変更後の値を採用する
+// 指定時点以前に履歴がなければ、指定時点以降の最初の変更前の値を採用し、
+// 履歴が一切ない対象は現在の値を作成日時で判定する
+fn replay_history<T, F>(
+    history: &[History],
+    entity_type: &str,
+    current: Vec<T>,
+    key: F,
+    at: OffsetDateTime,
+) -> Result<Vec<T>>
+where
+    T: DeserializeOwned,
+    F: Fn(&T) -> (&str, OffsetDateTime),
+{
+    let mut before: HashMap<&str, Option<&JsonValue>> = HashMap::new();
+    let mut after: HashMap<&str, Option<&JsonValue>> = HashMap::new();
+
+    for entry in history.iter().filter(|h| h.entity_type == entity_type) {
+        if entry.timestamp <= at {
+            before.insert(&entry.element_id, entry.new_value.as_ref());
+        } else {
+            after
+                .entry(&entry.element_id)
+                .or_insert(entry.old_value.as_ref());
+        }
+    }
+
+    let mut result = Vec::new();
+    for (id, value) in after.iter() {
+        if !before.contains_key(id) {
+            before.insert(id, *value);
+        }
+    }
+    for value in before.values().flatten() {
+        result.push(serde_json::from_value((*value).clone())?);
+    }
+    for item in current {
+        let (id, created_at) = key(&item);
+        if !before.contains_key(id) && created_at <= at {
+            result.push(item);
+        }
+    }
+
+    Ok(result)
+}
+
+// 対象の最新の変更履歴
+pub async fn latest_history_entry<'e, E>(
+    executor: E,
+    project_id: &str,
+    entity_type: &str,
+    element_id: &str,
+) -> Result<Option<History>>
+where
+    E: Executor<'e, Database = Sqlite>,
+{
+    let row = sqlx::query(
+        r#"
+        SELECT h.id, h.project_id, h.element_id, h.entity_type, h.change_type, h.old_value, h.new_value,
+               h.timestamp, h.user_id, h.undo_status, h.change_set_id
+        FROM change_history h
+        LEFT JOIN events e ON e.id = h.id
+        WHERE h.project_id = ? AND h.entity_type = ? AND h.element_id = ?
+        ORDER BY e.seq DESC, h.rowid DESC
+        LIMIT 1
+        "#
+    )
+    .bind(project_id)
+    .bind(entity_type)
+    .bind(element_id)
+    .fetch_optional(executor)
+    .await
+    .map_err(AppError::Database)?;
+
+    Ok(row.as_ref().map(history_from_row))
+}
+
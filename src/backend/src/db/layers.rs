@@ -0,0 +1,148 @@
+use std::collections::BTreeMap;
+
+use sqlx::{sqlite::SqliteRow, Executor, Row, Sqlite, SqliteConnection, SqlitePool};
+use serde_json::Value as JsonValue;
+
+use crate::{
+    error::{AppError, Result},
+    models::layer::{Layer, UpdateLayer},
+};
+
+fn layer_from_row(row: &SqliteRow) -> Result<Layer> {
+    Ok(Layer {
+        project_id: row.get("project_id"),
+        name: row.get("name"),
+        locked: row.get("locked"),
+        visible: row.get("visible"),
+        allowed_users: serde_json::from_value(row.get::<JsonValue, _>("allowed_users"))?,
+        element_count: 0,
+        updated_by: row.get("updated_by"),
+        updated_at: row.get("updated_at"),
+    })
+}
+
+// 要素が配置されているレイヤーと設定済みのレイヤーの一覧
+pub async fn list_layers(pool: &SqlitePool, project_id: &str) -> Result<Vec<Layer>> {
+    let rows = sqlx::query(
+        r#"
+        SELECT project_id, name, locked, visible, allowed_users, updated_by, updated_at
+        FROM layer_settings
+        WHERE project_id = ?
+        "#
+    )
+    .bind(project_id)
+    .fetch_all(pool)
+    .await
+    .map_err(AppError::Database)?;
+
+    let mut layers = BTreeMap::new();
+    for row in &rows {
+        let layer = layer_from_row(row)?;
+        layers.insert(layer.name.clone(), layer);
+    }
+
+    let counts = sqlx::query(
+        r#"
+        SELECT json_extract(properties, '$.common.layer') AS name, COUNT(*) AS element_count
+        FROM elements
+        WHERE project_id = ? AND json_extract(properties, '$.common.layer') IS NOT NULL
+        GROUP BY name
+        "#
+    )
+    .bind(project_id)
+    .fetch_all(pool)
+    .await
+    .map_err(AppError::Database)?;
+
+    for row in &counts {
+        let name: String = row.get("name");
+        layers
+            .entry(name.clone())
+            .or_insert_with(|| Layer::new(project_id.to_string(), name))
+            .element_count = row.get("element_count");
+    }
+
+    Ok(layers.into_values().collect())
+}
+
+// レイヤーの設定（未設定なら既定値）
+pub async fn get_layer<'e, E>(executor: E, project_id: &str, name: &str) -> Result<Layer>
+where
+    E: Executor<'e, Database = Sqlite>,
+{
+    let row = sqlx::query(
+        r#"
+        SELECT project_id, name, locked, visible, allowed_users, updated_by, updated_at
+        FROM layer_settings
+        WHERE project_id = ? AND name = ?
+        "#
+    )
+    .bind(project_id)
+    .bind(name)
+    .fetch_optional(executor)
+    .await
+    .map_err(AppError::Database)?;
+
+    match row {
+        Some(row) => layer_from_row(&row),
+        None => Ok(Layer::new(project_id.to_string(), name.to_string())),
+    }
+}
+
+pub async fn update_layer(
+    conn: &mut SqliteConnection,
+    project_id: &str,
+    name: &str,
+    data: UpdateLayer,
+    user_id: &str,
+) -> Result<Layer> {
+    let mut layer = get_layer(&mut *conn, project_id, name).await?;
+    if let Some(locked) = data.locked {
+        layer.locked = locked;
+    }
+    if let Some(visible) = data.visible {
+        layer.visible = visible;
+    }
+    if let Some(allowed_users) = data.allowed_users {
+        layer.allowed_users = allowed_users;
+    }
+
+    sqlx::query(
+        r#"
+        INSERT INTO layer_settings (project_id, name, locked, visible, allowed_users, updated_by)
+        VALUES (?, ?, ?, ?, ?, ?)
+        ON CONFLICT(project_id, name) DO UPDATE SET
+            locked = excluded.locked,
+            visible = excluded.visible,
+            allowed_users = excluded.allowed_users,
+            updated_by = excluded.updated_by,
+            updated_at = CURRENT_TIMESTAMP
+        "#
+    )
+    .bind(project_id)
+    .bind(name)
+    .bind(layer.locked)
+    .bind(layer.visible)
+    .bind(serde_json::to_value(&layer.allowed_users)?)
+    .bind(user_id)
+    .execute(&mut *conn)
+    .await
+    .map_err(AppError::Database)?;
+
+    let mut layer = get_layer(&mut *conn, project_id, name).await?;
+    layer.element_count = sqlx::query(
+        r#"
+        SELECT COUNT(*) AS element_count
+        FROM elements
+        WHERE project_id = ? AND json_extract(properties, '$.common.layer') = ?
+        "#
+    )
+    .bind(project_id)
+    .bind(name)
+    .fetch_one(&mut *conn)
+    .await
+    .map_err(AppError::Database)?
+    .get("element_count");
+
+    Ok(layer)
+}
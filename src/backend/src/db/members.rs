@@ -0,0 +1,160 @@
+use sqlx::{sqlite::SqliteRow, Executor, Row, Sqlite, SqliteConnection, SqlitePool};
+
+use crate::{
+    error::{AppError, Result},
+    models::member::{Member, ProjectRole},
+};
+
+fn member_from_row(row: &SqliteRow) -> Result<Member> {
+    let role: String = row.get("role");
+    Ok(Member {
+        project_id: row.get("project_id"),
+        user_id: row.get("user_id"),
+        username: row.get("username"),
+        role: ProjectRole::parse(&role)
+            .ok_or_else(|| AppError::Internal(format!("Unknown project role: {}", role)))?,
+        created_at: row.get("created_at"),
+    })
+}
+
+pub async fn list_members(pool: &SqlitePool, project_id: &str) -> Result<Vec<Member>> {
+    let rows = sqlx::query(
+        r#"
+        SELECT m.project_id, m.user_id, u.username, m.role, m.created_at
+        FROM project_members m
+        JOIN users u ON u.id = m.user_id
+        WHERE m.project_id = ?
+        ORDER BY m.created_at ASC
+        "#
+    )
+    .bind(project_id)
+    .fetch_all(pool)
+    .await
+    .map_err(AppError::Database)?;
+
+    rows.iter().map(member_from_row).collect()
+}
+
+pub async fn get_member<'e, E>(executor: E, project_id: &str, user_id: &str) -> Result<Option<Member>>
+where
+    E: Executor<'e, Database = Sqlite>,
+{
+    let row = sqlx::query(
+        r#"
+        SELECT m.project_id, m.user_id, u.username, m.role, m.created_at
+        FROM project_members m
+        JOIN users u ON u.id = m.user_id
+        WHERE m.project_id = ? AND m.user_id = ?
+        "#
+    )
+    .bind(project_id)
+    .bind(user_id)
+    .fetch_optional(executor)
+    .await
+    .map_err(AppError::Database)?;
+
+    row.as_ref().map(member_from_row).transpose()
+}
+
+pub async fn count_members<'e, E>(executor: E, project_id: &str, role: Option<ProjectRole>) -> Result<i64>
+where
+    E: Executor<'e, Database = Sqlite>,
+{
+    let count = sqlx::query_scalar(
+        r#"
+        SELECT COUNT(*)
+        FROM project_members
+        WHERE project_id = ? AND role = COALESCE(?, role)
+        "#
+    )
+    .bind(project_id)
+    .bind(role.map(|r| r.as_str()))
+    .fetch_one(executor)
+    .await
+    .map_err(AppError::Database)?;
+
+    Ok(count)
+}
+
+// メンバーが一人もいないプロジェクトの数
+pub async fn count_memberless_projects(pool: &SqlitePool) -> Result<i64> {
+    let count = sqlx::query_scalar(
+        r#"
+        SELECT COUNT(*)
+        FROM projects p
+        WHERE NOT EXISTS (SELECT 1 FROM project_members m WHERE m.project_id = p.id)
+        "#
+    )
+    .fetch_one(pool)
+    .await
+    .map_err(AppError::Database)?;
+
+    Ok(count)
+}
+
+// メンバーが一人もいないプロジェクトに、ユーザーをオーナーとして登録する
+// 認証の導入前に作成されたプロジェクトの移行に使う
+pub async fn assign_memberless_projects(pool: &SqlitePool, user_id: &str) -> Result<u64> {
+    let result = sqlx::query(
+        r#"
+        INSERT INTO project_members (project_id, user_id, role)
+        SELECT p.id, ?, 'owner'
+        FROM projects p
+        WHERE NOT EXISTS (SELECT 1 FROM project_members m WHERE m.project_id = p.id)
+        "#
+    )
+    .bind(user_id)
+    .execute(pool)
+    .await
+    .map_err(AppError::Database)?;
+
+    Ok(result.rows_affected())
+}
+
+// メンバーを追加する（登録済みなら権限を変更する）
+pub async fn upsert_member(
+    conn: &mut SqliteConnection,
+    project_id: &str,
+    user_id: &str,
+    role: ProjectRole,
+) -> Result<()> {
+    sqlx::query(
+        r#"
+        INSERT INTO project_members (project_id, user_id, role)
+        VALUES (?, ?, ?)
+        ON CONFLICT(project_id, user_id) DO UPDATE SET role = excluded.role
+        "#
+    )
+    .bind(project_id)
+    .bind(user_id)
+    .bind(role.as_str())
+    .execute(conn)
+    .await
+    .map_err(AppError::Database)?;
+
+    Ok(())
+}
+
+pub async fn delete_member(
+    conn: &mut SqliteConnection,
+    project_id: &str,
+    user_id: &str,
+) -> Result<()> {
+    let result = sqlx::query(
+        r#"
+        DELETE FROM project_members
+        WHERE project_id = ? AND user_id = ?
+        "#
+    )
+    .bind(project_id)
+    .bind(user_id)
+    .execute(conn)
+    .await
+    .map_err(AppError::Database)?;
+
+    if result.rows_affected() == 0 {
+        return Err(AppError::NotFound(format!("Member not found: {}", user_id)));
+    }
+
+    Ok(())
+}
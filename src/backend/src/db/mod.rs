@@ -3,9 +3,27 @@ pub mod elements;
 pub mod relationships;
 pub mod views;
 pub mod history;
+pub mod snapshots;
+pub mod branches;
+pub mod rules;
+pub mod users;
+pub mod members;
+pub mod layers;
+pub mod events;
+pub mod change_sets;
+pub mod spatial;
 
 pub use projects::*;
 pub use elements::*;
 pub use relationships::*;
 pub use views::*;
-pub use history::*; 
\ No newline at end of file
+pub use history::*;
+pub use snapshots::*;
+pub use branches::*;
+pub use rules::*;
+pub use users::*;
+pub use members::*;
+pub use layers::*;
+pub use events::*;
+pub use change_sets::*;
+pub use spatial::*;
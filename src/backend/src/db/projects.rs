@@ -1,18 +1,27 @@
-use sqlx::{Row, SqlitePool};
+use sqlx::{Executor, Row, Sqlite, SqliteConnection, SqlitePool};
 
+use super::events::append_event;
 use crate::{
     error::{AppError, Result},
-    models::project::{CreateProject, Project, UpdateProject},
+    models::{
+        event::{Event, NewEvent, ENTITY_PROJECT},
+        project::{CreateProject, Project, UpdateProject},
+    },
 };
 
-pub async fn list_projects(pool: &SqlitePool) -> Result<Vec<Project>> {
+// ユーザーが参加しているプロジェクト（メンバー未登録のプロジェクトを含む）
+pub async fn list_projects(pool: &SqlitePool, user_id: &str) -> Result<Vec<Project>> {
     let rows = sqlx::query(
         r#"
         SELECT id, name, description, created_at, updated_at, version
-        FROM projects
+        FROM projects p
+        WHERE EXISTS (
+            SELECT 1 FROM project_members m WHERE m.project_id = p.id AND m.user_id = ?
+        )
         ORDER BY updated_at DESC
         "#
     )
+    .bind(user_id)
     .fetch_all(pool)
     .await
     .map_err(AppError::Database)?;
@@ -32,7 +41,10 @@ pub async fn list_projects(pool: &SqlitePool) -> Result<Vec<Project>> {
     Ok(projects)
 }
 
-pub async fn get_project(pool: &SqlitePool, id: &str) -> Result<Project> {
+pub async fn get_project<'e, E>(executor: E, id: &str) -> Result<Project>
+where
+    E: Executor<'e, Database = Sqlite>,
+{
     let row = sqlx::query(
         r#"
         SELECT id, name, description, created_at, updated_at, version
@@ -41,7 +53,7 @@ pub async fn get_project(pool: &SqlitePool, id: &str) -> Result<Project> {
         "#
     )
     .bind(id)
-    .fetch_optional(pool)
+    .fetch_optional(executor)
     .await
     .map_err(AppError::Database)?
     .ok_or_else(|| AppError::NotFound(format!("Project not found: {}", id)))?;
@@ -56,7 +68,10 @@ pub async fn get_project(pool: &SqlitePool, id: &str) -> Result<Project> {
     })
 }
 
-pub async fn create_project(pool: &SqlitePool, data: CreateProject) -> Result<Project> {
+pub async fn create_project<'e, E>(executor: E, data: CreateProject) -> Result<Project>
+where
+    E: Executor<'e, Database = Sqlite>,
+{
     let project = Project::new(data.name, data.description);
 
     sqlx::query(
@@ -71,15 +86,20 @@ pub async fn create_project(pool: &SqlitePool, data: CreateProject) -> Result<Pr
     .bind(project.created_at)
     .bind(project.updated_at)
     .bind(project.version as i64)
-    .execute(pool)
+    .execute(executor)
     .await
     .map_err(AppError::Database)?;
 
     Ok(project)
 }
 
-pub async fn update_project(pool: &SqlitePool, id: &str, data: UpdateProject) -> Result<Project> {
-    let mut project = get_project(pool, id).await?;
+pub async fn update_project(
+    conn: &mut SqliteConnection,
+    id: &str,
+    data: UpdateProject,
+    expected_version: Option<i32>,
+) -> Result<Project> {
+    let mut project = get_project(&mut *conn, id).await?;
 
     if let Some(name) = data.name {
         project.name = name;
@@ -88,24 +108,38 @@ pub async fn update_project(pool: &SqlitePool, id: &str, data: UpdateProject) ->
         project.description = Some(description);
     }
 
-    sqlx::query(
+    let result = sqlx::query(
         r#"
         UPDATE projects
         SET name = ?, description = ?, updated_at = CURRENT_TIMESTAMP, version = version + 1
-        WHERE id = ?
+        WHERE id = ? AND version = COALESCE(?, version)
         "#
     )
     .bind(&project.name)
     .bind(&project.description)
     .bind(id)
-    .execute(pool)
+    .bind(expected_version.map(i64::from))
+    .execute(&mut *conn)
     .await
     .map_err(AppError::Database)?;
 
-    get_project(pool, id).await
+    if result.rows_affected() == 0 {
+        let current = get_project(&mut *conn, id).await?;
+        return Err(AppError::VersionConflict {
+            message: format!(
+                "Project {} has been modified (expected version {}, current version {})",
+                id,
+                expected_version.unwrap_or_default(),
+                current.version
+            ),
+            current: serde_json::to_value(current)?,
+        });
+    }
+
+    get_project(&mut *conn, id).await
 }
 
-pub async fn delete_project(pool: &SqlitePool, id: &str) -> Result<()> {
+pub async fn delete_project(conn: &mut SqliteConnection, id: &str) -> Result<()> {
     let result = sqlx::query(
         r#"
         DELETE FROM projects
@@ -113,7 +147,28 @@ pub async fn delete_project(pool: &SqlitePool, id: &str) -> Result<()> {
         "#
     )
     .bind(id)
-    .execute(pool)
+    .execute(conn)
+    .await
+    .map_err(AppError::Database)?;
+
+    if result.rows_affected() == 0 {
+        return Err(AppError::NotFound(format!("Project not found: {}", id)));
+    }
+
+    Ok(())
+}
+
+// スナップショットの復元など、プロジェクト全体に及ぶ変更でバージョンを進める
+pub async fn touch_project(conn: &mut SqliteConnection, id: &str) -> Result<()> {
+    let result = sqlx::query(
+        r#"
+        UPDATE projects
+        SET updated_at = CURRENT_TIMESTAMP, version = version + 1
+        WHERE id = ?
+        "#
+    )
+    .bind(id)
+    .execute(conn)
     .await
     .map_err(AppError::Database)?;
 
@@ -122,4 +177,21 @@ pub async fn delete_project(pool: &SqlitePool, id: &str) -> Result<()> {
     }
 
     Ok(())
+}
+
+// プロジェクト自体の変更をイベントとして記録する
+pub async fn record_project_change(
+    conn: &mut SqliteConnection,
+    project_id: &str,
+    change_type: &str,
+    before: Option<&Project>,
+    after: Option<&Project>,
+    user_id: &str,
+) -> Result<Event> {
+    let event = NewEvent::new(project_id, ENTITY_PROJECT, project_id, change_type, user_id)
+        .with_states(
+            before.map(serde_json::to_value).transpose()?,
+            after.map(serde_json::to_value).transpose()?,
+        );
+    append_event(conn, event).await
 } 
\ No newline at end of file
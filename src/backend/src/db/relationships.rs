@@ -1,4 +1,4 @@
-use sqlx::{Row, SqlitePool};
+use sqlx::{sqlite::SqliteRow, Executor, Row, Sqlite, SqliteConnection};
 use serde_json::Value as JsonValue;
 
 use crate::{
@@ -6,95 +6,156 @@ use crate::{
     models::relationship::{CreateRelationship, Relationship, UpdateRelationship},
 };
 
-pub async fn list_relationships(pool: &SqlitePool, element_id: &str) -> Result<Vec<Relationship>> {
+use super::elements::get_element;
+
+fn relationship_from_row(row: &SqliteRow) -> Relationship {
+    Relationship {
+        id: row.get("id"),
+        project_id: row.get("project_id"),
+        source_id: row.get("source_id"),
+        target_id: row.get("target_id"),
+        relationship_type: row.get("relationship_type"),
+        properties: row.get::<Option<JsonValue>, _>("properties"),
+        version: row.get::<i64, _>("version") as i32,
+        created_at: row.get("created_at"),
+    }
+}
+
+pub async fn list_relationships<'e, E>(executor: E, project_id: &str) -> Result<Vec<Relationship>>
+where
+    E: Executor<'e, Database = Sqlite>,
+{
     let rows = sqlx::query(
         r#"
-        SELECT id, source_id, target_id, relationship_type, properties, created_at
+        SELECT id, project_id, source_id, target_id, relationship_type, properties, version, created_at
         FROM element_relationships
-        WHERE source_id = ? OR target_id = ?
+        WHERE project_id = ?
         ORDER BY created_at ASC
         "#
     )
+    .bind(project_id)
+    .fetch_all(executor)
+    .await
+    .map_err(AppError::Database)?;
+
+    let relationships = rows.iter().map(relationship_from_row).collect();
+
+    Ok(relationships)
+}
+
+// 要素に接続している関係性の一覧
+pub async fn list_element_relationships<'e, E>(
+    executor: E,
+    project_id: &str,
+    element_id: &str,
+) -> Result<Vec<Relationship>>
+where
+    E: Executor<'e, Database = Sqlite>,
+{
+    let rows = sqlx::query(
+        r#"
+        SELECT id, project_id, source_id, target_id, relationship_type, properties, version, created_at
+        FROM element_relationships
+        WHERE project_id = ? AND (source_id = ? OR target_id = ?)
+        ORDER BY created_at ASC
+        "#
+    )
+    .bind(project_id)
     .bind(element_id)
     .bind(element_id)
-    .fetch_all(pool)
+    .fetch_all(executor)
     .await
     .map_err(AppError::Database)?;
 
-    let relationships = rows
-        .iter()
-        .map(|row| Relationship {
-            id: row.get("id"),
-            source_id: row.get("source_id"),
-            target_id: row.get("target_id"),
-            relationship_type: row.get("relationship_type"),
-            properties: row.get::<Option<JsonValue>, _>("properties"),
-            created_at: row.get("created_at"),
-        })
-        .collect();
+    let relationships = rows.iter().map(relationship_from_row).collect();
 
     Ok(relationships)
 }
 
-pub async fn get_relationship(pool: &SqlitePool, relationship_id: &str) -> Result<Relationship> {
+pub async fn get_relationship<'e, E>(
+    executor: E,
+    project_id: &str,
+    relationship_id: &str,
+) -> Result<Relationship>
+where
+    E: Executor<'e, Database = Sqlite>,
+{
     let row = sqlx::query(
         r#"
-        SELECT id, source_id, target_id, relationship_type, properties, created_at
+        SELECT id, project_id, source_id, target_id, relationship_type, properties, version, created_at
         FROM element_relationships
-        WHERE id = ?
+        WHERE id = ? AND project_id = ?
         "#
     )
     .bind(relationship_id)
-    .fetch_optional(pool)
+    .bind(project_id)
+    .fetch_optional(executor)
     .await
     .map_err(AppError::Database)?
     .ok_or_else(|| AppError::NotFound(format!("Relationship not found: {}", relationship_id)))?;
 
-    Ok(Relationship {
-        id: row.get("id"),
-        source_id: row.get("source_id"),
-        target_id: row.get("target_id"),
-        relationship_type: row.get("relationship_type"),
-        properties: row.get::<Option<JsonValue>, _>("properties"),
-        created_at: row.get("created_at"),
-    })
+    Ok(relationship_from_row(&row))
 }
 
-pub async fn create_relationship(pool: &SqlitePool, data: CreateRelationship) -> Result<Relationship> {
+pub async fn create_relationship(
+    conn: &mut SqliteConnection,
+    project_id: &str,
+    data: CreateRelationship,
+) -> Result<Relationship> {
+    // 関係の両端が同じプロジェクトの要素であることを確認する
+    for element_id in [&data.source_id, &data.target_id] {
+        get_element(&mut *conn, project_id, element_id).await?;
+    }
+
     let relationship = Relationship::new(
+        project_id.to_string(),
         data.source_id,
         data.target_id,
         data.relationship_type,
         data.properties,
     );
 
+    insert_relationship(conn, &relationship).await?;
+
+    Ok(relationship)
+}
+
+// 関係性をそのままの内容・バージョンで挿入する（プロジェクトの複製など）
+pub async fn insert_relationship(
+    conn: &mut SqliteConnection,
+    relationship: &Relationship,
+) -> Result<()> {
     sqlx::query(
         r#"
         INSERT INTO element_relationships (
-            id, source_id, target_id, relationship_type, properties, created_at
+            id, project_id, source_id, target_id, relationship_type, properties, version, created_at
         )
-        VALUES (?, ?, ?, ?, ?, ?)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?)
         "#
     )
     .bind(&relationship.id)
+    .bind(&relationship.project_id)
     .bind(&relationship.source_id)
     .bind(&relationship.target_id)
     .bind(&relationship.relationship_type)
     .bind(&relationship.properties)
+    .bind(relationship.version as i64)
     .bind(relationship.created_at)
-    .execute(pool)
+    .execute(conn)
     .await
     .map_err(AppError::Database)?;
 
-    Ok(relationship)
+    Ok(())
 }
 
 pub async fn update_relationship(
-    pool: &SqlitePool,
+    conn: &mut SqliteConnection,
+    project_id: &str,
     relationship_id: &str,
     data: UpdateRelationship,
+    expected_version: Option<i32>,
 ) -> Result<Relationship> {
-    let mut relationship = get_relationship(pool, relationship_id).await?;
+    let mut relationship = get_relationship(&mut *conn, project_id, relationship_id).await?;
 
     if let Some(relationship_type) = data.relationship_type {
         relationship.relationship_type = relationship_type;
@@ -105,32 +166,52 @@ pub async fn update_relationship(
         })?);
     }
 
-    sqlx::query(
+    let result = sqlx::query(
         r#"
         UPDATE element_relationships
-        SET relationship_type = ?, properties = ?
-        WHERE id = ?
+        SET relationship_type = ?, properties = ?, version = version + 1
+        WHERE id = ? AND project_id = ? AND version = COALESCE(?, version)
         "#
     )
     .bind(&relationship.relationship_type)
     .bind(&relationship.properties)
     .bind(relationship_id)
-    .execute(pool)
+    .bind(project_id)
+    .bind(expected_version.map(i64::from))
+    .execute(&mut *conn)
     .await
     .map_err(AppError::Database)?;
 
-    get_relationship(pool, relationship_id).await
+    if result.rows_affected() == 0 {
+        let current = get_relationship(&mut *conn, project_id, relationship_id).await?;
+        return Err(AppError::VersionConflict {
+            message: format!(
+                "Relationship {} has been modified (expected version {}, current version {})",
+                relationship_id,
+                expected_version.unwrap_or_default(),
+                current.version
+            ),
+            current: serde_json::to_value(current)?,
+        });
+    }
+
+    get_relationship(&mut *conn, project_id, relationship_id).await
 }
 
-pub async fn delete_relationship(pool: &SqlitePool, relationship_id: &str) -> Result<()> {
+pub async fn delete_relationship(
+    conn: &mut SqliteConnection,
+    project_id: &str,
+    relationship_id: &str,
+) -> Result<()> {
     let result = sqlx::query(
         r#"
         DELETE FROM element_relationships
-        WHERE id = ?
+        WHERE id = ? AND project_id = ?
         "#
     )
     .bind(relationship_id)
-    .execute(pool)
+    .bind(project_id)
+    .execute(conn)
     .await
     .map_err(AppError::Database)?;
 
@@ -139,4 +220,40 @@ pub async fn delete_relationship(pool: &SqlitePool, relationship_id: &str) -> Re
     }
 
     Ok(())
-} 
\ No newline at end of file
+}
+
+// スナップショット等から関係性の状態を復元する（削除済みであれば再作成する）
+pub async fn restore_relationship(
+    conn: &mut SqliteConnection,
+    relationship: &Relationship,
+) -> Result<Relationship> {
+    sqlx::query(
+        r#"
+        INSERT INTO element_relationships (
+            id, project_id, source_id, target_id, relationship_type, properties, version, created_at
+        )
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+        ON CONFLICT(id) DO UPDATE SET
+            source_id = excluded.source_id,
+            target_id = excluded.target_id,
+            relationship_type = excluded.relationship_type,
+            properties = excluded.properties,
+            version = element_relationships.version + 1
+        WHERE element_relationships.project_id = excluded.project_id
+        "#
+    )
+    .bind(&relationship.id)
+    .bind(&relationship.project_id)
+    .bind(&relationship.source_id)
+    .bind(&relationship.target_id)
+    .bind(&relationship.relationship_type)
+    .bind(&relationship.properties)
+    .bind(relationship.version as i64 + 1)
+    .bind(relationship.created_at)
+    .execute(&mut *conn)
+    .await
+    .map_err(AppError::Database)?;
+
+    get_relationship(&mut *conn, &relationship.project_id, &relationship.id).await
+}
+
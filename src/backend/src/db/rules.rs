@@ -0,0 +1,81 @@
+use sqlx::{Executor, Row, Sqlite, SqlitePool};
+use serde_json::Value as JsonValue;
+
+use crate::{
+    error::{AppError, Result},
+    models::propagation::{PropagationRule, RuleSet},
+};
+
+// 登録済みのルールセット（未登録なら None）
+pub async fn get_rule_set<'e, E>(executor: E, project_id: &str) -> Result<Option<RuleSet>>
+where
+    E: Executor<'e, Database = Sqlite>,
+{
+    let row = sqlx::query(
+        r#"
+        SELECT project_id, rules, version, updated_by, updated_at
+        FROM propagation_rules
+        WHERE project_id = ?
+        "#
+    )
+    .bind(project_id)
+    .fetch_optional(executor)
+    .await
+    .map_err(AppError::Database)?;
+
+    row.map(|row| {
+        Ok(RuleSet {
+            project_id: row.get("project_id"),
+            rules: serde_json::from_value(row.get::<JsonValue, _>("rules"))?,
+            custom: true,
+            version: row.get::<i64, _>("version") as i32,
+            updated_by: row.get("updated_by"),
+            updated_at: row.get("updated_at"),
+        })
+    })
+    .transpose()
+}
+
+pub async fn save_rule_set(
+    pool: &SqlitePool,
+    project_id: &str,
+    rules: &[PropagationRule],
+    user_id: &str,
+) -> Result<RuleSet> {
+    sqlx::query(
+        r#"
+        INSERT INTO propagation_rules (project_id, rules, updated_by)
+        VALUES (?, ?, ?)
+        ON CONFLICT(project_id) DO UPDATE SET
+            rules = excluded.rules,
+            version = propagation_rules.version + 1,
+            updated_by = excluded.updated_by,
+            updated_at = CURRENT_TIMESTAMP
+        "#
+    )
+    .bind(project_id)
+    .bind(serde_json::to_value(rules)?)
+    .bind(user_id)
+    .execute(pool)
+    .await
+    .map_err(AppError::Database)?;
+
+    get_rule_set(pool, project_id)
+        .await?
+        .ok_or_else(|| AppError::Internal("Failed to save rule set".to_string()))
+}
+
+pub async fn delete_rule_set(pool: &SqlitePool, project_id: &str) -> Result<()> {
+    sqlx::query(
+        r#"
+        DELETE FROM propagation_rules
+        WHERE project_id = ?
+        "#
+    )
+    .bind(project_id)
+    .execute(pool)
+    .await
+    .map_err(AppError::Database)?;
+
+    Ok(())
+}
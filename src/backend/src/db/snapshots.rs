@@ -0,0 +1,162 @@
+use sqlx::{sqlite::SqliteRow, Row, SqlitePool};
+use serde_json::Value as JsonValue;
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+use crate::{
+    error::{AppError, Result},
+    models::snapshot::{CreateSnapshot, Snapshot, SnapshotSummary},
+};
+
+use super::{
+    elements::list_elements, projects::get_project, relationships::list_relationships,
+    views::list_views,
+};
+
+fn snapshot_from_row(row: &SqliteRow) -> Result<Snapshot> {
+    Ok(Snapshot {
+        id: row.get("id"),
+        project_id: row.get("project_id"),
+        label: row.get("label"),
+        description: row.get("description"),
+        project_version: row.get::<i64, _>("project_version") as i32,
+        elements: serde_json::from_value(row.get::<JsonValue, _>("elements"))?,
+        relationships: serde_json::from_value(row.get::<JsonValue, _>("relationships"))?,
+        views: serde_json::from_value(row.get::<JsonValue, _>("views"))?,
+        created_by: row.get("created_by"),
+        created_at: row.get("created_at"),
+    })
+}
+
+pub async fn list_snapshots(pool: &SqlitePool, project_id: &str) -> Result<Vec<SnapshotSummary>> {
+    let rows = sqlx::query(
+        r#"
+        SELECT id, project_id, label, description, project_version,
+               json_array_length(elements) AS element_count,
+               json_array_length(relationships) AS relationship_count,
+               json_array_length(views) AS view_count,
+               created_by, created_at
+        FROM project_snapshots
+        WHERE project_id = ?
+        ORDER BY created_at DESC
+        "#
+    )
+    .bind(project_id)
+    .fetch_all(pool)
+    .await
+    .map_err(AppError::Database)?;
+
+    let snapshots = rows
+        .iter()
+        .map(|row| SnapshotSummary {
+            id: row.get("id"),
+            project_id: row.get("project_id"),
+            label: row.get("label"),
+            description: row.get("description"),
+            project_version: row.get::<i64, _>("project_version") as i32,
+            element_count: row.get("element_count"),
+            relationship_count: row.get("relationship_count"),
+            view_count: row.get("view_count"),
+            created_by: row.get("created_by"),
+            created_at: row.get("created_at"),
+        })
+        .collect();
+
+    Ok(snapshots)
+}
+
+pub async fn get_snapshot(pool: &SqlitePool, project_id: &str, snapshot_id: &str) -> Result<Snapshot> {
+    let row = sqlx::query(
+        r#"
+        SELECT id, project_id, label, description, project_version,
+               elements, relationships, views, created_by, created_at
+        FROM project_snapshots
+        WHERE project_id = ? AND id = ?
+        "#
+    )
+    .bind(project_id)
+    .bind(snapshot_id)
+    .fetch_optional(pool)
+    .await
+    .map_err(AppError::Database)?
+    .ok_or_else(|| AppError::NotFound(format!("Snapshot not found: {}", snapshot_id)))?;
+
+    snapshot_from_row(&row)
+}
+
+// プロジェクトの要素・関係性・ビューをラベル付きで凍結する
+pub async fn create_snapshot(
+    pool: &SqlitePool,
+    project_id: &str,
+    data: CreateSnapshot,
+    user_id: &str,
+) -> Result<Snapshot> {
+    let project = get_project(pool, project_id).await?;
+
+    // 要素・関係性・ビューを同一トランザクション内で読み取る
+    let mut tx = pool.begin().await?;
+    let snapshot = Snapshot {
+        id: Uuid::new_v4().to_string(),
+        project_id: project_id.to_string(),
+        label: data.label,
+        description: data.description,
+        project_version: project.version,
+        elements: list_elements(&mut *tx, project_id).await?,
+        relationships: list_relationships(&mut *tx, project_id).await?,
+        views: list_views(&mut *tx, project_id).await?,
+        created_by: user_id.to_string(),
+        created_at: OffsetDateTime::now_utc(),
+    };
+
+    sqlx::query(
+        r#"
+        INSERT INTO project_snapshots (
+            id, project_id, label, description, project_version,
+            elements, relationships, views, created_by, created_at
+        )
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+        "#
+    )
+    .bind(&snapshot.id)
+    .bind(&snapshot.project_id)
+    .bind(&snapshot.label)
+    .bind(&snapshot.description)
+    .bind(snapshot.project_version as i64)
+    .bind(serde_json::to_value(&snapshot.elements)?)
+    .bind(serde_json::to_value(&snapshot.relationships)?)
+    .bind(serde_json::to_value(&snapshot.views)?)
+    .bind(&snapshot.created_by)
+    .bind(snapshot.created_at)
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| match e {
+        sqlx::Error::Database(ref db_err) if db_err.is_unique_violation() => AppError::Conflict(
+            format!("Snapshot label already exists: {}", snapshot.label),
+        ),
+        e => AppError::Database(e),
+    })?;
+
+    tx.commit().await?;
+
+    Ok(snapshot)
+}
+
+pub async fn delete_snapshot(pool: &SqlitePool, project_id: &str, snapshot_id: &str) -> Result<()> {
+    let result = sqlx::query(
+        r#"
+        DELETE FROM project_snapshots
+        WHERE project_id = ? AND id = ?
+        "#
+    )
+    .bind(project_id)
+    .bind(snapshot_id)
+    .execute(pool)
+    .await
+    .map_err(AppError::Database)?;
+
+    if result.rows_affected() == 0 {
+        return Err(AppError::NotFound(format!("Snapshot not found: {}", snapshot_id)));
+    }
+
+    Ok(())
+}
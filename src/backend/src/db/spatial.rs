@@ -0,0 +1,102 @@
+use sqlx::{Executor, Sqlite, SqliteConnection, SqlitePool};
+
+use crate::{
+    error::{AppError, Result},
+    models::{element::Element, spatial::Bounds},
+};
+
+use super::elements::element_from_row;
+
+// 要素の外接矩形を空間インデックスへ登録する（登録済みなら置き換える）
+pub async fn index_element_bounds(conn: &mut SqliteConnection, element: &Element) -> Result<()> {
+    let bounds = Bounds::of(&element.geometry);
+
+    sqlx::query(
+        r#"
+        INSERT INTO element_bounds_keys (element_id, project_id)
+        VALUES (?, ?)
+        ON CONFLICT(element_id) DO NOTHING
+        "#
+    )
+    .bind(&element.id)
+    .bind(&element.project_id)
+    .execute(&mut *conn)
+    .await
+    .map_err(AppError::Database)?;
+
+    sqlx::query(
+        r#"
+        INSERT OR REPLACE INTO element_bounds (id, min_x, max_x, min_y, max_y)
+        SELECT id, ?, ?, ?, ?
+        FROM element_bounds_keys
+        WHERE element_id = ?
+        "#
+    )
+    .bind(bounds.min_x)
+    .bind(bounds.max_x)
+    .bind(bounds.min_y)
+    .bind(bounds.max_y)
+    .bind(&element.id)
+    .execute(&mut *conn)
+    .await
+    .map_err(AppError::Database)?;
+
+    Ok(())
+}
+
+// 外接矩形が指定範囲と重なる要素を返す
+// インデックスの座標は単精度で丸められるため、形状との厳密な判定は呼び出し側で行う
+pub async fn list_elements_in_bounds<'e, E>(
+    executor: E,
+    project_id: &str,
+    bounds: &Bounds,
+) -> Result<Vec<Element>>
+where
+    E: Executor<'e, Database = Sqlite>,
+{
+    let rows = sqlx::query(
+        r#"
+        SELECT e.id, e.project_id, e.element_type, e.geometry, e.properties, e.metadata,
+               e.version, e.created_at, e.updated_at
+        FROM element_bounds b
+        JOIN element_bounds_keys k ON k.id = b.id
+        JOIN elements e ON e.id = k.element_id
+        WHERE b.min_x <= ? AND b.max_x >= ? AND b.min_y <= ? AND b.max_y >= ?
+          AND k.project_id = ?
+        ORDER BY e.created_at ASC
+        "#
+    )
+    .bind(bounds.max_x)
+    .bind(bounds.min_x)
+    .bind(bounds.max_y)
+    .bind(bounds.min_y)
+    .bind(project_id)
+    .fetch_all(executor)
+    .await
+    .map_err(AppError::Database)?;
+
+    rows.iter().map(element_from_row).collect()
+}
+
+// 空間インデックスに未登録の要素（インデックス追加前の要素）を登録する
+pub async fn index_missing_element_bounds(pool: &SqlitePool) -> Result<usize> {
+    let mut tx = pool.begin().await?;
+
+    let rows = sqlx::query(
+        r#"
+        SELECT id, project_id, element_type, geometry, properties, metadata, version, created_at, updated_at
+        FROM elements
+        WHERE id NOT IN (SELECT element_id FROM element_bounds_keys)
+        "#
+    )
+    .fetch_all(&mut *tx)
+    .await
+    .map_err(AppError::Database)?;
+
+    for row in &rows {
+        index_element_bounds(&mut tx, &element_from_row(row)?).await?;
+    }
+    tx.commit().await?;
+
+    Ok(rows.len())
+}
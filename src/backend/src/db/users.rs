@@ -0,0 +1,79 @@
+use sqlx::{sqlite::SqliteRow, Executor, Row, Sqlite, SqlitePool};
+
+use crate::{
+    error::{AppError, Result},
+    models::user::User,
+};
+
+fn user_from_row(row: &SqliteRow) -> User {
+    User {
+        id: row.get("id"),
+        username: row.get("username"),
+        display_name: row.get("display_name"),
+        created_at: row.get("created_at"),
+    }
+}
+
+pub async fn create_user(pool: &SqlitePool, user: &User, password_hash: &str) -> Result<()> {
+    sqlx::query(
+        r#"
+        INSERT INTO users (id, username, display_name, password_hash, created_at)
+        VALUES (?, ?, ?, ?, ?)
+        "#
+    )
+    .bind(&user.id)
+    .bind(&user.username)
+    .bind(&user.display_name)
+    .bind(password_hash)
+    .bind(user.created_at)
+    .execute(pool)
+    .await
+    .map_err(|e| match e {
+        sqlx::Error::Database(ref db_err) if db_err.is_unique_violation() => {
+            AppError::Conflict(format!("Username already taken: {}", user.username))
+        }
+        e => AppError::Database(e),
+    })?;
+
+    Ok(())
+}
+
+pub async fn get_user<'e, E>(executor: E, user_id: &str) -> Result<User>
+where
+    E: Executor<'e, Database = Sqlite>,
+{
+    let row = sqlx::query(
+        r#"
+        SELECT id, username, display_name, created_at
+        FROM users
+        WHERE id = ?
+        "#
+    )
+    .bind(user_id)
+    .fetch_optional(executor)
+    .await
+    .map_err(AppError::Database)?
+    .ok_or_else(|| AppError::NotFound(format!("User not found: {}", user_id)))?;
+
+    Ok(user_from_row(&row))
+}
+
+// ログイン用にユーザーとパスワードハッシュを取得する
+pub async fn find_user_credentials(
+    pool: &SqlitePool,
+    username: &str,
+) -> Result<Option<(User, String)>> {
+    let row = sqlx::query(
+        r#"
+        SELECT id, username, display_name, created_at, password_hash
+        FROM users
+        WHERE username = ?
+        "#
+    )
+    .bind(username)
+    .fetch_optional(pool)
+    .await
+    .map_err(AppError::Database)?;
+
+    Ok(row.map(|row| (user_from_row(&row), row.get("password_hash"))))
+}
@@ -1,22 +1,25 @@
-use sqlx::{Row, SqlitePool};
+use sqlx::{Executor, Row, Sqlite, SqliteConnection};
 use serde_json::Value as JsonValue;
 
 use crate::{
     error::{AppError, Result},
-    models::view::{UpdateView, View, ViewState},
+    models::view::{UpdateView, View},
 };
 
-pub async fn list_views(pool: &SqlitePool, project_id: &str) -> Result<Vec<View>> {
+pub async fn list_views<'e, E>(executor: E, project_id: &str) -> Result<Vec<View>>
+where
+    E: Executor<'e, Database = Sqlite>,
+{
     let rows = sqlx::query(
         r#"
-        SELECT id, project_id, view_type, state, created_at, updated_at
+        SELECT id, project_id, view_type, state, version, created_at, updated_at
         FROM views
         WHERE project_id = ?
         ORDER BY created_at ASC
         "#
     )
     .bind(project_id)
-    .fetch_all(pool)
+    .fetch_all(executor)
     .await
     .map_err(AppError::Database)?;
 
@@ -27,6 +30,7 @@ pub async fn list_views(pool: &SqlitePool, project_id: &str) -> Result<Vec<View>
             project_id: row.get("project_id"),
             view_type: row.get("view_type"),
             state: row.get::<JsonValue, _>("state"),
+            version: row.get::<i64, _>("version") as i32,
             created_at: row.get("created_at"),
             updated_at: row.get("updated_at"),
         })
@@ -35,17 +39,20 @@ pub async fn list_views(pool: &SqlitePool, project_id: &str) -> Result<Vec<View>
     Ok(views)
 }
 
-pub async fn get_view(pool: &SqlitePool, project_id: &str, view_type: &str) -> Result<View> {
+pub async fn get_view<'e, E>(executor: E, project_id: &str, view_type: &str) -> Result<View>
+where
+    E: Executor<'e, Database = Sqlite>,
+{
     let row = sqlx::query(
         r#"
-        SELECT id, project_id, view_type, state, created_at, updated_at
+        SELECT id, project_id, view_type, state, version, created_at, updated_at
         FROM views
         WHERE project_id = ? AND view_type = ?
         "#
     )
     .bind(project_id)
     .bind(view_type)
-    .fetch_optional(pool)
+    .fetch_optional(executor)
     .await
     .map_err(AppError::Database)?
     .ok_or_else(|| {
@@ -60,66 +67,59 @@ pub async fn get_view(pool: &SqlitePool, project_id: &str, view_type: &str) -> R
         project_id: row.get("project_id"),
         view_type: row.get("view_type"),
         state: row.get::<JsonValue, _>("state"),
+        version: row.get::<i64, _>("version") as i32,
         created_at: row.get("created_at"),
         updated_at: row.get("updated_at"),
     })
 }
 
-pub async fn create_view(
-    pool: &SqlitePool,
-    project_id: &str,
-    view_type: &str,
-    state: ViewState,
-) -> Result<View> {
-    let view = View::new(project_id.to_string(), view_type.to_string(), state);
-
-    sqlx::query(
-        r#"
-        INSERT INTO views (id, project_id, view_type, state, created_at, updated_at)
-        VALUES (?, ?, ?, ?, ?, ?)
-        "#
-    )
-    .bind(&view.id)
-    .bind(&view.project_id)
-    .bind(&view.view_type)
-    .bind(&view.state)
-    .bind(view.created_at)
-    .bind(view.updated_at)
-    .execute(pool)
-    .await
-    .map_err(AppError::Database)?;
-
-    Ok(view)
-}
-
 pub async fn update_view(
-    pool: &SqlitePool,
+    conn: &mut SqliteConnection,
     project_id: &str,
     view_type: &str,
     data: UpdateView,
+    expected_version: Option<i32>,
 ) -> Result<View> {
     let state = serde_json::to_value(data.state).map_err(|e| {
         AppError::InvalidRequest(format!("Failed to serialize view state: {}", e))
     })?;
 
-    sqlx::query(
+    let result = sqlx::query(
         r#"
         UPDATE views
-        SET state = ?, updated_at = CURRENT_TIMESTAMP
-        WHERE project_id = ? AND view_type = ?
+        SET state = ?, version = version + 1, updated_at = CURRENT_TIMESTAMP
+        WHERE project_id = ? AND view_type = ? AND version = COALESCE(?, version)
         "#
     )
     .bind(&state)
     .bind(project_id)
     .bind(view_type)
-    .execute(pool)
+    .bind(expected_version.map(i64::from))
+    .execute(&mut *conn)
     .await
     .map_err(AppError::Database)?;
 
-    get_view(pool, project_id, view_type).await
+    if result.rows_affected() == 0 {
+        let current = get_view(&mut *conn, project_id, view_type).await?;
+        return Err(AppError::VersionConflict {
+            message: format!(
+                "View {} has been modified (expected version {}, current version {})",
+                view_type,
+                expected_version.unwrap_or_default(),
+                current.version
+            ),
+            current: serde_json::to_value(current)?,
+        });
+    }
+
+    get_view(&mut *conn, project_id, view_type).await
 }
 
-pub async fn delete_view(pool: &SqlitePool, project_id: &str, view_type: &str) -> Result<()> {
+pub async fn delete_view(
+    conn: &mut SqliteConnection,
+    project_id: &str,
+    view_type: &str,
+) -> Result<()> {
     let result = sqlx::query(
         r#"
         DELETE FROM views
@@ -128,7 +128,7 @@ pub async fn delete_view(pool: &SqlitePool, project_id: &str, view_type: &str) -
     )
     .bind(project_id)
     .bind(view_type)
-    .execute(pool)
+    .execute(conn)
     .await
     .map_err(AppError::Database)?;
 
@@ -140,4 +140,30 @@ pub async fn delete_view(pool: &SqlitePool, project_id: &str, view_type: &str) -
     }
 
     Ok(())
-} 
\ No newline at end of file
+}
+
+// スナップショット等からビューの状態を復元する（削除済みであれば再作成する）
+pub async fn restore_view(conn: &mut SqliteConnection, view: &View) -> Result<View> {
+    sqlx::query(
+        r#"
+        INSERT INTO views (id, project_id, view_type, state, version, created_at, updated_at)
+        VALUES (?, ?, ?, ?, ?, ?, CURRENT_TIMESTAMP)
+        ON CONFLICT(id) DO UPDATE SET
+            view_type = excluded.view_type,
+            state = excluded.state,
+            version = views.version + 1,
+            updated_at = CURRENT_TIMESTAMP
+        "#
+    )
+    .bind(&view.id)
+    .bind(&view.project_id)
+    .bind(&view.view_type)
+    .bind(&view.state)
+    .bind(view.version as i64 + 1)
+    .bind(view.created_at)
+    .execute(&mut *conn)
+    .await
+    .map_err(AppError::Database)?;
+
+    get_view(&mut *conn, &view.project_id, &view.view_type).await
+}
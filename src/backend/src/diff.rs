@@ -0,0 +1,226 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
+use serde_json::Value as JsonValue;
+
+use crate::{
+    error::Result,
+    models::diff::{EntityDiff, FieldChange, ModifiedEntry},
+};
+
+// 要素の比較対象フィールド
+pub const ELEMENT_DIFF_FIELDS: [&str; 4] = ["element_type", "geometry", "properties", "metadata"];
+
+// 関係性の比較対象フィールド
+pub const RELATIONSHIP_DIFF_FIELDS: [&str; 4] =
+    ["relationship_type", "source_id", "target_id", "properties"];
+
+// 2つの一覧をIDで突き合わせ、追加・削除・変更を求める
+pub fn diff_entities<T, F>(
+    from: &[T],
+    to: &[T],
+    id_of: F,
+    fields: &[&str],
+) -> Result<EntityDiff>
+where
+    T: Serialize,
+    F: Fn(&T) -> &str,
+{
+    let from_map: HashMap<&str, &T> = from.iter().map(|item| (id_of(item), item)).collect();
+    let to_map: HashMap<&str, &T> = to.iter().map(|item| (id_of(item), item)).collect();
+    let mut diff = EntityDiff::default();
+
+    for item in from {
+        if !to_map.contains_key(id_of(item)) {
+            diff.removed.push(serde_json::to_value(item)?);
+        }
+    }
+
+    for item in to {
+        let id = id_of(item);
+        let Some(before) = from_map.get(id) else {
+            diff.added.push(serde_json::to_value(item)?);
+            continue;
+        };
+
+        let old = serde_json::to_value(before)?;
+        let new = serde_json::to_value(item)?;
+        let mut changes = Vec::new();
+        for field in fields {
+            json_changes(field, &old[field], &new[field], &mut changes);
+        }
+        if !changes.is_empty() {
+            diff.modified.push(ModifiedEntry {
+                id: id.to_string(),
+                changes,
+            });
+        }
+    }
+
+    Ok(diff)
+}
+
+// JSON値を再帰的に比較し、葉の単位で変更を列挙する
+// パスは "geometry.points[2].x" の形式
+pub fn json_changes(path: &str, old: &JsonValue, new: &JsonValue, out: &mut Vec<FieldChange>) {
+    if old == new {
+        return;
+    }
+
+    match (old, new) {
+        (JsonValue::Object(old_map), JsonValue::Object(new_map)) => {
+            for (key, old_value) in old_map {
+                let child = format!("{}.{}", path, key);
+                match new_map.get(key) {
+                    Some(new_value) => json_changes(&child, old_value, new_value, out),
+                    None => out.push(FieldChange {
+                        path: child,
+                        old: Some(old_value.clone()),
+                        new: None,
+                    }),
+                }
+            }
+            for (key, new_value) in new_map {
+                if !old_map.contains_key(key) {
+                    out.push(FieldChange {
+                        path: format!("{}.{}", path, key),
+                        old: None,
+                        new: Some(new_value.clone()),
+                    });
+                }
+            }
+        }
+        (JsonValue::Array(old_items), JsonValue::Array(new_items)) => {
+            for index in 0..old_items.len().max(new_items.len()) {
+                let child = format!("{}[{}]", path, index);
+                match (old_items.get(index), new_items.get(index)) {
+                    (Some(old_value), Some(new_value)) => {
+                        json_changes(&child, old_value, new_value, out)
+                    }
+                    (old_value, new_value) => out.push(FieldChange {
+                        path: child,
+                        old: old_value.cloned(),
+                        new: new_value.cloned(),
+                    }),
+                }
+            }
+        }
+        _ => out.push(FieldChange {
+            path: path.to_string(),
+            old: (!old.is_null()).then(|| old.clone()),
+            new: (!new.is_null()).then(|| new.clone()),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    type Change = (String, Option<JsonValue>, Option<JsonValue>);
+
+    fn flatten(changes: &[FieldChange]) -> Vec<Change> {
+        changes
+            .iter()
+            .map(|c| (c.path.clone(), c.old.clone(), c.new.clone()))
+            .collect()
+    }
+
+    fn changes(path: &str, old: JsonValue, new: JsonValue) -> Vec<Change> {
+        let mut out = Vec::new();
+        json_changes(path, &old, &new, &mut out);
+        flatten(&out)
+    }
+
+    fn change(path: &str, old: Option<JsonValue>, new: Option<JsonValue>) -> Change {
+        (path.to_string(), old, new)
+    }
+
+    fn element(id: &str, x: f64, version: i64) -> JsonValue {
+        json!({
+            "id": id,
+            "element_type": "wall",
+            "geometry": { "kind": "rect", "x": x, "y": 0.0, "width": 1.0, "height": 1.0 },
+            "properties": {},
+            "metadata": {},
+            "version": version,
+        })
+    }
+
+    #[test]
+    fn detects_added_removed_and_modified_entities() {
+        let from = vec![element("e1", 0.0, 1), element("e2", 0.0, 1), element("e3", 0.0, 1)];
+        let to = vec![element("e2", 2.0, 2), element("e3", 0.0, 5), element("e4", 0.0, 1)];
+
+        let diff = diff_entities(&from, &to, |e| e["id"].as_str().unwrap(), &ELEMENT_DIFF_FIELDS)
+            .unwrap();
+        assert_eq!(diff.added, vec![element("e4", 0.0, 1)]);
+        assert_eq!(diff.removed, vec![element("e1", 0.0, 1)]);
+        // 比較対象でないフィールド（version）だけが異なる要素は変更に含めない
+        assert_eq!(diff.modified.len(), 1);
+        assert_eq!(diff.modified[0].id, "e2");
+        assert_eq!(
+            flatten(&diff.modified[0].changes),
+            vec![change("geometry.x", Some(json!(0.0)), Some(json!(2.0)))]
+        );
+    }
+
+    #[test]
+    fn reports_nested_paths() {
+        assert_eq!(
+            changes(
+                "properties",
+                json!({ "common": { "name": "a", "layer": "0" } }),
+                json!({ "common": { "name": "b" }, "floorPlan": { "area": 4.0 } }),
+            ),
+            vec![
+                change("properties.common.layer", Some(json!("0")), None),
+                change("properties.common.name", Some(json!("a")), Some(json!("b"))),
+                change("properties.floorPlan", None, Some(json!({ "area": 4.0 }))),
+            ]
+        );
+        assert!(changes("properties", json!({ "a": [1] }), json!({ "a": [1] })).is_empty());
+    }
+
+    #[test]
+    fn reports_array_changes_by_index() {
+        let points = |coords: &[(f64, f64)]| {
+            JsonValue::from(coords.iter().map(|(x, y)| json!({ "x": x, "y": y })).collect::<Vec<_>>())
+        };
+        assert_eq!(
+            changes(
+                "geometry.points",
+                points(&[(0.0, 0.0), (1.0, 0.0)]),
+                points(&[(0.0, 0.5), (1.0, 0.0), (1.0, 1.0)]),
+            ),
+            vec![
+                change("geometry.points[0].y", Some(json!(0.0)), Some(json!(0.5))),
+                change("geometry.points[2]", None, Some(json!({ "x": 1.0, "y": 1.0 }))),
+            ]
+        );
+        assert_eq!(
+            changes("tags", json!(["a", "b", "c"]), json!(["a"])),
+            vec![
+                change("tags[1]", Some(json!("b")), None),
+                change("tags[2]", Some(json!("c")), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn treats_null_as_absent() {
+        assert_eq!(
+            changes("properties.floorPlan", JsonValue::Null, json!({ "area": 4.0 })),
+            vec![change("properties.floorPlan", None, Some(json!({ "area": 4.0 })))]
+        );
+        assert_eq!(
+            changes("properties.structural", json!("steel"), JsonValue::Null),
+            vec![change("properties.structural", Some(json!("steel")), None)]
+        );
+        assert_eq!(
+            changes("geometry", json!({ "kind": "rect" }), json!([1])),
+            vec![change("geometry", Some(json!({ "kind": "rect" })), Some(json!([1])))]
+        );
+    }
+}
@@ -23,6 +23,13 @@ pub enum AppError {
     #[error("Conflict: {0}")]
     Conflict(String),
 
+    // 期待したバージョンがサーバー上の最新と異なる（現在のデータを返す）
+    #[error("Version conflict: {message}")]
+    VersionConflict {
+        message: String,
+        current: serde_json::Value,
+    },
+
     #[error("Unauthorized: {0}")]
     Unauthorized(String),
 
@@ -33,36 +40,46 @@ pub enum AppError {
     Internal(String),
 }
 
-impl IntoResponse for AppError {
-    fn into_response(self) -> Response {
-        let (status, error_message) = match self {
-            AppError::Database(ref e) => (
+impl AppError {
+    // HTTPステータスとクライアントに返すメッセージ
+    pub fn status_and_message(&self) -> (StatusCode, String) {
+        match self {
+            AppError::Database(e) => (
                 StatusCode::INTERNAL_SERVER_ERROR,
                 format!("A database error occurred: {}", e),
             ),
-            AppError::Json(ref e) => (
+            AppError::Json(e) => (
                 StatusCode::BAD_REQUEST,
                 format!("A JSON error occurred: {}", e),
             ),
-            AppError::NotFound(ref message) => (StatusCode::NOT_FOUND, message.clone()),
-            AppError::InvalidRequest(ref message) => (StatusCode::BAD_REQUEST, message.clone()),
-            AppError::Conflict(ref message) => (StatusCode::CONFLICT, message.clone()),
-            AppError::Unauthorized(ref message) => (StatusCode::UNAUTHORIZED, message.clone()),
-            AppError::Forbidden(ref message) => (StatusCode::FORBIDDEN, message.clone()),
-            AppError::Internal(ref message) => (
+            AppError::NotFound(message) => (StatusCode::NOT_FOUND, message.clone()),
+            AppError::InvalidRequest(message) => (StatusCode::BAD_REQUEST, message.clone()),
+            AppError::Conflict(message) | AppError::VersionConflict { message, .. } => {
+                (StatusCode::CONFLICT, message.clone())
+            }
+            AppError::Unauthorized(message) => (StatusCode::UNAUTHORIZED, message.clone()),
+            AppError::Forbidden(message) => (StatusCode::FORBIDDEN, message.clone()),
+            AppError::Internal(message) => (
                 StatusCode::INTERNAL_SERVER_ERROR,
                 format!("An internal error occurred: {}", message),
             ),
-        };
+        }
+    }
+}
 
-        let body = Json(json!({
-            "error": {
-                "code": status.as_u16(),
-                "message": error_message,
-            }
-        }));
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        let (status, error_message) = self.status_and_message();
+        let mut error = json!({
+            "code": status.as_u16(),
+            "message": error_message,
+        });
+        // バージョン競合では現在のデータも返す
+        if let AppError::VersionConflict { current, .. } = self {
+            error["current"] = current;
+        }
 
-        (status, body).into_response()
+        (status, Json(json!({ "error": error }))).into_response()
     }
 }
 
@@ -0,0 +1,65 @@
+use axum::{extract::State, Json};
+
+use crate::{
+    auth::{self, AuthUser},
+    db,
+    error::{AppError, Result},
+    models::user::{LoginRequest, LoginResponse, RegisterUser, User},
+    AppState,
+};
+
+// パスワードの最小文字数
+const MIN_PASSWORD_LENGTH: usize = 8;
+
+pub async fn register(
+    State(state): State<AppState>,
+    Json(data): Json<RegisterUser>,
+) -> Result<Json<User>> {
+    let username = data.username.trim().to_string();
+    if username.is_empty() {
+        return Err(AppError::InvalidRequest(
+            "Username must not be empty".to_string(),
+        ));
+    }
+    if data.password.chars().count() < MIN_PASSWORD_LENGTH {
+        return Err(AppError::InvalidRequest(format!(
+            "Password must be at least {} characters",
+            MIN_PASSWORD_LENGTH
+        )));
+    }
+
+    let user = User::new(username, data.display_name);
+    let password_hash = auth::hash_password(&data.password)?;
+    db::create_user(&state.db, &user, &password_hash).await?;
+    Ok(Json(user))
+}
+
+pub async fn login(
+    State(state): State<AppState>,
+    Json(data): Json<LoginRequest>,
+) -> Result<Json<LoginResponse>> {
+    let invalid = || AppError::Unauthorized("Invalid username or password".to_string());
+
+    // ユーザーが存在しない場合もダミーのハッシュで照合し、応答時間からユーザー名を推測させない
+    let credentials = db::find_user_credentials(&state.db, data.username.trim()).await?;
+    let password_hash = credentials
+        .as_ref()
+        .map_or(auth::dummy_password_hash(), |(_, hash)| hash.as_str());
+    let verified = auth::verify_password(&data.password, password_hash);
+    let user = credentials
+        .filter(|_| verified)
+        .map(|(user, _)| user)
+        .ok_or_else(invalid)?;
+
+    let (token, expires_at) = state.auth.issue(&user.id)?;
+    Ok(Json(LoginResponse {
+        token,
+        expires_at,
+        user,
+    }))
+}
+
+pub async fn me(State(state): State<AppState>, user: AuthUser) -> Result<Json<User>> {
+    let user = db::get_user(&state.db, &user.id).await?;
+    Ok(Json(user))
+}
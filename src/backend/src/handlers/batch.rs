@@ -0,0 +1,115 @@
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+use sqlx::SqliteConnection;
+
+use crate::{
+    auth::AuthUser,
+    error::{AppError, Result},
+    models::batch::{BatchOperation, BatchOutcome, BatchRequest, BatchResult, MAX_BATCH_OPERATIONS},
+    mutations::Mutation,
+    AppState,
+};
+
+// 要素・関係性の作成・更新・削除をまとめて一つのトランザクションで適用する
+// いずれかの操作が失敗した場合は何も適用しない
+pub async fn apply_batch(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(project_id): Path<String>,
+    Json(data): Json<BatchRequest>,
+) -> Result<Json<BatchResult>> {
+    if data.operations.is_empty() {
+        return Err(AppError::InvalidRequest(
+            "A batch must contain at least one operation".to_string(),
+        ));
+    }
+    if data.operations.len() > MAX_BATCH_OPERATIONS {
+        return Err(AppError::InvalidRequest(format!(
+            "A batch can contain at most {} operations",
+            MAX_BATCH_OPERATIONS
+        )));
+    }
+
+    let mut mutation = Mutation::new(&state, &user, &project_id).await?;
+
+    let description = data
+        .description
+        .unwrap_or_else(|| format!("Batch of {} operations", data.operations.len()));
+
+    let mut tx = state.db.begin().await?;
+    let mut results = Vec::with_capacity(data.operations.len());
+    for (index, operation) in data.operations.into_iter().enumerate() {
+        let outcome = apply_operation(&mut mutation, &mut tx, operation)
+            .await
+            .map_err(|e| at_operation(index, e))?;
+        results.push(outcome);
+    }
+    mutation.describe(description);
+    let change_set = mutation.commit(tx).await?;
+
+    // WebSocketで一つの通知として送る
+    mutation.publish();
+
+    Ok(Json(BatchResult {
+        change_set_id: change_set.map(|c| c.id),
+        results,
+    }))
+}
+
+async fn apply_operation(
+    mutation: &mut Mutation<'_>,
+    conn: &mut SqliteConnection,
+    operation: BatchOperation,
+) -> Result<BatchOutcome> {
+    let outcome = match operation {
+        BatchOperation::CreateElement { data } => BatchOutcome::CreateElement {
+            element: mutation.create_element(conn, data).await?,
+        },
+        BatchOperation::UpdateElement { id, data } => {
+            let expected_version = data.version;
+            BatchOutcome::UpdateElement {
+                element: mutation
+                    .update_element(conn, &id, data, expected_version)
+                    .await?,
+            }
+        }
+        BatchOperation::DeleteElement { id } => {
+            mutation.delete_element(conn, &id).await?;
+            BatchOutcome::DeleteElement { id }
+        }
+        BatchOperation::CreateRelationship { data } => BatchOutcome::CreateRelationship {
+            relationship: mutation.create_relationship(conn, data).await?,
+        },
+        BatchOperation::UpdateRelationship { id, data } => {
+            let expected_version = data.version;
+            BatchOutcome::UpdateRelationship {
+                relationship: mutation
+                    .update_relationship(conn, &id, data, expected_version)
+                    .await?,
+            }
+        }
+        BatchOperation::DeleteRelationship { id } => {
+            mutation.delete_relationship(conn, &id).await?;
+            BatchOutcome::DeleteRelationship { id }
+        }
+    };
+    Ok(outcome)
+}
+
+// 失敗した操作の位置をエラーメッセージに含める
+fn at_operation(index: usize, error: AppError) -> AppError {
+    let prefix = |message: String| format!("Operation {} failed: {}", index, message);
+    match error {
+        AppError::NotFound(message) => AppError::NotFound(prefix(message)),
+        AppError::InvalidRequest(message) => AppError::InvalidRequest(prefix(message)),
+        AppError::Conflict(message) => AppError::Conflict(prefix(message)),
+        AppError::VersionConflict { message, current } => AppError::VersionConflict {
+            message: prefix(message),
+            current,
+        },
+        AppError::Forbidden(message) => AppError::Forbidden(prefix(message)),
+        error => error,
+    }
+}
@@ -0,0 +1,348 @@
+use std::collections::HashMap;
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use sqlx::SqliteConnection;
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+use crate::{
+    access,
+    auth::AuthUser,
+    db,
+    error::{AppError, Result},
+    hosting,
+    merge::{self, MergeSide},
+    models::{
+        branch::{
+            Branch, BranchInfo, CreateBranch, MergeReport, MergeRequest, BRANCH_STATUS_OPEN,
+        },
+        event::{NewEvent, ENTITY_VIEW},
+        history::{CHANGE_CREATE, CHANGE_DELETE, CHANGE_UPDATE},
+        member::{Permission, ProjectRole},
+        project::CreateProject,
+    },
+    propagation, AppState,
+};
+
+pub async fn list_branches(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(project_id): Path<String>,
+) -> Result<Json<Vec<BranchInfo>>> {
+    access::authorize(&state.db, &project_id, &user, Permission::View).await?;
+
+    let branches = db::list_branches(&state.db, &project_id).await?;
+    Ok(Json(branches))
+}
+
+// プロジェクトをフォークしてブランチを作成する
+// 要素・関係性・ビューは新しいIDで複製し、元のIDとの対応を保持する
+pub async fn create_branch(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(project_id): Path<String>,
+    Json(data): Json<CreateBranch>,
+) -> Result<Json<BranchInfo>> {
+    access::authorize(&state.db, &project_id, &user, Permission::View).await?;
+
+    let user_id = user.id.as_str();
+    db::get_project(&state.db, &project_id).await?;
+
+    let mut tx = state.db.begin().await?;
+    let elements = db::list_elements(&mut *tx, &project_id).await?;
+    let relationships = db::list_relationships(&mut *tx, &project_id).await?;
+    let views = db::list_views(&mut *tx, &project_id).await?;
+
+    let project = db::create_project(
+        &mut *tx,
+        CreateProject {
+            name: data.name,
+            description: data.description,
+        },
+    )
+    .await?;
+    db::upsert_member(&mut tx, &project.id, user_id, ProjectRole::Owner).await?;
+    db::record_project_change(&mut tx, &project.id, CHANGE_CREATE, None, Some(&project), user_id)
+        .await?;
+
+    let mut element_map = HashMap::new();
+    for element in &elements {
+        let mut copy = element.clone();
+        copy.id = Uuid::new_v4().to_string();
+        copy.project_id = project.id.clone();
+        db::insert_element(&mut tx, &copy).await?;
+        db::record_element_change(
+            &mut tx,
+            &project.id,
+            &copy.id,
+            CHANGE_CREATE,
+            None,
+            Some(serde_json::to_value(&copy)?),
+            user_id,
+        )
+        .await?;
+        element_map.insert(element.id.clone(), copy.id);
+    }
+
+    let mut relationship_map = HashMap::new();
+    for relationship in &relationships {
+        let (Some(source_id), Some(target_id)) = (
+            element_map.get(&relationship.source_id),
+            element_map.get(&relationship.target_id),
+        ) else {
+            continue;
+        };
+        let mut copy = relationship.clone();
+        copy.id = Uuid::new_v4().to_string();
+        copy.project_id = project.id.clone();
+        copy.source_id = source_id.clone();
+        copy.target_id = target_id.clone();
+        db::insert_relationship(&mut tx, &copy).await?;
+        db::record_relationship_change(
+            &mut tx,
+            &project.id,
+            &copy.id,
+            CHANGE_CREATE,
+            None,
+            Some(serde_json::to_value(&copy)?),
+            user_id,
+        )
+        .await?;
+        relationship_map.insert(relationship.id.clone(), copy.id);
+    }
+
+    for view in &views {
+        let mut copy = view.clone();
+        copy.id = Uuid::new_v4().to_string();
+        copy.project_id = project.id.clone();
+        let copy = db::restore_view(&mut tx, &copy).await?;
+        let event = NewEvent::new(&project.id, ENTITY_VIEW, &copy.view_type, CHANGE_CREATE, user_id)
+            .with_states(None, Some(serde_json::to_value(&copy)?));
+        db::append_event(&mut tx, event).await?;
+    }
+
+    let branch = Branch {
+        project_id: project.id.clone(),
+        parent_project_id: project_id,
+        base_elements: elements,
+        base_relationships: relationships,
+        element_map,
+        relationship_map,
+        status: BRANCH_STATUS_OPEN.to_string(),
+        created_by: user_id.to_string(),
+        created_at: OffsetDateTime::now_utc(),
+        merged_at: None,
+    };
+    db::create_branch(&mut tx, &branch).await?;
+    tx.commit().await?;
+
+    Ok(Json(BranchInfo {
+        project,
+        parent_project_id: branch.parent_project_id,
+        status: branch.status,
+        created_by: branch.created_by,
+        created_at: branch.created_at,
+        merged_at: branch.merged_at,
+    }))
+}
+
+// ブランチを親プロジェクトへマージする
+// フォーク時点を基準に3-wayで比較し、競合があれば何も変更せず競合一覧を返す
+pub async fn merge_branch(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(project_id): Path<String>,
+    Json(request): Json<MergeRequest>,
+) -> Result<(StatusCode, Json<MergeReport>)> {
+    access::authorize(&state.db, &project_id, &user, Permission::Edit).await?;
+
+    let user_id = user.id.as_str();
+
+    let mut tx = state.db.begin().await?;
+    let branch = db::get_branch(&mut *tx, &project_id).await?;
+    if branch.status != BRANCH_STATUS_OPEN {
+        return Err(AppError::Conflict(format!(
+            "Branch has already been merged: {}",
+            project_id
+        )));
+    }
+    let parent_id = branch.parent_project_id.clone();
+    access::authorize(&state.db, &parent_id, &user, Permission::Edit).await?;
+
+    let ours = MergeSide {
+        elements: db::list_elements(&mut *tx, &parent_id).await?,
+        relationships: db::list_relationships(&mut *tx, &parent_id).await?,
+    };
+    let theirs = MergeSide {
+        elements: db::list_elements(&mut *tx, &project_id).await?,
+        relationships: db::list_relationships(&mut *tx, &project_id).await?,
+    };
+    let mut plan = merge::plan_merge(&branch, &ours, &theirs, &request.resolutions)?;
+
+    // 競合には双方で最後に変更したユーザーを添える
+    for conflict in plan.conflicts.iter_mut().filter(|c| c.base.is_some()) {
+        conflict.ours_modified_by =
+            last_modified_by(&mut tx, &parent_id, &conflict.entity_type, &conflict.id).await?;
+        conflict.theirs_modified_by =
+            last_modified_by(&mut tx, &project_id, &conflict.entity_type, &conflict.branch_id)
+                .await?;
+    }
+
+    let mut report = MergeReport {
+        branch_project_id: project_id.clone(),
+        parent_project_id: parent_id.clone(),
+        ..Default::default()
+    };
+    plan.fill_report(&mut report);
+    report.conflicts = std::mem::take(&mut plan.conflicts);
+
+    if !report.conflicts.is_empty() {
+        return Ok((StatusCode::CONFLICT, Json(report)));
+    }
+    if request.dry_run {
+        return Ok((StatusCode::OK, Json(report)));
+    }
+
+    // 変更の適用（関係性の削除 → 要素の削除 → 要素の作成・更新 → 関係性の作成・更新）
+    let mut events = Vec::new();
+
+    for action in &plan.relationships {
+        if let (Some(relationship), None) = (&action.before, &action.after) {
+            db::delete_relationship(&mut tx, &parent_id, &relationship.id).await?;
+            let event = db::record_relationship_change(
+                &mut tx,
+                &parent_id,
+                &relationship.id,
+                CHANGE_DELETE,
+                Some(serde_json::to_value(relationship)?),
+                None,
+                user_id,
+            )
+            .await?;
+            events.push(event);
+        }
+    }
+
+    for action in &plan.elements {
+        if let (Some(element), None) = (&action.before, &action.after) {
+            for relationship in db::list_element_relationships(&mut *tx, &parent_id, &element.id).await? {
+                let event = db::record_relationship_change(
+                    &mut tx,
+                    &parent_id,
+                    &relationship.id,
+                    CHANGE_DELETE,
+                    Some(serde_json::to_value(&relationship)?),
+                    None,
+                    user_id,
+                )
+                .await?;
+                events.push(event);
+            }
+            db::delete_element(&mut tx, &parent_id, &element.id, user_id).await?;
+            let event = db::record_element_change(
+                &mut tx,
+                &parent_id,
+                &element.id,
+                CHANGE_DELETE,
+                Some(serde_json::to_value(element)?),
+                None,
+                user_id,
+            )
+            .await?;
+            events.push(event);
+        }
+    }
+
+    for action in &plan.elements {
+        let (before, Some(after)) = (&action.before, &action.after) else {
+            continue;
+        };
+        let element = db::restore_element(&mut tx, after, user_id).await?;
+        let event = db::record_element_change(
+            &mut tx,
+            &parent_id,
+            &element.id,
+            if before.is_some() { CHANGE_UPDATE } else { CHANGE_CREATE },
+            before.as_ref().map(serde_json::to_value).transpose()?,
+            Some(serde_json::to_value(&element)?),
+            user_id,
+        )
+        .await?;
+        events.push(event);
+    }
+
+    for action in &plan.relationships {
+        let (before, Some(after)) = (&action.before, &action.after) else {
+            continue;
+        };
+        let relationship = db::restore_relationship(&mut tx, after).await?;
+        let event = db::record_relationship_change(
+            &mut tx,
+            &parent_id,
+            &relationship.id,
+            if before.is_some() { CHANGE_UPDATE } else { CHANGE_CREATE },
+            before.as_ref().map(serde_json::to_value).transpose()?,
+            Some(serde_json::to_value(&relationship)?),
+            user_id,
+        )
+        .await?;
+        events.push(event);
+    }
+
+    // 通常の更新と同じく、開口部を壁の上に保ち、ルールによる値の伝播を適用する
+    for action in &plan.relationships {
+        if let Some(relationship) = &action.after {
+            hosting::check_hosting(&mut tx, relationship).await?;
+        }
+    }
+    for action in &plan.elements {
+        if let (Some(before), Some(after)) = (&action.before, &action.after) {
+            let moved = hosting::reposition_hosted_openings(&mut tx, before, after, user_id).await?;
+            events.extend(moved);
+        }
+    }
+    let rules = propagation::rules_for(&mut tx, &state.rules, &parent_id).await?;
+    for action in &plan.elements {
+        let Some(after) = &action.after else {
+            continue;
+        };
+        let element = db::get_element(&mut *tx, &parent_id, &after.id).await?;
+        hosting::check_opening_on_host(&mut tx, &element).await?;
+        let (_, derived) =
+            propagation::propagate(&mut tx, &rules, action.before.as_ref(), element, user_id)
+                .await?;
+        events.extend(derived);
+    }
+
+    state.ws_manager.ensure_events_unlocked(&parent_id, &events, user_id)?;
+    db::mark_branch_merged(&mut tx, &project_id).await?;
+    db::touch_project(&mut tx, &parent_id).await?;
+    // マージによる変更はまとめて取り消せる
+    if !events.is_empty() {
+        let branch_project = db::get_project(&mut *tx, &project_id).await?;
+        let description = format!("Merge branch {}", branch_project.name);
+        db::create_change_set(&mut tx, &parent_id, &description, user_id, &mut events).await?;
+    }
+    tx.commit().await?;
+    report.applied = true;
+
+    // WebSocketで親プロジェクトに通知
+    state.ws_manager.publish(&parent_id, events);
+
+    Ok((StatusCode::OK, Json(report)))
+}
+
+// 変更履歴から最後に変更したユーザーを取得する
+async fn last_modified_by(
+    conn: &mut SqliteConnection,
+    project_id: &str,
+    entity_type: &str,
+    id: &str,
+) -> Result<Option<String>> {
+    let entry = db::latest_history_entry(conn, project_id, entity_type, id).await?;
+    Ok(entry.map(|h| h.user_id))
+}
@@ -0,0 +1,41 @@
+use axum::{
+    extract::{Path, Query, State},
+    Json,
+};
+use serde::Deserialize;
+
+use crate::{
+    access,
+    auth::AuthUser,
+    clashes,
+    db,
+    error::Result,
+    models::{clash::Clash, member::Permission},
+    AppState,
+};
+
+#[derive(Debug, Deserialize)]
+pub struct ClashQuery {
+    // 指定した要素が関わる干渉のみ
+    element_id: Option<String>,
+}
+
+// 壁どうし・部屋どうしの重なりと、壁の上にない開口部を検出する
+pub async fn detect_clashes(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(project_id): Path<String>,
+    Query(query): Query<ClashQuery>,
+) -> Result<Json<Vec<Clash>>> {
+    access::authorize(&state.db, &project_id, &user, Permission::View).await?;
+
+    let mut conn = state.db.acquire().await?;
+    let clashes = match query.element_id {
+        Some(element_id) => {
+            let element = db::get_element(&mut *conn, &project_id, &element_id).await?;
+            clashes::detect_element_clashes(&mut conn, &element).await?
+        }
+        None => clashes::detect_clashes(&mut conn, &project_id).await?,
+    };
+    Ok(Json(clashes))
+}
@@ -1,93 +1,115 @@
 use axum::{
-    extract::{Path, State},
+    extract::{Path, Query, State},
+    http::{HeaderMap, HeaderValue},
     Json,
 };
-use time::OffsetDateTime;
 
 use crate::{
+    access,
+    auth::AuthUser,
     db,
-    error::Result,
-    models::element::{CreateElement, Element, UpdateElement},
-    websocket::WebSocketMessage,
+    error::{AppError, Result},
+    handlers::{expected_version, with_etag, WithETag},
+    models::{
+        element::{CreateElement, Element, UpdateElement},
+        element_query::ElementQuery,
+        member::Permission,
+    },
+    mutations::Mutation,
     AppState,
 };
 
+// 次のページを取得するためのカーソルを返すヘッダー
+const NEXT_CURSOR_HEADER: &str = "x-next-cursor";
+
+// 絞り込み・並び順・ページはクエリパラメータで指定する（ElementQuery を参照）
+// 続きがある場合は X-Next-Cursor ヘッダーで次のページのカーソルを返す
 pub async fn list_elements(
     State(state): State<AppState>,
+    user: AuthUser,
     Path(project_id): Path<String>,
-) -> Result<Json<Vec<Element>>> {
-    let elements = db::list_elements(&state.db, &project_id).await?;
-    Ok(Json(elements))
+    Query(params): Query<Vec<(String, String)>>,
+) -> Result<(HeaderMap, Json<Vec<Element>>)> {
+    access::authorize(&state.db, &project_id, &user, Permission::View).await?;
+
+    let query = ElementQuery::from_params(params).map_err(AppError::InvalidRequest)?;
+    let (elements, next_cursor) = db::query_elements(&state.db, &project_id, &query).await?;
+
+    let mut headers = HeaderMap::new();
+    if let Some(cursor) = next_cursor {
+        headers.insert(
+            NEXT_CURSOR_HEADER,
+            HeaderValue::from_str(&cursor.encode())
+                .map_err(|e| AppError::Internal(e.to_string()))?,
+        );
+    }
+    Ok((headers, Json(elements)))
 }
 
 pub async fn get_element(
     State(state): State<AppState>,
+    user: AuthUser,
     Path((project_id, element_id)): Path<(String, String)>,
-) -> Result<Json<Element>> {
+) -> Result<WithETag<Element>> {
+    access::authorize(&state.db, &project_id, &user, Permission::View).await?;
+
     let element = db::get_element(&state.db, &project_id, &element_id).await?;
-    Ok(Json(element))
+    Ok(with_etag(element.version, element))
 }
 
 pub async fn create_element(
     State(state): State<AppState>,
+    user: AuthUser,
     Path(project_id): Path<String>,
     Json(data): Json<CreateElement>,
 ) -> Result<Json<Element>> {
-    let element = db::create_element(&state.db, &project_id, data).await?;
+    let mut mutation = Mutation::new(&state, &user, &project_id).await?;
 
-    // WebSocketで通知
-    let msg = WebSocketMessage::ElementUpdate {
-        id: element.id.clone(),
-        project_id: project_id.clone(),
-        data: serde_json::to_value(&element)?,
-        timestamp: OffsetDateTime::now_utc().to_string(),
-        user_id: "system".to_string(), // TODO: 実際のユーザーIDを使用
-    };
+    let mut tx = state.db.begin().await?;
+    let element = mutation.create_element(&mut tx, data).await?;
+    mutation.commit(tx).await?;
 
-    let tx = state.ws_manager.get_or_create_channel(&project_id);
-    let _ = tx.send(msg);
+    // WebSocketで通知
+    mutation.publish();
 
     Ok(Json(element))
 }
 
 pub async fn update_element(
     State(state): State<AppState>,
+    user: AuthUser,
     Path((project_id, element_id)): Path<(String, String)>,
+    headers: HeaderMap,
     Json(data): Json<UpdateElement>,
-) -> Result<Json<Element>> {
-    let element = db::update_element(&state.db, &project_id, &element_id, data).await?;
+) -> Result<WithETag<Element>> {
+    let mut mutation = Mutation::new(&state, &user, &project_id).await?;
+    let expected_version = expected_version(&headers, data.version)?;
 
-    // WebSocketで通知
-    let msg = WebSocketMessage::ElementUpdate {
-        id: element_id.clone(),
-        project_id: project_id.clone(),
-        data: serde_json::to_value(&element)?,
-        timestamp: OffsetDateTime::now_utc().to_string(),
-        user_id: "system".to_string(), // TODO: 実際のユーザーIDを使用
-    };
+    let mut tx = state.db.begin().await?;
+    let element = mutation
+        .update_element(&mut tx, &element_id, data, expected_version)
+        .await?;
+    mutation.commit(tx).await?;
 
-    let tx = state.ws_manager.get_or_create_channel(&project_id);
-    let _ = tx.send(msg);
+    // WebSocketで通知
+    mutation.publish();
 
-    Ok(Json(element))
+    Ok(with_etag(element.version, element))
 }
 
 pub async fn delete_element(
     State(state): State<AppState>,
+    user: AuthUser,
     Path((project_id, element_id)): Path<(String, String)>,
 ) -> Result<()> {
-    db::delete_element(&state.db, &project_id, &element_id).await?;
+    let mut mutation = Mutation::new(&state, &user, &project_id).await?;
 
-    // WebSocketで通知
-    let msg = WebSocketMessage::ElementDelete {
-        id: element_id.clone(),
-        project_id: project_id.clone(),
-        timestamp: OffsetDateTime::now_utc().to_string(),
-        user_id: "system".to_string(), // TODO: 実際のユーザーIDを使用
-    };
+    let mut tx = state.db.begin().await?;
+    mutation.delete_element(&mut tx, &element_id).await?;
+    mutation.commit(tx).await?;
 
-    let tx = state.ws_manager.get_or_create_channel(&project_id);
-    let _ = tx.send(msg);
+    // WebSocketで通知
+    mutation.publish();
 
     Ok(())
-} 
\ No newline at end of file
+}
@@ -1,13 +1,30 @@
 use axum::{
     extract::{Path, Query, State},
+    response::{IntoResponse, Response},
     Json,
 };
 use serde::Deserialize;
+use serde_json::Value as JsonValue;
+use sqlx::SqliteConnection;
+use time::OffsetDateTime;
 
 use crate::{
-    db,
-    error::Result,
-    models::history::History,
+    access,
+    auth::AuthUser,
+    db, diff,
+    error::{AppError, Result},
+    models::{
+        diff::{DiffSide, ProjectDiff},
+        element::Element,
+        event::Event,
+        history::{
+            History, UndoResult, CHANGE_REDO, CHANGE_UNDO, ENTITY_RELATIONSHIP,
+            UNDO_STATUS_APPLIED, UNDO_STATUS_UNDONE,
+        },
+        member::Permission,
+        relationship::Relationship,
+        snapshot::ProjectState,
+    },
     AppState,
 };
 
@@ -15,13 +32,47 @@ use crate::{
 pub struct HistoryQuery {
     element_id: Option<String>,
     limit: Option<i64>,
+    // 変更グループ単位で返す（limit はグループ数）
+    #[serde(default)]
+    grouped: bool,
+}
+
+// 比較元・比較先はそれぞれ時点（RFC 3339）またはスナップショットIDで指定する
+#[derive(Debug, Deserialize)]
+pub struct DiffQuery {
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    from: Option<OffsetDateTime>,
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    to: Option<OffsetDateTime>,
+    from_snapshot: Option<String>,
+    to_snapshot: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SnapshotQuery {
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    at: Option<OffsetDateTime>,
 }
 
 pub async fn get_history(
     State(state): State<AppState>,
+    user: AuthUser,
     Path(project_id): Path<String>,
     Query(query): Query<HistoryQuery>,
-) -> Result<Json<Vec<History>>> {
+) -> Result<Response> {
+    access::authorize(&state.db, &project_id, &user, Permission::View).await?;
+
+    if query.grouped {
+        let groups = db::list_history_groups(
+            &state.db,
+            &project_id,
+            query.element_id.as_deref(),
+            query.limit,
+        )
+        .await?;
+        return Ok(Json(groups).into_response());
+    }
+
     let history = db::list_history(
         &state.db,
         &project_id,
@@ -29,12 +80,310 @@ pub async fn get_history(
         query.limit,
     )
     .await?;
-    Ok(Json(history))
+    Ok(Json(history).into_response())
+}
+
+// 指定時点（省略時は現在）のプロジェクトの状態を変更履歴から復元する
+pub async fn get_snapshot_at(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(project_id): Path<String>,
+    Query(query): Query<SnapshotQuery>,
+) -> Result<Json<ProjectState>> {
+    access::authorize(&state.db, &project_id, &user, Permission::View).await?;
+    db::get_project(&state.db, &project_id).await?;
+
+    let at = query.at.unwrap_or_else(OffsetDateTime::now_utc);
+    let snapshot = db::reconstruct_project_state(&state.db, &project_id, at).await?;
+    Ok(Json(snapshot))
+}
+
+// 2つの時点またはスナップショット間の差分を返す
+pub async fn get_diff(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(project_id): Path<String>,
+    Query(query): Query<DiffQuery>,
+) -> Result<Json<ProjectDiff>> {
+    access::authorize(&state.db, &project_id, &user, Permission::View).await?;
+    db::get_project(&state.db, &project_id).await?;
+
+    if query.from.is_none() && query.from_snapshot.is_none() {
+        return Err(AppError::InvalidRequest(
+            "Either from or from_snapshot is required".to_string(),
+        ));
+    }
+    let (from, from_elements, from_relationships) =
+        resolve_diff_side(&state, &project_id, query.from, query.from_snapshot).await?;
+    let (to, to_elements, to_relationships) =
+        resolve_diff_side(&state, &project_id, query.to, query.to_snapshot).await?;
+
+    let elements = diff::diff_entities(
+        &from_elements,
+        &to_elements,
+        |e| e.id.as_str(),
+        &diff::ELEMENT_DIFF_FIELDS,
+    )?;
+    let relationships = diff::diff_entities(
+        &from_relationships,
+        &to_relationships,
+        |r| r.id.as_str(),
+        &diff::RELATIONSHIP_DIFF_FIELDS,
+    )?;
+
+    Ok(Json(ProjectDiff {
+        project_id,
+        from,
+        to,
+        elements,
+        relationships,
+    }))
+}
+
+// スナップショットIDが指定されていればその内容、なければ指定時点（省略時は現在）の状態
+async fn resolve_diff_side(
+    state: &AppState,
+    project_id: &str,
+    at: Option<OffsetDateTime>,
+    snapshot_id: Option<String>,
+) -> Result<(DiffSide, Vec<Element>, Vec<Relationship>)> {
+    if at.is_some() && snapshot_id.is_some() {
+        return Err(AppError::InvalidRequest(
+            "Specify either a timestamp or a snapshot, not both".to_string(),
+        ));
+    }
+
+    match snapshot_id {
+        Some(snapshot_id) => {
+            let snapshot = db::get_snapshot(&state.db, project_id, &snapshot_id).await?;
+            let side = DiffSide {
+                snapshot_id: Some(snapshot.id),
+                label: Some(snapshot.label),
+                at: snapshot.created_at,
+            };
+            Ok((side, snapshot.elements, snapshot.relationships))
+        }
+        None => {
+            let at = at.unwrap_or_else(OffsetDateTime::now_utc);
+            let project_state = db::reconstruct_project_state(&state.db, project_id, at).await?;
+            let side = DiffSide {
+                snapshot_id: None,
+                label: None,
+                at,
+            };
+            Ok((side, project_state.elements, project_state.relationships))
+        }
+    }
+}
+
+pub async fn undo(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(project_id): Path<String>,
+) -> Result<Json<UndoResult>> {
+    access::authorize(&state.db, &project_id, &user, Permission::Edit).await?;
+    step_history(&state, &project_id, &user.id, true).await.map(Json)
 }
 
-pub async fn clear_history(
+pub async fn redo(
     State(state): State<AppState>,
+    user: AuthUser,
     Path(project_id): Path<String>,
-) -> Result<()> {
-    db::clear_history(&state.db, &project_id).await
-} 
\ No newline at end of file
+) -> Result<Json<UndoResult>> {
+    access::authorize(&state.db, &project_id, &user, Permission::Edit).await?;
+    step_history(&state, &project_id, &user.id, false).await.map(Json)
+}
+
+// 直近の変更を取り消す（undo）か、取り消した変更を再実行する
+// 変更グループに属する変更は、グループ内のすべての変更をまとめて適用する
+async fn step_history(
+    state: &AppState,
+    project_id: &str,
+    user_id: &str,
+    undo: bool,
+) -> Result<UndoResult> {
+    let (status, next_status, change_type) = if undo {
+        (UNDO_STATUS_APPLIED, UNDO_STATUS_UNDONE, CHANGE_UNDO)
+    } else {
+        (UNDO_STATUS_UNDONE, UNDO_STATUS_APPLIED, CHANGE_REDO)
+    };
+
+    let mut tx = state.db.begin().await?;
+    let candidate = if undo {
+        db::find_undo_candidate(&mut tx, project_id, user_id).await?
+    } else {
+        db::find_redo_candidate(&mut tx, project_id, user_id).await?
+    };
+    let mut entry = candidate.ok_or_else(|| {
+        AppError::NotFound(format!("Nothing to {}", change_type))
+    })?;
+
+    let change_set = match &entry.change_set_id {
+        Some(change_set_id) => Some(db::get_change_set(&mut *tx, change_set_id).await?),
+        None => None,
+    };
+    let mut entries = match &change_set {
+        Some(change_set) => db::list_change_set_history(&mut tx, &change_set.id, status).await?,
+        None => vec![entry.clone()],
+    };
+    // 取り消しは新しい変更から、再実行は古い変更から順に適用する
+    if undo {
+        entries.reverse();
+    }
+
+    let mut element = None;
+    let mut events = Vec::with_capacity(entries.len());
+    for history in &mut entries {
+        let (expected, target) = if undo {
+            (history.new_value.as_ref(), history.old_value.as_ref())
+        } else {
+            (history.old_value.as_ref(), history.new_value.as_ref())
+        };
+        let (applied, event) =
+            apply_history_state(&mut tx, history, expected, target, change_type, user_id).await?;
+        db::set_undo_status(&mut tx, &history.id, next_status).await?;
+        history.undo_status = next_status.to_string();
+        if history.id == entry.id {
+            element = applied;
+        }
+        events.push(event);
+    }
+    if undo {
+        entries.reverse();
+    }
+
+    state.ws_manager.ensure_events_unlocked(project_id, &events, user_id)?;
+
+    // undo/redo 自体も一つの変更グループとして記録する
+    let description = match &change_set {
+        Some(change_set) => change_set.description.clone(),
+        None => format!("{} {}", entry.change_type, entry.entity_type),
+    };
+    db::create_change_set(
+        &mut tx,
+        project_id,
+        &format!("{} {}", if undo { "Undo" } else { "Redo" }, description),
+        user_id,
+        &mut events,
+    )
+    .await?;
+    tx.commit().await?;
+
+    entry.undo_status = next_status.to_string();
+    state.ws_manager.publish(project_id, events);
+
+    Ok(UndoResult {
+        entry,
+        element,
+        change_set,
+        entries,
+    })
+}
+
+// 要素・関係性が`expected`の状態であることを確認してから`target`の状態にする
+// 他のユーザーがその後に変更していた場合は上書きせずConflictを返す
+// 要素の場合は適用後の要素を返す
+async fn apply_history_state(
+    conn: &mut SqliteConnection,
+    entry: &History,
+    expected: Option<&JsonValue>,
+    target: Option<&JsonValue>,
+    change_type: &str,
+    user_id: &str,
+) -> Result<(Option<Element>, Event)> {
+    if entry.entity_type == ENTITY_RELATIONSHIP {
+        let event =
+            apply_relationship_state(conn, entry, expected, target, change_type, user_id).await?;
+        return Ok((None, event));
+    }
+
+    let current = match db::get_element(&mut *conn, &entry.project_id, &entry.element_id).await {
+        Ok(element) => Some(element),
+        Err(AppError::NotFound(_)) => None,
+        Err(e) => return Err(e),
+    };
+    let expected: Option<Element> = expected.cloned().map(serde_json::from_value).transpose()?;
+    let target: Option<Element> = target.cloned().map(serde_json::from_value).transpose()?;
+
+    let unchanged = match (&current, &expected) {
+        (Some(current), Some(expected)) => current.same_content(expected),
+        (None, None) => true,
+        _ => false,
+    };
+    if !unchanged {
+        return Err(AppError::Conflict(format!(
+            "Element {} has been modified since change {}",
+            entry.element_id, entry.id
+        )));
+    }
+
+    let result = match &target {
+        Some(target) => Some(db::restore_element(&mut *conn, target, user_id).await?),
+        None => {
+            db::delete_element(&mut *conn, &entry.project_id, &entry.element_id, user_id).await?;
+            None
+        }
+    };
+
+    let event = db::record_element_change(
+        &mut *conn,
+        &entry.project_id,
+        &entry.element_id,
+        change_type,
+        current.as_ref().map(serde_json::to_value).transpose()?,
+        result.as_ref().map(serde_json::to_value).transpose()?,
+        user_id,
+    )
+    .await?;
+
+    Ok((result, event))
+}
+
+async fn apply_relationship_state(
+    conn: &mut SqliteConnection,
+    entry: &History,
+    expected: Option<&JsonValue>,
+    target: Option<&JsonValue>,
+    change_type: &str,
+    user_id: &str,
+) -> Result<Event> {
+    let current = match db::get_relationship(&mut *conn, &entry.project_id, &entry.element_id).await {
+        Ok(relationship) => Some(relationship),
+        Err(AppError::NotFound(_)) => None,
+        Err(e) => return Err(e),
+    };
+    let expected: Option<Relationship> =
+        expected.cloned().map(serde_json::from_value).transpose()?;
+    let target: Option<Relationship> = target.cloned().map(serde_json::from_value).transpose()?;
+
+    let unchanged = match (&current, &expected) {
+        (Some(current), Some(expected)) => current.same_content(expected),
+        (None, None) => true,
+        _ => false,
+    };
+    if !unchanged {
+        return Err(AppError::Conflict(format!(
+            "Relationship {} has been modified since change {}",
+            entry.element_id, entry.id
+        )));
+    }
+
+    let result = match &target {
+        Some(target) => Some(db::restore_relationship(&mut *conn, target).await?),
+        None => {
+            db::delete_relationship(&mut *conn, &entry.project_id, &entry.element_id).await?;
+            None
+        }
+    };
+
+    db::record_relationship_change(
+        &mut *conn,
+        &entry.project_id,
+        &entry.element_id,
+        change_type,
+        current.as_ref().map(serde_json::to_value).transpose()?,
+        result.as_ref().map(serde_json::to_value).transpose()?,
+        user_id,
+    )
+    .await
+}
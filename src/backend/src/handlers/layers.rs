@@ -0,0 +1,64 @@
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+
+use crate::{
+    access,
+    auth::AuthUser,
+    db,
+    error::{AppError, Result},
+    models::{
+        event::{NewEvent, ENTITY_LAYER},
+        history::CHANGE_UPDATE,
+        layer::{Layer, UpdateLayer},
+        member::Permission,
+    },
+    AppState,
+};
+
+pub async fn list_layers(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(project_id): Path<String>,
+) -> Result<Json<Vec<Layer>>> {
+    access::authorize(&state.db, &project_id, &user, Permission::View).await?;
+    let layers = db::list_layers(&state.db, &project_id).await?;
+    Ok(Json(layers))
+}
+
+// レイヤーのロック・表示状態を変更する
+pub async fn update_layer(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path((project_id, name)): Path<(String, String)>,
+    Json(data): Json<UpdateLayer>,
+) -> Result<Json<Layer>> {
+    access::authorize(&state.db, &project_id, &user, Permission::Edit).await?;
+    let user_id = user.id.as_str();
+
+    // 許可ユーザー限定のロックは、許可ユーザー以外は変更できない
+    // 確認から更新までの間に他の変更が入らないよう、同じトランザクション内で読み取る
+    let mut tx = state.db.begin().await?;
+    let current = db::get_layer(&mut *tx, &project_id, &name).await?;
+    if current.blocks(user_id) && !current.allowed_users.is_empty() {
+        return Err(AppError::Conflict(format!(
+            "Layer is locked: {}",
+            name
+        )));
+    }
+
+    let layer = db::update_layer(&mut tx, &project_id, &name, data, user_id).await?;
+    let event = NewEvent::new(&project_id, ENTITY_LAYER, &name, CHANGE_UPDATE, user_id)
+        .with_states(
+            Some(serde_json::to_value(&current)?),
+            Some(serde_json::to_value(&layer)?),
+        );
+    let event = db::append_event(&mut tx, event).await?;
+    tx.commit().await?;
+
+    // WebSocketで通知
+    state.ws_manager.publish(&project_id, vec![event]);
+
+    Ok(Json(layer))
+}
@@ -0,0 +1,108 @@
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+
+use crate::{
+    access,
+    auth::AuthUser,
+    db,
+    error::{AppError, Result},
+    models::member::{AddMember, Member, Permission, ProjectRole, UpdateMember},
+    AppState,
+};
+
+pub async fn list_members(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(project_id): Path<String>,
+) -> Result<Json<Vec<Member>>> {
+    access::authorize(&state.db, &project_id, &user, Permission::View).await?;
+    let members = db::list_members(&state.db, &project_id).await?;
+    Ok(Json(members))
+}
+
+pub async fn add_member(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(project_id): Path<String>,
+    Json(data): Json<AddMember>,
+) -> Result<Json<Member>> {
+    access::authorize(&state.db, &project_id, &user, Permission::Manage).await?;
+
+    let member_id = match (data.user_id, data.username) {
+        (Some(user_id), _) => db::get_user(&state.db, &user_id).await?.id,
+        (None, Some(username)) => {
+            db::find_user_credentials(&state.db, &username)
+                .await?
+                .ok_or_else(|| AppError::NotFound(format!("User not found: {}", username)))?
+                .0
+                .id
+        }
+        (None, None) => {
+            return Err(AppError::InvalidRequest(
+                "Either user_id or username is required".to_string(),
+            ))
+        }
+    };
+
+    let mut tx = state.db.begin().await?;
+    db::upsert_member(&mut tx, &project_id, &member_id, data.role).await?;
+    ensure_owner_remains(&mut tx, &project_id).await?;
+    let member = db::get_member(&mut *tx, &project_id, &member_id)
+        .await?
+        .ok_or_else(|| AppError::Internal("Failed to add member".to_string()))?;
+    tx.commit().await?;
+
+    Ok(Json(member))
+}
+
+pub async fn update_member(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path((project_id, member_id)): Path<(String, String)>,
+    Json(data): Json<UpdateMember>,
+) -> Result<Json<Member>> {
+    access::authorize(&state.db, &project_id, &user, Permission::Manage).await?;
+
+    let mut tx = state.db.begin().await?;
+    db::get_member(&mut *tx, &project_id, &member_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("Member not found: {}", member_id)))?;
+    db::upsert_member(&mut tx, &project_id, &member_id, data.role).await?;
+    ensure_owner_remains(&mut tx, &project_id).await?;
+    let member = db::get_member(&mut *tx, &project_id, &member_id)
+        .await?
+        .ok_or_else(|| AppError::Internal("Failed to update member".to_string()))?;
+    tx.commit().await?;
+
+    Ok(Json(member))
+}
+
+pub async fn remove_member(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path((project_id, member_id)): Path<(String, String)>,
+) -> Result<()> {
+    access::authorize(&state.db, &project_id, &user, Permission::Manage).await?;
+
+    let mut tx = state.db.begin().await?;
+    db::delete_member(&mut tx, &project_id, &member_id).await?;
+    ensure_owner_remains(&mut tx, &project_id).await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+// オーナーが一人もいなくなる変更は受け付けない
+async fn ensure_owner_remains(
+    conn: &mut sqlx::SqliteConnection,
+    project_id: &str,
+) -> Result<()> {
+    if db::count_members(&mut *conn, project_id, Some(ProjectRole::Owner)).await? == 0 {
+        return Err(AppError::Conflict(
+            "A project must keep at least one owner".to_string(),
+        ));
+    }
+    Ok(())
+}
@@ -1,5 +1,58 @@
+pub mod auth;
 pub mod projects;
 pub mod elements;
 pub mod relationships;
 pub mod views;
-pub mod history; 
\ No newline at end of file
+pub mod history;
+pub mod snapshots;
+pub mod branches;
+pub mod rules;
+pub mod members;
+pub mod layers;
+pub mod batch;
+pub mod spatial;
+pub mod clashes;
+
+use axum::{
+    http::{header, HeaderMap, HeaderName},
+    Json,
+};
+
+use crate::error::{AppError, Result};
+
+// ETagヘッダー付きのレスポンス
+pub type WithETag<T> = ([(HeaderName, String); 1], Json<T>);
+
+pub fn with_etag<T>(version: i32, body: T) -> WithETag<T> {
+    ([(header::ETAG, format!("\"{}\"", version))], Json(body))
+}
+
+// If-Matchヘッダーまたはリクエスト本文から、更新時に期待するバージョンを取得する
+pub fn expected_version(headers: &HeaderMap, body_version: Option<i32>) -> Result<Option<i32>> {
+    let header_version = match headers.get(header::IF_MATCH) {
+        Some(value) => {
+            let value = value
+                .to_str()
+                .map_err(|_| AppError::InvalidRequest("Invalid If-Match header".to_string()))?
+                .trim();
+            if value == "*" {
+                None
+            } else {
+                let tag = value.trim_start_matches("W/").trim_matches('"');
+                Some(tag.parse::<i32>().map_err(|_| {
+                    AppError::InvalidRequest(format!("Invalid If-Match header: {}", value))
+                })?)
+            }
+        }
+        None => None,
+    };
+
+    match (header_version, body_version) {
+        (Some(h), Some(b)) if h != b => Err(AppError::InvalidRequest(format!(
+            "If-Match version {} does not match request version {}",
+            h, b
+        ))),
+        (h, b) => Ok(h.or(b)),
+    }
+}
+pub mod presence;
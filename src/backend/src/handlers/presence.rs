@@ -0,0 +1,22 @@
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+
+use crate::{
+    access,
+    auth::AuthUser,
+    error::Result,
+    models::{member::Permission, presence::Presence},
+    AppState,
+};
+
+// プロジェクトに接続中のユーザー一覧
+pub async fn list_presence(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(project_id): Path<String>,
+) -> Result<Json<Vec<Presence>>> {
+    access::authorize(&state.db, &project_id, &user, Permission::View).await?;
+    Ok(Json(state.ws_manager.roster(&project_id)))
+}
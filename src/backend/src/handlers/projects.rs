@@ -1,51 +1,100 @@
 use axum::{
     extract::{Path, State},
+    http::HeaderMap,
     Json,
 };
-use sqlx::SqlitePool;
 
 use crate::{
+    access,
+    auth::AuthUser,
     db,
     error::Result,
-    models::project::{CreateProject, Project, UpdateProject},
+    handlers::{expected_version, with_etag, WithETag},
+    models::{
+        history::{CHANGE_CREATE, CHANGE_DELETE, CHANGE_UPDATE},
+        member::{Permission, ProjectRole},
+        project::{CreateProject, Project, UpdateProject},
+    },
     AppState,
 };
 
 pub async fn list_projects(
     State(state): State<AppState>,
+    user: AuthUser,
 ) -> Result<Json<Vec<Project>>> {
-    let projects = db::list_projects(&state.db).await?;
+    let projects = db::list_projects(&state.db, &user.id).await?;
     Ok(Json(projects))
 }
 
 pub async fn get_project(
     State(state): State<AppState>,
+    user: AuthUser,
     Path(id): Path<String>,
-) -> Result<Json<Project>> {
+) -> Result<WithETag<Project>> {
+    access::authorize(&state.db, &id, &user, Permission::View).await?;
+
     let project = db::get_project(&state.db, &id).await?;
-    Ok(Json(project))
+    Ok(with_etag(project.version, project))
 }
 
 pub async fn create_project(
     State(state): State<AppState>,
+    user: AuthUser,
     Json(data): Json<CreateProject>,
 ) -> Result<Json<Project>> {
-    let project = db::create_project(&state.db, data).await?;
+    // 作成者をオーナーとして登録する
+    let mut tx = state.db.begin().await?;
+    let project = db::create_project(&mut *tx, data).await?;
+    db::upsert_member(&mut tx, &project.id, &user.id, ProjectRole::Owner).await?;
+    db::record_project_change(&mut tx, &project.id, CHANGE_CREATE, None, Some(&project), &user.id)
+        .await?;
+    tx.commit().await?;
     Ok(Json(project))
 }
 
 pub async fn update_project(
     State(state): State<AppState>,
+    user: AuthUser,
     Path(id): Path<String>,
+    headers: HeaderMap,
     Json(data): Json<UpdateProject>,
-) -> Result<Json<Project>> {
-    let project = db::update_project(&state.db, &id, data).await?;
-    Ok(Json(project))
+) -> Result<WithETag<Project>> {
+    access::authorize(&state.db, &id, &user, Permission::Manage).await?;
+
+    let expected_version = expected_version(&headers, data.version)?;
+    let mut tx = state.db.begin().await?;
+    let before = db::get_project(&mut *tx, &id).await?;
+    let project = db::update_project(&mut tx, &id, data, expected_version).await?;
+    let event = db::record_project_change(
+        &mut tx,
+        &id,
+        CHANGE_UPDATE,
+        Some(&before),
+        Some(&project),
+        &user.id,
+    )
+    .await?;
+    tx.commit().await?;
+
+    state.ws_manager.publish(&id, vec![event]);
+    Ok(with_etag(project.version, project))
 }
 
 pub async fn delete_project(
     State(state): State<AppState>,
+    user: AuthUser,
     Path(id): Path<String>,
 ) -> Result<()> {
-    db::delete_project(&state.db, &id).await
-} 
\ No newline at end of file
+    access::authorize(&state.db, &id, &user, Permission::Manage).await?;
+
+    let mut tx = state.db.begin().await?;
+    let before = db::get_project(&mut *tx, &id).await?;
+    db::delete_project(&mut tx, &id).await?;
+    let event =
+        db::record_project_change(&mut tx, &id, CHANGE_DELETE, Some(&before), None, &user.id).await?;
+    tx.commit().await?;
+
+    state.ws_manager.publish(&id, vec![event]);
+    Ok(())
+}
+
@@ -1,85 +1,87 @@
 use axum::{
     extract::{Path, State},
+    http::HeaderMap,
     Json,
 };
-use time::OffsetDateTime;
 
 use crate::{
+    access,
+    auth::AuthUser,
     db,
     error::Result,
-    models::relationship::{CreateRelationship, Relationship, UpdateRelationship},
-    websocket::WebSocketMessage,
+    handlers::{expected_version, with_etag, WithETag},
+    models::{
+        member::Permission,
+        relationship::{CreateRelationship, Relationship, UpdateRelationship},
+    },
+    mutations::Mutation,
     AppState,
 };
 
 pub async fn list_relationships(
     State(state): State<AppState>,
-    Path(element_id): Path<String>,
+    user: AuthUser,
+    Path(project_id): Path<String>,
 ) -> Result<Json<Vec<Relationship>>> {
-    let relationships = db::list_relationships(&state.db, &element_id).await?;
+    access::authorize(&state.db, &project_id, &user, Permission::View).await?;
+
+    let relationships = db::list_relationships(&state.db, &project_id).await?;
     Ok(Json(relationships))
 }
 
 pub async fn create_relationship(
     State(state): State<AppState>,
+    user: AuthUser,
     Path(project_id): Path<String>,
     Json(data): Json<CreateRelationship>,
 ) -> Result<Json<Relationship>> {
-    let relationship = db::create_relationship(&state.db, data).await?;
+    let mut mutation = Mutation::new(&state, &user, &project_id).await?;
 
-    // WebSocketで通知
-    let msg = WebSocketMessage::RelationshipUpdate {
-        id: relationship.id.clone(),
-        project_id: project_id.clone(),
-        data: serde_json::to_value(&relationship)?,
-        timestamp: OffsetDateTime::now_utc().to_string(),
-        user_id: "system".to_string(), // TODO: 実際のユーザーIDを使用
-    };
+    let mut tx = state.db.begin().await?;
+    let relationship = mutation.create_relationship(&mut tx, data).await?;
+    mutation.commit(tx).await?;
 
-    let tx = state.ws_manager.get_or_create_channel(&project_id);
-    let _ = tx.send(msg);
+    // WebSocketで通知
+    mutation.publish();
 
     Ok(Json(relationship))
 }
 
 pub async fn update_relationship(
     State(state): State<AppState>,
+    user: AuthUser,
     Path((project_id, relationship_id)): Path<(String, String)>,
+    headers: HeaderMap,
     Json(data): Json<UpdateRelationship>,
-) -> Result<Json<Relationship>> {
-    let relationship = db::update_relationship(&state.db, &relationship_id, data).await?;
+) -> Result<WithETag<Relationship>> {
+    let mut mutation = Mutation::new(&state, &user, &project_id).await?;
+    let expected_version = expected_version(&headers, data.version)?;
 
-    // WebSocketで通知
-    let msg = WebSocketMessage::RelationshipUpdate {
-        id: relationship_id.clone(),
-        project_id: project_id.clone(),
-        data: serde_json::to_value(&relationship)?,
-        timestamp: OffsetDateTime::now_utc().to_string(),
-        user_id: "system".to_string(), // TODO: 実際のユーザーIDを使用
-    };
+    let mut tx = state.db.begin().await?;
+    let relationship = mutation
+        .update_relationship(&mut tx, &relationship_id, data, expected_version)
+        .await?;
+    mutation.commit(tx).await?;
 
-    let tx = state.ws_manager.get_or_create_channel(&project_id);
-    let _ = tx.send(msg);
+    // WebSocketで通知
+    mutation.publish();
 
-    Ok(Json(relationship))
+    Ok(with_etag(relationship.version, relationship))
 }
 
 pub async fn delete_relationship(
     State(state): State<AppState>,
+    user: AuthUser,
     Path((project_id, relationship_id)): Path<(String, String)>,
 ) -> Result<()> {
-    db::delete_relationship(&state.db, &relationship_id).await?;
+    let mut mutation = Mutation::new(&state, &user, &project_id).await?;
 
-    // WebSocketで通知
-    let msg = WebSocketMessage::RelationshipDelete {
-        id: relationship_id.clone(),
-        project_id: project_id.clone(),
-        timestamp: OffsetDateTime::now_utc().to_string(),
-        user_id: "system".to_string(), // TODO: 実際のユーザーIDを使用
-    };
+    let mut tx = state.db.begin().await?;
+    mutation.delete_relationship(&mut tx, &relationship_id).await?;
+    mutation.commit(tx).await?;
 
-    let tx = state.ws_manager.get_or_create_channel(&project_id);
-    let _ = tx.send(msg);
+    // WebSocketで通知
+    mutation.publish();
 
     Ok(())
-} 
\ No newline at end of file
+}
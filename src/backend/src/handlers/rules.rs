@@ -0,0 +1,99 @@
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+
+use crate::{
+    access,
+    auth::AuthUser,
+    db,
+    error::Result,
+    models::{
+        member::Permission,
+        propagation::{DryRunReport, DryRunRequest, RuleSet, UpdateRuleSet},
+    },
+    propagation,
+    AppState,
+};
+
+pub async fn get_rules(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(project_id): Path<String>,
+) -> Result<Json<RuleSet>> {
+    access::authorize(&state.db, &project_id, &user, Permission::View).await?;
+    db::get_project(&state.db, &project_id).await?;
+
+    let rule_set = match db::get_rule_set(&state.db, &project_id).await? {
+        Some(rule_set) => rule_set,
+        None => RuleSet {
+            project_id,
+            rules: state.rules.to_vec(),
+            custom: false,
+            version: 0,
+            updated_by: None,
+            updated_at: None,
+        },
+    };
+    Ok(Json(rule_set))
+}
+
+pub async fn update_rules(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(project_id): Path<String>,
+    Json(data): Json<UpdateRuleSet>,
+) -> Result<Json<RuleSet>> {
+    access::authorize(&state.db, &project_id, &user, Permission::Edit).await?;
+
+    let user_id = user.id.as_str();
+
+    db::get_project(&state.db, &project_id).await?;
+    propagation::validate_rules(&data.rules)?;
+    let rule_set = db::save_rule_set(&state.db, &project_id, &data.rules, user_id).await?;
+    Ok(Json(rule_set))
+}
+
+// 登録済みのルールを削除して標準ルールに戻す
+pub async fn delete_rules(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(project_id): Path<String>,
+) -> Result<()> {
+    access::authorize(&state.db, &project_id, &user, Permission::Edit).await?;
+    db::delete_rule_set(&state.db, &project_id).await
+}
+
+// ルールを全要素に適用した場合の変更内容を返す（保存はしない）
+pub async fn dry_run(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(project_id): Path<String>,
+    data: Option<Json<DryRunRequest>>,
+) -> Result<Json<DryRunReport>> {
+    access::authorize(&state.db, &project_id, &user, Permission::View).await?;
+    db::get_project(&state.db, &project_id).await?;
+
+    let rules = match data.and_then(|Json(data)| data.rules) {
+        Some(rules) => {
+            propagation::validate_rules(&rules)?;
+            rules
+        }
+        None => {
+            let mut conn = state.db.acquire().await?;
+            propagation::rules_for(&mut conn, &state.rules, &project_id).await?
+        }
+    };
+
+    let elements = db::list_elements(&state.db, &project_id).await?;
+    let mut changes = Vec::new();
+    for element in &elements {
+        let (_, derived) = propagation::evaluate(&rules, None, element)?;
+        changes.extend(derived);
+    }
+
+    Ok(Json(DryRunReport {
+        element_count: elements.len(),
+        changes,
+    }))
+}
@@ -0,0 +1,259 @@
+use std::collections::HashMap;
+
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+use sqlx::SqliteConnection;
+
+use crate::{
+    access,
+    auth::AuthUser,
+    db,
+    error::{AppError, Result},
+    models::{
+        event::{Event, NewEvent, ENTITY_VIEW},
+        history::{CHANGE_CREATE, CHANGE_DELETE, CHANGE_UPDATE},
+        member::Permission,
+        snapshot::{CreateSnapshot, RestoreSummary, Snapshot, SnapshotSummary},
+    },
+    AppState,
+};
+
+pub async fn list_snapshots(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(project_id): Path<String>,
+) -> Result<Json<Vec<SnapshotSummary>>> {
+    access::authorize(&state.db, &project_id, &user, Permission::View).await?;
+
+    let snapshots = db::list_snapshots(&state.db, &project_id).await?;
+    Ok(Json(snapshots))
+}
+
+pub async fn get_snapshot(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path((project_id, snapshot_id)): Path<(String, String)>,
+) -> Result<Json<Snapshot>> {
+    access::authorize(&state.db, &project_id, &user, Permission::View).await?;
+
+    let snapshot = db::get_snapshot(&state.db, &project_id, &snapshot_id).await?;
+    Ok(Json(snapshot))
+}
+
+pub async fn create_snapshot(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(project_id): Path<String>,
+    Json(mut data): Json<CreateSnapshot>,
+) -> Result<Json<Snapshot>> {
+    access::authorize(&state.db, &project_id, &user, Permission::Edit).await?;
+
+    data.label = data.label.trim().to_string();
+    if data.label.is_empty() {
+        return Err(AppError::InvalidRequest(
+            "Snapshot label must not be empty".to_string(),
+        ));
+    }
+
+    let user_id = user.id.as_str();
+    let snapshot = db::create_snapshot(&state.db, &project_id, data, user_id).await?;
+    Ok(Json(snapshot))
+}
+
+pub async fn delete_snapshot(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path((project_id, snapshot_id)): Path<(String, String)>,
+) -> Result<()> {
+    access::authorize(&state.db, &project_id, &user, Permission::Edit).await?;
+    db::delete_snapshot(&state.db, &project_id, &snapshot_id).await
+}
+
+// スナップショットの状態にプロジェクトを戻す
+// 差分のある要素・関係性のみを変更し、それぞれ変更履歴に記録する
+pub async fn restore_snapshot(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path((project_id, snapshot_id)): Path<(String, String)>,
+) -> Result<Json<RestoreSummary>> {
+    access::authorize(&state.db, &project_id, &user, Permission::Edit).await?;
+
+    let user_id = user.id.as_str();
+    let snapshot = db::get_snapshot(&state.db, &project_id, &snapshot_id).await?;
+
+    let mut tx = state.db.begin().await?;
+    let (summary, mut events) = apply_snapshot(&mut tx, &snapshot, user_id).await?;
+    state.ws_manager.ensure_events_unlocked(&project_id, &events, user_id)?;
+    db::touch_project(&mut tx, &project_id).await?;
+    // 復元による変更はまとめて取り消せる
+    if !events.is_empty() {
+        let description = format!("Restore snapshot {}", snapshot.label);
+        db::create_change_set(&mut tx, &project_id, &description, user_id, &mut events).await?;
+    }
+    tx.commit().await?;
+
+    // WebSocketで通知
+    state.ws_manager.publish(&project_id, events);
+
+    Ok(Json(summary))
+}
+
+async fn apply_snapshot(
+    conn: &mut SqliteConnection,
+    snapshot: &Snapshot,
+    user_id: &str,
+) -> Result<(RestoreSummary, Vec<Event>)> {
+    let project_id = snapshot.project_id.as_str();
+    let mut summary = RestoreSummary {
+        snapshot_id: snapshot.id.clone(),
+        ..Default::default()
+    };
+    let mut events = Vec::new();
+
+    let current_elements = db::list_elements(&mut *conn, project_id).await?;
+    let current_relationships = db::list_relationships(&mut *conn, project_id).await?;
+    let current_views = db::list_views(&mut *conn, project_id).await?;
+
+    let snapshot_elements: HashMap<&str, _> =
+        snapshot.elements.iter().map(|e| (e.id.as_str(), e)).collect();
+    let snapshot_relationships: HashMap<&str, _> =
+        snapshot.relationships.iter().map(|r| (r.id.as_str(), r)).collect();
+
+    // スナップショットに存在しない関係性の削除（要素より先に行う）
+    for relationship in current_relationships
+        .iter()
+        .filter(|r| !snapshot_relationships.contains_key(r.id.as_str()))
+    {
+        db::delete_relationship(&mut *conn, project_id, &relationship.id).await?;
+        let event = db::record_relationship_change(
+            &mut *conn,
+            project_id,
+            &relationship.id,
+            CHANGE_DELETE,
+            Some(serde_json::to_value(relationship)?),
+            None,
+            user_id,
+        )
+        .await?;
+        summary.relationships_deleted += 1;
+        events.push(event);
+    }
+
+    // スナップショットに存在しない要素の削除
+    for element in current_elements
+        .iter()
+        .filter(|e| !snapshot_elements.contains_key(e.id.as_str()))
+    {
+        // 連動して削除される関係性は上で処理済み
+        db::delete_element(&mut *conn, project_id, &element.id, user_id).await?;
+        let event = db::record_element_change(
+            &mut *conn,
+            project_id,
+            &element.id,
+            CHANGE_DELETE,
+            Some(serde_json::to_value(element)?),
+            None,
+            user_id,
+        )
+        .await?;
+        summary.elements_deleted += 1;
+        events.push(event);
+    }
+
+    // 要素の作成・更新
+    let current_elements: HashMap<&str, _> =
+        current_elements.iter().map(|e| (e.id.as_str(), e)).collect();
+    for element in &snapshot.elements {
+        let before = current_elements.get(element.id.as_str()).copied();
+        if before.is_some_and(|before| before.same_content(element)) {
+            continue;
+        }
+
+        let restored = db::restore_element(&mut *conn, element, user_id).await?;
+        let change_type = if before.is_some() { CHANGE_UPDATE } else { CHANGE_CREATE };
+        let event = db::record_element_change(
+            &mut *conn,
+            project_id,
+            &element.id,
+            change_type,
+            before.map(serde_json::to_value).transpose()?,
+            Some(serde_json::to_value(&restored)?),
+            user_id,
+        )
+        .await?;
+        if before.is_some() {
+            summary.elements_updated += 1;
+        } else {
+            summary.elements_created += 1;
+        }
+        events.push(event);
+    }
+
+    // 関係性の作成・更新
+    let current_relationships: HashMap<&str, _> =
+        current_relationships.iter().map(|r| (r.id.as_str(), r)).collect();
+    for relationship in &snapshot.relationships {
+        let before = current_relationships.get(relationship.id.as_str()).copied();
+        if before.is_some_and(|before| {
+            before.source_id == relationship.source_id
+                && before.target_id == relationship.target_id
+                && before.relationship_type == relationship.relationship_type
+                && before.properties == relationship.properties
+        }) {
+            continue;
+        }
+
+        let restored = db::restore_relationship(&mut *conn, relationship).await?;
+        let change_type = if before.is_some() { CHANGE_UPDATE } else { CHANGE_CREATE };
+        let event = db::record_relationship_change(
+            &mut *conn,
+            project_id,
+            &relationship.id,
+            change_type,
+            before.map(serde_json::to_value).transpose()?,
+            Some(serde_json::to_value(&restored)?),
+            user_id,
+        )
+        .await?;
+        if before.is_some() {
+            summary.relationships_updated += 1;
+        } else {
+            summary.relationships_created += 1;
+        }
+        events.push(event);
+    }
+
+    // ビューの復元
+    for view in current_views
+        .iter()
+        .filter(|v| !snapshot.views.iter().any(|s| s.id == v.id))
+    {
+        db::delete_view(&mut *conn, project_id, &view.view_type).await?;
+        let event = NewEvent::new(project_id, ENTITY_VIEW, &view.view_type, CHANGE_DELETE, user_id)
+            .with_states(Some(serde_json::to_value(view)?), None);
+        events.push(db::append_event(&mut *conn, event).await?);
+    }
+    for view in &snapshot.views {
+        let unchanged = current_views
+            .iter()
+            .any(|v| v.id == view.id && v.view_type == view.view_type && v.state == view.state);
+        if unchanged {
+            continue;
+        }
+
+        let before = current_views.iter().find(|v| v.view_type == view.view_type);
+        let restored = db::restore_view(&mut *conn, view).await?;
+        let change_type = if before.is_some() { CHANGE_UPDATE } else { CHANGE_CREATE };
+        let event = NewEvent::new(project_id, ENTITY_VIEW, &restored.view_type, change_type, user_id)
+            .with_states(
+                before.map(serde_json::to_value).transpose()?,
+                Some(serde_json::to_value(&restored)?),
+            );
+        summary.views_restored += 1;
+        events.push(db::append_event(&mut *conn, event).await?);
+    }
+
+    Ok((summary, events))
+}
@@ -0,0 +1,120 @@
+use axum::{
+    extract::{Path, Query, State},
+    Json,
+};
+use serde::Deserialize;
+
+use crate::{
+    access,
+    auth::AuthUser,
+    db,
+    error::{AppError, Result},
+    models::{
+        element::{Element, Point},
+        member::Permission,
+        spatial::{contains_point, distance_between, intersects_bounds, Bounds, NearbyElement},
+    },
+    AppState,
+};
+
+#[derive(Debug, Deserialize)]
+pub struct BoundsQuery {
+    min_x: f64,
+    min_y: f64,
+    max_x: f64,
+    max_y: f64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PointQuery {
+    x: f64,
+    y: f64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NearQuery {
+    distance: f64,
+    // 近い順に返す件数（省略時は範囲内すべて）
+    limit: Option<usize>,
+}
+
+// 指定範囲と重なる要素（キャンバスの表示範囲の読み込み用）
+pub async fn elements_in_bounds(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(project_id): Path<String>,
+    Query(query): Query<BoundsQuery>,
+) -> Result<Json<Vec<Element>>> {
+    access::authorize(&state.db, &project_id, &user, Permission::View).await?;
+
+    let bounds = Bounds {
+        min_x: query.min_x,
+        min_y: query.min_y,
+        max_x: query.max_x,
+        max_y: query.max_y,
+    };
+    bounds.validate().map_err(AppError::InvalidRequest)?;
+
+    let candidates = db::list_elements_in_bounds(&state.db, &project_id, &bounds).await?;
+    let elements = candidates
+        .into_iter()
+        .filter(|e| intersects_bounds(&e.geometry, &bounds))
+        .collect();
+    Ok(Json(elements))
+}
+
+// 指定した点を含む要素
+pub async fn elements_at_point(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(project_id): Path<String>,
+    Query(query): Query<PointQuery>,
+) -> Result<Json<Vec<Element>>> {
+    access::authorize(&state.db, &project_id, &user, Permission::View).await?;
+
+    let point = Point { x: query.x, y: query.y };
+    let bounds = Bounds::point(point);
+    bounds.validate().map_err(AppError::InvalidRequest)?;
+
+    let candidates = db::list_elements_in_bounds(&state.db, &project_id, &bounds).await?;
+    let elements = candidates
+        .into_iter()
+        .filter(|e| contains_point(&e.geometry, point))
+        .collect();
+    Ok(Json(elements))
+}
+
+// 要素から指定距離以内にある他の要素を近い順に返す（スナップ用）
+pub async fn elements_near(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path((project_id, element_id)): Path<(String, String)>,
+    Query(query): Query<NearQuery>,
+) -> Result<Json<Vec<NearbyElement>>> {
+    access::authorize(&state.db, &project_id, &user, Permission::View).await?;
+
+    if !query.distance.is_finite() || query.distance < 0.0 {
+        return Err(AppError::InvalidRequest(
+            "distance must be a non-negative number".to_string(),
+        ));
+    }
+
+    let origin = db::get_element(&state.db, &project_id, &element_id).await?;
+    let bounds = Bounds::of(&origin.geometry).expanded(query.distance);
+    let candidates = db::list_elements_in_bounds(&state.db, &project_id, &bounds).await?;
+
+    let mut nearby: Vec<NearbyElement> = candidates
+        .into_iter()
+        .filter(|e| e.id != origin.id)
+        .map(|element| NearbyElement {
+            distance: distance_between(&origin.geometry, &element.geometry),
+            element,
+        })
+        .filter(|n| n.distance <= query.distance)
+        .collect();
+    nearby.sort_by(|a, b| a.distance.total_cmp(&b.distance));
+    if let Some(limit) = query.limit {
+        nearby.truncate(limit);
+    }
+    Ok(Json(nearby))
+}
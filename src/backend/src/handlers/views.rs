@@ -1,51 +1,63 @@
 use axum::{
     extract::{Path, State},
+    http::HeaderMap,
     Json,
 };
-use time::OffsetDateTime;
 
 use crate::{
+    access,
+    auth::AuthUser,
     db,
     error::Result,
-    models::view::{UpdateView, View},
-    websocket::WebSocketMessage,
+    handlers::{expected_version, with_etag, WithETag},
+    models::{
+        member::Permission,
+        view::{UpdateView, View},
+    },
+    mutations::Mutation,
     AppState,
 };
 
 pub async fn list_views(
     State(state): State<AppState>,
+    user: AuthUser,
     Path(project_id): Path<String>,
 ) -> Result<Json<Vec<View>>> {
+    access::authorize(&state.db, &project_id, &user, Permission::View).await?;
+
     let views = db::list_views(&state.db, &project_id).await?;
     Ok(Json(views))
 }
 
 pub async fn get_view(
     State(state): State<AppState>,
+    user: AuthUser,
     Path((project_id, view_type)): Path<(String, String)>,
-) -> Result<Json<View>> {
+) -> Result<WithETag<View>> {
+    access::authorize(&state.db, &project_id, &user, Permission::View).await?;
+
     let view = db::get_view(&state.db, &project_id, &view_type).await?;
-    Ok(Json(view))
+    Ok(with_etag(view.version, view))
 }
 
 pub async fn update_view(
     State(state): State<AppState>,
+    user: AuthUser,
     Path((project_id, view_type)): Path<(String, String)>,
+    headers: HeaderMap,
     Json(data): Json<UpdateView>,
-) -> Result<Json<View>> {
-    let view = db::update_view(&state.db, &project_id, &view_type, data).await?;
+) -> Result<WithETag<View>> {
+    let mut mutation = Mutation::new(&state, &user, &project_id).await?;
+    let expected_version = expected_version(&headers, data.version)?;
+
+    let mut tx = state.db.begin().await?;
+    let view = mutation
+        .update_view(&mut tx, &view_type, data, expected_version)
+        .await?;
+    mutation.commit(tx).await?;
 
     // WebSocketで通知
-    let msg = WebSocketMessage::ViewUpdate {
-        project_id: project_id.clone(),
-        view_type: view_type.clone(),
-        data: serde_json::to_value(&view)?,
-        timestamp: OffsetDateTime::now_utc().to_string(),
-        user_id: "system".to_string(), // TODO: 実際のユーザーIDを使用
-    };
-
-    let tx = state.ws_manager.get_or_create_channel(&project_id);
-    let _ = tx.send(msg);
-
-    Ok(Json(view))
-} 
\ No newline at end of file
+    mutation.publish();
+
+    Ok(with_etag(view.version, view))
+}
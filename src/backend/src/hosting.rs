@@ -0,0 +1,146 @@
+use sqlx::SqliteConnection;
+
+use crate::{
+    db,
+    error::{AppError, Result},
+    models::{
+        element::{
+            point_along_polyline, project_onto_polyline, Element, ELEMENT_TYPE_OPENING,
+            ELEMENT_TYPE_WALL,
+        },
+        event::Event,
+        history::CHANGE_UPDATE,
+        relationship::{Relationship, RELATIONSHIP_TYPE_HOSTS},
+    },
+};
+
+// 壁芯からの距離の許容誤差
+const HOST_TOLERANCE: f64 = 1e-6;
+
+// hosts 関係の両端が壁と開口部であり、開口部が壁の上にあることを確認する
+pub async fn check_hosting(conn: &mut SqliteConnection, relationship: &Relationship) -> Result<()> {
+    if relationship.relationship_type != RELATIONSHIP_TYPE_HOSTS {
+        return Ok(());
+    }
+
+    let wall = db::get_element(&mut *conn, &relationship.project_id, &relationship.source_id).await?;
+    let opening =
+        db::get_element(&mut *conn, &relationship.project_id, &relationship.target_id).await?;
+    if wall.element_type != ELEMENT_TYPE_WALL || opening.element_type != ELEMENT_TYPE_OPENING {
+        return Err(AppError::InvalidRequest(
+            "A hosts relationship must link a wall (source) to an opening (target)".to_string(),
+        ));
+    }
+
+    let hosts = hosting_relationships(conn, &opening).await?;
+    if hosts.iter().any(|r| r.target_id == opening.id && r.id != relationship.id) {
+        return Err(AppError::Conflict(format!(
+            "Opening {} already has a host wall",
+            opening.id
+        )));
+    }
+
+    ensure_on_wall(&wall, &opening)
+}
+
+// 開口部が保持されていれば、その壁の上にあることを確認する
+pub async fn check_opening_on_host(conn: &mut SqliteConnection, opening: &Element) -> Result<()> {
+    let hosts = hosting_relationships(conn, opening).await?;
+    for relationship in hosts.iter().filter(|r| r.target_id == opening.id) {
+        let wall = db::get_element(&mut *conn, &opening.project_id, &relationship.source_id).await?;
+        ensure_on_wall(&wall, opening)?;
+    }
+    Ok(())
+}
+
+// 壁の形状変更に合わせて、保持している開口部を壁芯上の同じ位置へ移動する
+// 開口部の移動を記録したイベントを返す
+pub async fn reposition_hosted_openings(
+    conn: &mut SqliteConnection,
+    before: &Element,
+    wall: &Element,
+    user_id: &str,
+) -> Result<Vec<Event>> {
+    if before.geometry == wall.geometry {
+        return Ok(Vec::new());
+    }
+    let hosts = hosting_relationships(conn, wall).await?;
+    if !hosts.iter().any(|r| r.source_id == wall.id) {
+        return Ok(Vec::new());
+    }
+
+    let (old_axis, _) = before.geometry.wall_axis().ok_or_else(|| {
+        AppError::InvalidRequest(format!("Element {} is not a wall shape", before.id))
+    })?;
+    let (new_axis, _) = wall.geometry.wall_axis().ok_or_else(|| {
+        AppError::InvalidRequest(format!(
+            "Wall {} hosts openings and must keep a wall shape",
+            wall.id
+        ))
+    })?;
+
+    let mut moved = Vec::new();
+    for relationship in hosts.iter().filter(|r| r.source_id == wall.id) {
+        let opening = db::get_element(&mut *conn, &wall.project_id, &relationship.target_id).await?;
+        let Some(anchor) = opening.geometry.anchor() else {
+            continue;
+        };
+        let (_, along) = project_onto_polyline(&old_axis, anchor);
+        let target = point_along_polyline(&new_axis, along);
+        if target == anchor {
+            continue;
+        }
+
+        let mut updated = opening.clone();
+        updated.geometry = opening.geometry.moved_to(target);
+        let updated = db::restore_element(&mut *conn, &updated, user_id).await?;
+        let event = db::record_element_change(
+            &mut *conn,
+            &wall.project_id,
+            &updated.id,
+            CHANGE_UPDATE,
+            Some(serde_json::to_value(&opening)?),
+            Some(serde_json::to_value(&updated)?),
+            user_id,
+        )
+        .await?;
+        moved.push(event);
+    }
+
+    Ok(moved)
+}
+
+async fn hosting_relationships(
+    conn: &mut SqliteConnection,
+    element: &Element,
+) -> Result<Vec<Relationship>> {
+    let relationships =
+        db::list_element_relationships(&mut *conn, &element.project_id, &element.id).await?;
+    Ok(relationships
+        .into_iter()
+        .filter(|r| r.relationship_type == RELATIONSHIP_TYPE_HOSTS)
+        .collect())
+}
+
+fn ensure_on_wall(wall: &Element, opening: &Element) -> Result<()> {
+    let (axis, thickness) = wall.geometry.wall_axis().ok_or_else(|| {
+        AppError::InvalidRequest(format!("Element {} is not a wall shape", wall.id))
+    })?;
+    let anchor = opening.geometry.anchor().ok_or_else(|| {
+        AppError::InvalidRequest(format!("Opening {} has no position", opening.id))
+    })?;
+
+    let (distance, _) = project_onto_polyline(&axis, anchor);
+    if distance > thickness / 2.0 + HOST_TOLERANCE {
+        return Err(AppError::InvalidRequest(format!(
+            "Opening {} does not lie on wall {}",
+            opening.id, wall.id
+        )));
+    }
+    Ok(())
+}
+
+// 開口部の基準点が壁の上にあるか（壁の形状でない場合も false）
+pub fn is_on_wall(wall: &Element, opening: &Element) -> bool {
+    ensure_on_wall(wall, opening).is_ok()
+}
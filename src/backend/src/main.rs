@@ -12,15 +12,35 @@ mod handlers;
 mod db;
 mod error;
 mod websocket;
+mod diff;
+mod hosting;
+mod propagation;
+mod auth;
+mod access;
+mod mutations;
+mod clashes;
+mod merge;
 
 use crate::{
+    auth::TokenSigner,
     handlers::{
+        auth as auth_handlers,
         projects,
         elements,
         relationships,
         views,
         history,
+        snapshots,
+        branches,
+        rules,
+        members,
+        layers,
+        presence,
+        batch,
+        spatial,
+        clashes as clash_handlers,
     },
+    models::propagation::PropagationRule,
     websocket::{handler as ws_handler, ConnectionManager},
 };
 
@@ -28,6 +48,10 @@ use crate::{
 pub struct AppState {
     db: SqlitePool,
     ws_manager: Arc<ConnectionManager>,
+    rules: Arc<Vec<PropagationRule>>,
+    auth: Arc<TokenSigner>,
+    // 要素の更新ごとに干渉を検出して通知する
+    incremental_clashes: bool,
 }
 
 #[tokio::main]
@@ -38,6 +62,9 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // ロガーの初期化
     tracing_subscriber::fmt::init();
 
+    // トークンの署名鍵（未設定なら起動しない）
+    let auth = Arc::new(TokenSigner::from_env()?);
+
     // データベース接続
     let database_url = std::env::var("DATABASE_URL")
         .unwrap_or_else(|_| "sqlite:./data/rddm.db".to_string());
@@ -47,23 +74,48 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // マイグレーションの実行
     sqlx::migrate!("./migrations").run(&pool).await?;
 
+    // 空間インデックスに未登録の要素を登録
+    let indexed = db::index_missing_element_bounds(&pool).await?;
+    if indexed > 0 {
+        tracing::info!("Indexed bounds of {} elements", indexed);
+    }
+
+    // メンバーのいないプロジェクト（認証の導入前に作成したもの）のオーナーを登録
+    assign_memberless_projects(&pool).await?;
+
     // WebSocket接続管理の初期化
     let ws_manager = Arc::new(ConnectionManager::new());
 
+    // 伝播ルールの読み込み
+    let default_rules = Arc::new(propagation::load_rules()?);
+
     // アプリケーション状態の作成
     let state = AppState {
         db: pool,
         ws_manager: ws_manager.clone(),
+        rules: default_rules,
+        auth,
+        incremental_clashes: std::env::var("INCREMENTAL_CLASH_DETECTION")
+            .is_ok_and(|value| value == "1" || value == "true"),
     };
 
     // ルーターの設定
     let app = Router::new()
+        // 認証関連
+        .route("/api/auth/register", post(auth_handlers::register))
+        .route("/api/auth/login", post(auth_handlers::login))
+        .route("/api/auth/me", get(auth_handlers::me))
+        
         // プロジェクト関連
         .route("/api/projects", get(projects::list_projects))
         .route("/api/projects", post(projects::create_project))
         .route("/api/projects/:id", get(projects::get_project))
         .route("/api/projects/:id", put(projects::update_project))
         .route("/api/projects/:id", delete(projects::delete_project))
+        .route("/api/projects/:project_id/members", get(members::list_members))
+        .route("/api/projects/:project_id/members", post(members::add_member))
+        .route("/api/projects/:project_id/members/:user_id", put(members::update_member))
+        .route("/api/projects/:project_id/members/:user_id", delete(members::remove_member))
         
         // 要素関連
         .route("/api/projects/:project_id/elements", get(elements::list_elements))
@@ -71,6 +123,17 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .route("/api/projects/:project_id/elements/:element_id", get(elements::get_element))
         .route("/api/projects/:project_id/elements/:element_id", put(elements::update_element))
         .route("/api/projects/:project_id/elements/:element_id", delete(elements::delete_element))
+        .route("/api/projects/:project_id/batch", post(batch::apply_batch))
+
+        // 空間検索
+        .route("/api/projects/:project_id/spatial/bbox", get(spatial::elements_in_bounds))
+        .route("/api/projects/:project_id/spatial/point", get(spatial::elements_at_point))
+        .route("/api/projects/:project_id/spatial/near/:element_id", get(spatial::elements_near))
+        .route("/api/projects/:project_id/clashes", get(clash_handlers::detect_clashes))
+        
+        // レイヤー関連
+        .route("/api/projects/:project_id/layers", get(layers::list_layers))
+        .route("/api/projects/:project_id/layers/:name", put(layers::update_layer))
         
         // 関係性関連
         .route("/api/projects/:project_id/relationships", get(relationships::list_relationships))
@@ -85,9 +148,32 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         
         // 変更履歴関連
         .route("/api/projects/:project_id/history", get(history::get_history))
+        .route("/api/projects/:project_id/snapshot", get(history::get_snapshot_at))
+        .route("/api/projects/:project_id/diff", get(history::get_diff))
+        .route("/api/projects/:project_id/undo", post(history::undo))
+        .route("/api/projects/:project_id/redo", post(history::redo))
+        
+        // スナップショット関連
+        .route("/api/projects/:project_id/snapshots", get(snapshots::list_snapshots))
+        .route("/api/projects/:project_id/snapshots", post(snapshots::create_snapshot))
+        .route("/api/projects/:project_id/snapshots/:snapshot_id", get(snapshots::get_snapshot))
+        .route("/api/projects/:project_id/snapshots/:snapshot_id", delete(snapshots::delete_snapshot))
+        .route("/api/projects/:project_id/snapshots/:snapshot_id/restore", post(snapshots::restore_snapshot))
+        
+        // ブランチ関連
+        .route("/api/projects/:project_id/branches", get(branches::list_branches))
+        .route("/api/projects/:project_id/branches", post(branches::create_branch))
+        .route("/api/projects/:project_id/merge", post(branches::merge_branch))
+        
+        // 伝播ルール関連
+        .route("/api/projects/:project_id/rules", get(rules::get_rules))
+        .route("/api/projects/:project_id/rules", put(rules::update_rules))
+        .route("/api/projects/:project_id/rules", delete(rules::delete_rules))
+        .route("/api/projects/:project_id/rules/dry-run", post(rules::dry_run))
         
         // WebSocket
         .route("/ws/projects/:project_id", get(ws_handler))
+        .route("/api/projects/:project_id/presence", get(presence::list_presence))
         
         // CORS設定
         .layer(CorsLayer::permissive())
@@ -103,4 +189,34 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     axum::serve(listener, app).await?;
 
     Ok(())
-} 
\ No newline at end of file
+} 
+
+// INITIAL_PROJECT_OWNER に指定したユーザー名のユーザーを、メンバーのいないプロジェクトのオーナーにする
+// 未指定やユーザーが未登録の場合は、該当するプロジェクトが残っていることを警告する
+async fn assign_memberless_projects(pool: &SqlitePool) -> Result<(), Box<dyn std::error::Error>> {
+    let memberless = db::count_memberless_projects(pool).await?;
+    if memberless == 0 {
+        return Ok(());
+    }
+
+    let username = std::env::var("INITIAL_PROJECT_OWNER").unwrap_or_default();
+    if username.is_empty() {
+        tracing::warn!(
+            "{} projects have no members and cannot be accessed; set INITIAL_PROJECT_OWNER to a registered username to assign an owner",
+            memberless
+        );
+        return Ok(());
+    }
+    match db::find_user_credentials(pool, &username).await? {
+        Some((user, _)) => {
+            let assigned = db::assign_memberless_projects(pool, &user.id).await?;
+            tracing::info!("Assigned {} as owner of {} projects", username, assigned);
+        }
+        None => tracing::warn!(
+            "INITIAL_PROJECT_OWNER user {} is not registered; {} projects have no members",
+            username,
+            memberless
+        ),
+    }
+    Ok(())
+}
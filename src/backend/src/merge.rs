@@ -0,0 +1,589 @@
+use std::collections::{HashMap, HashSet};
+
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+use crate::{
+    error::{AppError, Result},
+    models::{
+        branch::{Branch, MergeConflict, MergeReport, MergeResolution},
+        element::Element,
+        history::{ENTITY_ELEMENT, ENTITY_RELATIONSHIP},
+        relationship::Relationship,
+    },
+};
+
+// 3-wayマージで比較する要素のフィールド
+const MERGE_FIELDS: [&str; 4] = ["element_type", "geometry", "properties", "metadata"];
+
+// マージする一方のプロジェクトの要素と関係性
+pub struct MergeSide {
+    pub elements: Vec<Element>,
+    pub relationships: Vec<Relationship>,
+}
+
+// 親プロジェクトへ適用する変更（before: 変更前, after: 変更後。Noneは存在しない）
+pub struct MergeAction<T> {
+    pub before: Option<T>,
+    pub after: Option<T>,
+}
+
+// マージで親プロジェクトへ適用する変更と、解決されていない競合
+pub struct MergePlan {
+    pub elements: Vec<MergeAction<Element>>,
+    pub relationships: Vec<MergeAction<Relationship>>,
+    pub conflicts: Vec<MergeConflict>,
+}
+
+impl MergePlan {
+    // 適用する変更の対象IDを報告に記入する
+    pub fn fill_report(&self, report: &mut MergeReport) {
+        for action in &self.elements {
+            match (&action.before, &action.after) {
+                (None, Some(after)) => report.elements_created.push(after.id.clone()),
+                (Some(_), Some(after)) => report.elements_updated.push(after.id.clone()),
+                (Some(before), None) => report.elements_deleted.push(before.id.clone()),
+                (None, None) => {}
+            }
+        }
+        for action in &self.relationships {
+            match (&action.before, &action.after) {
+                (None, Some(after)) => report.relationships_created.push(after.id.clone()),
+                (Some(_), Some(after)) => report.relationships_updated.push(after.id.clone()),
+                (Some(before), None) => report.relationships_deleted.push(before.id.clone()),
+                (None, None) => {}
+            }
+        }
+    }
+}
+
+// フォーク時点を基準に、親プロジェクト（ours）とブランチ（theirs）の変更を3-wayで比較する
+// 競合は resolutions で解決方法が指定されていなければ conflicts に含める
+pub fn plan_merge(
+    branch: &Branch,
+    ours: &MergeSide,
+    theirs: &MergeSide,
+    resolutions: &HashMap<String, MergeResolution>,
+) -> Result<MergePlan> {
+    let parent_id = &branch.parent_project_id;
+    let ours_elements: HashMap<&str, &Element> =
+        ours.elements.iter().map(|e| (e.id.as_str(), e)).collect();
+    let theirs_elements: HashMap<&str, &Element> =
+        theirs.elements.iter().map(|e| (e.id.as_str(), e)).collect();
+    let ours_relationships: HashMap<&str, &Relationship> =
+        ours.relationships.iter().map(|r| (r.id.as_str(), r)).collect();
+    let theirs_relationships: HashMap<&str, &Relationship> =
+        theirs.relationships.iter().map(|r| (r.id.as_str(), r)).collect();
+
+    let mut conflicts = Vec::new();
+
+    // ブランチ側のID → 親プロジェクト側のID
+    let mut element_ids: HashMap<String, String> = branch
+        .element_map
+        .iter()
+        .map(|(parent, branch)| (branch.clone(), parent.clone()))
+        .collect();
+
+    // 要素の3-way比較
+    let mut element_actions = Vec::new();
+    for base in &branch.base_elements {
+        let Some(branch_id) = branch.element_map.get(&base.id) else {
+            continue;
+        };
+        let ours = ours_elements.get(base.id.as_str()).copied();
+        let theirs = theirs_elements.get(branch_id.as_str()).map(|t| Element {
+            id: base.id.clone(),
+            project_id: parent_id.clone(),
+            ..(*t).clone()
+        });
+
+        let theirs_changed = theirs.as_ref().is_none_or(|t| t.version != base.version);
+        if !theirs_changed {
+            continue;
+        }
+        let ours_changed = ours.is_none_or(|o| o.version != base.version);
+
+        let (target, conflict_fields) = if !ours_changed {
+            (theirs.clone(), Vec::new())
+        } else {
+            match (ours, &theirs) {
+                (None, None) => continue,
+                (Some(ours), Some(theirs)) => {
+                    let (merged, fields) = merge_element_fields(base, ours, theirs)?;
+                    (Some(merged), fields)
+                }
+                _ => (None, vec!["deleted".to_string()]),
+            }
+        };
+
+        let target = if conflict_fields.is_empty() {
+            target
+        } else {
+            match resolutions.get(&base.id) {
+                Some(MergeResolution::Theirs) => theirs.clone(),
+                Some(MergeResolution::Ours) => continue,
+                None => {
+                    conflicts.push(MergeConflict {
+                        entity_type: ENTITY_ELEMENT.to_string(),
+                        id: base.id.clone(),
+                        branch_id: branch_id.clone(),
+                        fields: conflict_fields,
+                        base: Some(serde_json::to_value(base)?),
+                        ours: ours.map(serde_json::to_value).transpose()?,
+                        theirs: theirs.as_ref().map(serde_json::to_value).transpose()?,
+                        ours_modified_by: None,
+                        theirs_modified_by: None,
+                    });
+                    continue;
+                }
+            }
+        };
+        let target = target.map(derive_element).transpose()?;
+
+        let unchanged = match (ours, &target) {
+            (Some(ours), Some(target)) => ours.same_content(target),
+            (None, None) => true,
+            _ => false,
+        };
+        if !unchanged {
+            element_actions.push(MergeAction {
+                before: ours.cloned(),
+                after: target,
+            });
+        }
+    }
+
+    // ブランチで追加された要素
+    for theirs in &theirs.elements {
+        if element_ids.contains_key(&theirs.id) {
+            continue;
+        }
+        let now = OffsetDateTime::now_utc();
+        let created = derive_element(Element {
+            id: Uuid::new_v4().to_string(),
+            project_id: parent_id.clone(),
+            version: 1,
+            created_at: now,
+            updated_at: now,
+            ..theirs.clone()
+        })?;
+        element_ids.insert(theirs.id.clone(), created.id.clone());
+        element_actions.push(MergeAction {
+            before: None,
+            after: Some(created),
+        });
+    }
+
+    // マージ後に親プロジェクトに存在する要素
+    let mut merged_element_ids: HashSet<String> =
+        ours.elements.iter().map(|e| e.id.clone()).collect();
+    for action in &element_actions {
+        match (&action.before, &action.after) {
+            (_, Some(after)) => merged_element_ids.insert(after.id.clone()),
+            (Some(before), None) => merged_element_ids.remove(&before.id),
+            (None, None) => false,
+        };
+    }
+
+    // 関係性の3-way比較
+    let mut relationship_actions = Vec::new();
+    let mut mapped_relationships = HashSet::new();
+    for base in &branch.base_relationships {
+        let Some(branch_id) = branch.relationship_map.get(&base.id) else {
+            continue;
+        };
+        mapped_relationships.insert(branch_id.clone());
+        let ours = ours_relationships.get(base.id.as_str()).copied();
+        let theirs = theirs_relationships
+            .get(branch_id.as_str())
+            .map(|t| remap_relationship(t, &base.id, parent_id, &element_ids));
+
+        let theirs_changed = theirs.as_ref().is_none_or(|t| t.version != base.version);
+        if !theirs_changed {
+            continue;
+        }
+        let ours_changed = ours.is_none_or(|o| o.version != base.version);
+        if ours_changed && ours.is_none() && theirs.is_none() {
+            continue;
+        }
+
+        let target = if !ours_changed {
+            theirs.clone()
+        } else {
+            match resolutions.get(&base.id) {
+                Some(MergeResolution::Theirs) => theirs.clone(),
+                Some(MergeResolution::Ours) => continue,
+                None => {
+                    conflicts.push(MergeConflict {
+                        entity_type: ENTITY_RELATIONSHIP.to_string(),
+                        id: base.id.clone(),
+                        branch_id: branch_id.clone(),
+                        fields: vec!["relationship".to_string()],
+                        base: Some(serde_json::to_value(base)?),
+                        ours: ours.map(serde_json::to_value).transpose()?,
+                        theirs: theirs.as_ref().map(serde_json::to_value).transpose()?,
+                        ours_modified_by: None,
+                        theirs_modified_by: None,
+                    });
+                    continue;
+                }
+            }
+        };
+
+        if ours.is_some() || target.is_some() {
+            relationship_actions.push(MergeAction {
+                before: ours.cloned(),
+                after: target,
+            });
+        }
+    }
+
+    // ブランチで追加された関係性
+    for theirs in &theirs.relationships {
+        if mapped_relationships.contains(&theirs.id) {
+            continue;
+        }
+        let created = remap_relationship(theirs, &Uuid::new_v4().to_string(), parent_id, &element_ids);
+        relationship_actions.push(MergeAction {
+            before: None,
+            after: Some(Relationship {
+                version: 1,
+                created_at: OffsetDateTime::now_utc(),
+                ..created
+            }),
+        });
+    }
+
+    // 接続先の要素がマージ後に存在しない関係性は競合として扱う
+    relationship_actions.retain(|action| {
+        let Some(relationship) = &action.after else {
+            return true;
+        };
+        let connected = merged_element_ids.contains(&relationship.source_id)
+            && merged_element_ids.contains(&relationship.target_id);
+        if !connected {
+            conflicts.push(MergeConflict {
+                entity_type: ENTITY_RELATIONSHIP.to_string(),
+                id: relationship.id.clone(),
+                branch_id: relationship.id.clone(),
+                fields: vec!["missing_endpoint".to_string()],
+                base: None,
+                ours: None,
+                theirs: serde_json::to_value(relationship).ok(),
+                ours_modified_by: None,
+                theirs_modified_by: None,
+            });
+        }
+        connected
+    });
+
+    Ok(MergePlan {
+        elements: element_actions,
+        relationships: relationship_actions,
+        conflicts,
+    })
+}
+
+// 双方で変更された要素をフィールド単位でマージする
+// 同じフィールドが異なる値に変更されていれば競合として返す
+fn merge_element_fields(
+    base: &Element,
+    ours: &Element,
+    theirs: &Element,
+) -> Result<(Element, Vec<String>)> {
+    let base = serde_json::to_value(base)?;
+    let theirs = serde_json::to_value(theirs)?;
+    let mut merged = serde_json::to_value(ours)?;
+    let mut conflicts = Vec::new();
+
+    for field in MERGE_FIELDS {
+        let (b, o, t) = (&base[field], &merged[field], &theirs[field]);
+        if t == b || t == o {
+            continue;
+        }
+        if o == b {
+            merged[field] = t.clone();
+        } else {
+            conflicts.push(field.to_string());
+        }
+    }
+
+    Ok((serde_json::from_value(merged)?, conflicts))
+}
+
+// マージ後の要素を通常の更新と同じく検証し、形状から求める値を計算し直す
+// フィールド単位のマージで、形状と面積などが別々の側から採られても食い違わないようにする
+fn derive_element(mut element: Element) -> Result<Element> {
+    element.geometry.validate().map_err(AppError::InvalidRequest)?;
+    element
+        .apply_room_measurements(false)
+        .map_err(AppError::InvalidRequest)?;
+    Ok(element)
+}
+
+// ブランチ側の関係性を親プロジェクトのIDに置き換える
+fn remap_relationship(
+    relationship: &Relationship,
+    id: &str,
+    project_id: &str,
+    element_ids: &HashMap<String, String>,
+) -> Relationship {
+    let map = |branch_id: &str| {
+        element_ids
+            .get(branch_id)
+            .cloned()
+            .unwrap_or_else(|| branch_id.to_string())
+    };
+    Relationship {
+        id: id.to_string(),
+        project_id: project_id.to_string(),
+        source_id: map(&relationship.source_id),
+        target_id: map(&relationship.target_id),
+        ..relationship.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{
+        branch::BRANCH_STATUS_OPEN,
+        element::{Geometry, Point, ELEMENT_TYPE_ROOM, ELEMENT_TYPE_WALL},
+        relationship::RELATIONSHIP_TYPE_HOSTS,
+    };
+    use serde_json::json;
+
+    fn wall(id: &str, project_id: &str, x: f64) -> Element {
+        Element {
+            id: id.to_string(),
+            project_id: project_id.to_string(),
+            element_type: ELEMENT_TYPE_WALL.to_string(),
+            geometry: Geometry::Polyline {
+                points: vec![Point { x, y: 0.0 }, Point { x, y: 4.0 }],
+                thickness: 0.2,
+            },
+            properties: json!({}),
+            metadata: json!({}),
+            version: 1,
+            created_at: OffsetDateTime::UNIX_EPOCH,
+            updated_at: OffsetDateTime::UNIX_EPOCH,
+        }
+    }
+
+    fn relationship(id: &str, project_id: &str, source_id: &str, target_id: &str) -> Relationship {
+        Relationship {
+            id: id.to_string(),
+            project_id: project_id.to_string(),
+            source_id: source_id.to_string(),
+            target_id: target_id.to_string(),
+            relationship_type: RELATIONSHIP_TYPE_HOSTS.to_string(),
+            properties: None,
+            version: 1,
+            created_at: OffsetDateTime::UNIX_EPOCH,
+        }
+    }
+
+    // 親プロジェクト側の要素をそのままフォークしたブランチ（ブランチ側のIDは b + 親側のID）
+    fn fork(elements: &[Element], relationships: &[Relationship]) -> (Branch, MergeSide, MergeSide) {
+        let branch = Branch {
+            project_id: "branch".to_string(),
+            parent_project_id: "parent".to_string(),
+            base_elements: elements.to_vec(),
+            base_relationships: relationships.to_vec(),
+            element_map: elements.iter().map(|e| (e.id.clone(), format!("b{}", e.id))).collect(),
+            relationship_map: relationships
+                .iter()
+                .map(|r| (r.id.clone(), format!("b{}", r.id)))
+                .collect(),
+            status: BRANCH_STATUS_OPEN.to_string(),
+            created_by: "u1".to_string(),
+            created_at: OffsetDateTime::UNIX_EPOCH,
+            merged_at: None,
+        };
+        let ours = MergeSide {
+            elements: elements.to_vec(),
+            relationships: relationships.to_vec(),
+        };
+        let theirs = MergeSide {
+            elements: elements
+                .iter()
+                .map(|e| Element {
+                    id: format!("b{}", e.id),
+                    project_id: "branch".to_string(),
+                    ..e.clone()
+                })
+                .collect(),
+            relationships: relationships
+                .iter()
+                .map(|r| Relationship {
+                    id: format!("b{}", r.id),
+                    project_id: "branch".to_string(),
+                    source_id: format!("b{}", r.source_id),
+                    target_id: format!("b{}", r.target_id),
+                    ..r.clone()
+                })
+                .collect(),
+        };
+        (branch, ours, theirs)
+    }
+
+    fn modify(element: &mut Element, change: impl FnOnce(&mut Element)) {
+        change(element);
+        element.version += 1;
+    }
+
+    fn move_wall(element: &mut Element, x: f64) {
+        element.geometry = wall(&element.id, &element.project_id, x).geometry;
+    }
+
+    #[test]
+    fn applies_branch_changes_and_keeps_parent_changes() {
+        let (branch, mut ours, mut theirs) =
+            fork(&[wall("w1", "parent", 0.0), wall("w2", "parent", 5.0)], &[]);
+        modify(&mut ours.elements[0], |e| move_wall(e, 1.0));
+        modify(&mut theirs.elements[1], |e| move_wall(e, 6.0));
+
+        let plan = plan_merge(&branch, &ours, &theirs, &HashMap::new()).unwrap();
+        assert!(plan.conflicts.is_empty());
+        assert_eq!(plan.elements.len(), 1);
+        let after = plan.elements[0].after.as_ref().unwrap();
+        assert_eq!(after.id, "w2");
+        assert_eq!(after.project_id, "parent");
+        assert_eq!(after.geometry, wall("w2", "parent", 6.0).geometry);
+    }
+
+    #[test]
+    fn merges_different_fields_of_one_element() {
+        let (branch, mut ours, mut theirs) = fork(&[wall("w1", "parent", 0.0)], &[]);
+        modify(&mut ours.elements[0], |e| e.metadata = json!({ "note": "parent" }));
+        modify(&mut theirs.elements[0], |e| move_wall(e, 2.0));
+
+        let plan = plan_merge(&branch, &ours, &theirs, &HashMap::new()).unwrap();
+        assert!(plan.conflicts.is_empty());
+        let after = plan.elements[0].after.as_ref().unwrap();
+        assert_eq!(after.metadata, json!({ "note": "parent" }));
+        assert_eq!(after.geometry, wall("w1", "parent", 2.0).geometry);
+    }
+
+    #[test]
+    fn reports_and_resolves_conflicting_changes() {
+        let (branch, mut ours, mut theirs) = fork(&[wall("w1", "parent", 0.0)], &[]);
+        modify(&mut ours.elements[0], |e| move_wall(e, 1.0));
+        modify(&mut theirs.elements[0], |e| move_wall(e, 2.0));
+
+        let plan = plan_merge(&branch, &ours, &theirs, &HashMap::new()).unwrap();
+        assert!(plan.elements.is_empty());
+        assert_eq!(plan.conflicts.len(), 1);
+        assert_eq!(plan.conflicts[0].id, "w1");
+        assert_eq!(plan.conflicts[0].branch_id, "bw1");
+        assert_eq!(plan.conflicts[0].fields, vec!["geometry"]);
+
+        let resolutions = HashMap::from([("w1".to_string(), MergeResolution::Theirs)]);
+        let plan = plan_merge(&branch, &ours, &theirs, &resolutions).unwrap();
+        assert!(plan.conflicts.is_empty());
+        let after = plan.elements[0].after.as_ref().unwrap();
+        assert_eq!(after.geometry, wall("w1", "parent", 2.0).geometry);
+
+        let resolutions = HashMap::from([("w1".to_string(), MergeResolution::Ours)]);
+        let plan = plan_merge(&branch, &ours, &theirs, &resolutions).unwrap();
+        assert!(plan.conflicts.is_empty());
+        assert!(plan.elements.is_empty());
+    }
+
+    #[test]
+    fn reports_deleting_a_modified_element() {
+        let (branch, mut ours, mut theirs) = fork(&[wall("w1", "parent", 0.0)], &[]);
+        modify(&mut ours.elements[0], |e| move_wall(e, 1.0));
+        theirs.elements.clear();
+
+        let plan = plan_merge(&branch, &ours, &theirs, &HashMap::new()).unwrap();
+        assert_eq!(plan.conflicts.len(), 1);
+        assert_eq!(plan.conflicts[0].fields, vec!["deleted"]);
+        assert!(plan.conflicts[0].theirs.is_none());
+
+        // ブランチ側の削除を採用する
+        let resolutions = HashMap::from([("w1".to_string(), MergeResolution::Theirs)]);
+        let plan = plan_merge(&branch, &ours, &theirs, &resolutions).unwrap();
+        assert_eq!(plan.elements[0].before.as_ref().unwrap().id, "w1");
+        assert!(plan.elements[0].after.is_none());
+    }
+
+    #[test]
+    fn maps_branch_additions_to_parent_ids() {
+        let (branch, ours, mut theirs) = fork(&[wall("w1", "parent", 0.0)], &[]);
+        theirs.elements.push(wall("bnew", "branch", 3.0));
+        theirs.relationships.push(relationship("brel", "branch", "bw1", "bnew"));
+
+        let plan = plan_merge(&branch, &ours, &theirs, &HashMap::new()).unwrap();
+        assert!(plan.conflicts.is_empty());
+        let created = plan.elements[0].after.as_ref().unwrap();
+        assert_ne!(created.id, "bnew");
+        assert_eq!(created.project_id, "parent");
+
+        let relationship = plan.relationships[0].after.as_ref().unwrap();
+        assert_ne!(relationship.id, "brel");
+        assert_eq!(relationship.project_id, "parent");
+        assert_eq!(relationship.source_id, "w1");
+        assert_eq!(relationship.target_id, created.id);
+
+        let mut report = MergeReport::default();
+        plan.fill_report(&mut report);
+        assert_eq!(report.elements_created, vec![created.id.clone()]);
+        assert_eq!(report.relationships_created, vec![relationship.id.clone()]);
+    }
+
+    #[test]
+    fn reports_relationships_to_deleted_elements() {
+        let (branch, mut ours, mut theirs) =
+            fork(&[wall("w1", "parent", 0.0), wall("w2", "parent", 5.0)], &[]);
+        ours.elements.remove(0);
+        theirs.relationships.push(relationship("brel", "branch", "bw1", "bw2"));
+
+        let plan = plan_merge(&branch, &ours, &theirs, &HashMap::new()).unwrap();
+        assert!(plan.relationships.is_empty());
+        assert_eq!(plan.conflicts.len(), 1);
+        assert_eq!(plan.conflicts[0].fields, vec!["missing_endpoint"]);
+    }
+
+    #[test]
+    fn recomputes_room_measurements_of_merged_elements() {
+        let square = |size: f64| Geometry::Polygon {
+            points: vec![
+                Point { x: 0.0, y: 0.0 },
+                Point { x: size, y: 0.0 },
+                Point { x: size, y: size },
+                Point { x: 0.0, y: size },
+            ],
+        };
+        let room = Element {
+            element_type: ELEMENT_TYPE_ROOM.to_string(),
+            geometry: square(2.0),
+            properties: json!({
+                "common": { "name": "Room", "layer": "0", "visible": true, "locked": false },
+                "floorPlan": { "roomType": "office", "area": 4.0 },
+            }),
+            ..wall("r1", "parent", 0.0)
+        };
+        let (branch, mut ours, mut theirs) = fork(&[room], &[]);
+        modify(&mut ours.elements[0], |e| e.metadata = json!({ "note": "parent" }));
+        modify(&mut theirs.elements[0], |e| e.geometry = square(3.0));
+
+        // 面積は形状を変更した側の値ではなく、マージ後の形状から計算し直す
+        let plan = plan_merge(&branch, &ours, &theirs, &HashMap::new()).unwrap();
+        let after = plan.elements[0].after.as_ref().unwrap();
+        assert_eq!(after.properties["floorPlan"]["area"], json!(9.0));
+        assert_eq!(after.properties["floorPlan"]["perimeter"], json!(12.0));
+        assert_eq!(after.properties["floorPlan"]["roomType"], json!("office"));
+    }
+
+    #[test]
+    fn rejects_invalid_merged_geometry() {
+        let (branch, ours, mut theirs) = fork(&[wall("w1", "parent", 0.0)], &[]);
+        modify(&mut theirs.elements[0], |e| {
+            e.geometry = Geometry::Polyline { points: vec![Point { x: 0.0, y: 0.0 }], thickness: 0.2 }
+        });
+
+        let result = plan_merge(&branch, &ours, &theirs, &HashMap::new());
+        assert!(matches!(result, Err(AppError::InvalidRequest(_))));
+    }
+}
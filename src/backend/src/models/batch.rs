@@ -0,0 +1,47 @@
+use serde::{Deserialize, Serialize};
+
+use super::{
+    element::{CreateElement, Element, UpdateElement},
+    relationship::{CreateRelationship, Relationship, UpdateRelationship},
+};
+
+// 一度に受け付ける操作数の上限
+pub const MAX_BATCH_OPERATIONS: usize = 1000;
+
+// 一括操作の個々の操作（更新は data.version で期待するバージョンを指定できる）
+#[derive(Debug, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum BatchOperation {
+    CreateElement { data: CreateElement },
+    UpdateElement { id: String, data: UpdateElement },
+    DeleteElement { id: String },
+    CreateRelationship { data: CreateRelationship },
+    UpdateRelationship { id: String, data: UpdateRelationship },
+    DeleteRelationship { id: String },
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BatchRequest {
+    // 履歴に表示する操作の説明（例: "Move unit 301"）
+    pub description: Option<String>,
+    pub operations: Vec<BatchOperation>,
+}
+
+// 操作ごとの結果（リクエストと同じ順序）
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum BatchOutcome {
+    CreateElement { element: Element },
+    UpdateElement { element: Element },
+    DeleteElement { id: String },
+    CreateRelationship { relationship: Relationship },
+    UpdateRelationship { relationship: Relationship },
+    DeleteRelationship { id: String },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BatchResult {
+    // 履歴上でこの一括操作の変更をまとめるID（変更がなかった場合は None）
+    pub change_set_id: Option<String>,
+    pub results: Vec<BatchOutcome>,
+}
@@ -0,0 +1,88 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use time::OffsetDateTime;
+
+use super::{element::Element, project::Project, relationship::Relationship};
+
+// ブランチの状態
+pub const BRANCH_STATUS_OPEN: &str = "open";
+pub const BRANCH_STATUS_MERGED: &str = "merged";
+
+// フォーク時点の情報を含むブランチ
+#[derive(Debug, Clone)]
+pub struct Branch {
+    pub project_id: String,
+    pub parent_project_id: String,
+    pub base_elements: Vec<Element>,
+    pub base_relationships: Vec<Relationship>,
+    pub element_map: HashMap<String, String>,
+    pub relationship_map: HashMap<String, String>,
+    pub status: String,
+    pub created_by: String,
+    pub created_at: OffsetDateTime,
+    pub merged_at: Option<OffsetDateTime>,
+}
+
+// API応答用のブランチ情報
+#[derive(Debug, Clone, Serialize)]
+pub struct BranchInfo {
+    pub project: Project,
+    pub parent_project_id: String,
+    pub status: String,
+    pub created_by: String,
+    pub created_at: OffsetDateTime,
+    pub merged_at: Option<OffsetDateTime>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateBranch {
+    pub name: String,
+    pub description: Option<String>,
+}
+
+// 競合の解決方法（ours: 親プロジェクト側, theirs: ブランチ側）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MergeResolution {
+    Ours,
+    Theirs,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct MergeRequest {
+    // 親プロジェクト側のIDごとの解決方法
+    #[serde(default)]
+    pub resolutions: HashMap<String, MergeResolution>,
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+// 同じ対象への競合する変更
+#[derive(Debug, Clone, Serialize)]
+pub struct MergeConflict {
+    pub entity_type: String,
+    pub id: String,
+    pub branch_id: String,
+    pub fields: Vec<String>,
+    pub base: Option<JsonValue>,
+    pub ours: Option<JsonValue>,
+    pub theirs: Option<JsonValue>,
+    pub ours_modified_by: Option<String>,
+    pub theirs_modified_by: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct MergeReport {
+    pub branch_project_id: String,
+    pub parent_project_id: String,
+    pub applied: bool,
+    pub elements_created: Vec<String>,
+    pub elements_updated: Vec<String>,
+    pub elements_deleted: Vec<String>,
+    pub relationships_created: Vec<String>,
+    pub relationships_updated: Vec<String>,
+    pub relationships_deleted: Vec<String>,
+    pub conflicts: Vec<MergeConflict>,
+}
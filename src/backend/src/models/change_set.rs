@@ -0,0 +1,28 @@
+use serde::Serialize;
+use time::OffsetDateTime;
+
+use super::history::History;
+
+// ユーザーの一つの操作でまとめて記録した変更のグループ
+#[derive(Debug, Clone, Serialize)]
+pub struct ChangeSet {
+    pub id: String,
+    pub project_id: String,
+    pub description: String,
+    pub actor: String,
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_at: OffsetDateTime,
+}
+
+// 変更グループ単位の履歴（グループのない変更は単独のグループとして返す）
+#[derive(Debug, Clone, Serialize)]
+pub struct HistoryGroup {
+    pub change_set_id: Option<String>,
+    pub description: String,
+    pub actor: String,
+    #[serde(with = "time::serde::rfc3339")]
+    pub timestamp: OffsetDateTime,
+    pub undo_status: String,
+    // 記録順
+    pub entries: Vec<History>,
+}
@@ -0,0 +1,25 @@
+use serde::{Deserialize, Serialize};
+
+use super::element::Point;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ClashKind {
+    // 壁どうしの重なり（端部の接合は除く）
+    WallOverlap,
+    // 部屋どうしの重なり
+    RoomOverlap,
+    // 開口部が保持している壁の上にない
+    OpeningOutsideHost,
+}
+
+// 干渉している要素の組
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Clash {
+    pub kind: ClashKind,
+    // 開口部の位置ずれでは [開口部, 壁] の順
+    pub element_ids: [String; 2],
+    // 重なっている領域（凸多角形の集まり。開口部の位置ずれでは空）
+    pub overlap: Vec<Vec<Point>>,
+    pub overlap_area: f64,
+}
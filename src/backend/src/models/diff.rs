@@ -0,0 +1,42 @@
+use serde::Serialize;
+use serde_json::Value as JsonValue;
+use time::OffsetDateTime;
+
+// 比較対象（履歴上の時点またはスナップショット）
+#[derive(Debug, Clone, Serialize)]
+pub struct DiffSide {
+    pub snapshot_id: Option<String>,
+    pub label: Option<String>,
+    #[serde(with = "time::serde::rfc3339")]
+    pub at: OffsetDateTime,
+}
+
+// JSONパス単位の変更
+#[derive(Debug, Clone, Serialize)]
+pub struct FieldChange {
+    pub path: String,
+    pub old: Option<JsonValue>,
+    pub new: Option<JsonValue>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ModifiedEntry {
+    pub id: String,
+    pub changes: Vec<FieldChange>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct EntityDiff {
+    pub added: Vec<JsonValue>,
+    pub removed: Vec<JsonValue>,
+    pub modified: Vec<ModifiedEntry>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ProjectDiff {
+    pub project_id: String,
+    pub from: DiffSide,
+    pub to: DiffSide,
+    pub elements: EntityDiff,
+    pub relationships: EntityDiff,
+}
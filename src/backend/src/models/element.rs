@@ -1,16 +1,266 @@
 use serde::{Deserialize, Serialize};
-use sqlx::FromRow;
 use time::OffsetDateTime;
 use uuid::Uuid;
 use serde_json::Value as JsonValue;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
-pub struct Geometry {
+pub const ELEMENT_TYPE_ROOM: &str = "room";
+pub const ELEMENT_TYPE_WALL: &str = "wall";
+pub const ELEMENT_TYPE_OPENING: &str = "opening";
+
+// クライアント指定の面積と計算値の許容誤差（相対）
+const AREA_TOLERANCE: f64 = 1e-3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Point {
     pub x: f64,
     pub y: f64,
-    pub width: f64,
-    pub height: f64,
+}
+
+// 要素の形状（"kind" で種類を区別する）
+// 旧形式の {x, y, width, height} やフロントエンドの {position, size, rotation} も受け付ける
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(
+    remote = "Self",
+    tag = "kind",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+)]
+pub enum Geometry {
+    // 軸に平行な矩形
+    Rect {
+        x: f64,
+        y: f64,
+        width: f64,
+        height: f64,
+    },
+    // 中心まわりに回転した矩形（角度は度）
+    RotatedRect {
+        x: f64,
+        y: f64,
+        width: f64,
+        height: f64,
+        rotation: f64,
+    },
+    // 壁芯の折れ線と壁厚
+    Polyline {
+        points: Vec<Point>,
+        thickness: f64,
+    },
+    // 多角形（部屋など）
+    Polygon {
+        points: Vec<Point>,
+    },
+    // 点（開口部など。保持する壁は hosts 関係で表す）
+    Point {
+        x: f64,
+        y: f64,
+        width: Option<f64>,
+    },
+}
+
+#[derive(Deserialize)]
+struct LegacyRect {
+    x: f64,
+    y: f64,
+    width: f64,
+    height: f64,
+    #[serde(default)]
+    rotation: f64,
+}
+
+#[derive(Deserialize)]
+struct FrontendSize {
+    width: f64,
+    height: f64,
+}
+
+#[derive(Deserialize)]
+struct FrontendRect {
+    position: Point,
+    size: FrontendSize,
+    #[serde(default)]
+    rotation: f64,
+}
+
+impl Serialize for Geometry {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        Geometry::serialize(self, serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Geometry {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error;
+
+        let value = JsonValue::deserialize(deserializer)?;
+        if value.get("kind").is_some() {
+            return Geometry::deserialize(value).map_err(D::Error::custom);
+        }
+        if value.get("position").is_some() {
+            let rect = FrontendRect::deserialize(value).map_err(D::Error::custom)?;
+            return Ok(Geometry::from_rect(
+                rect.position.x,
+                rect.position.y,
+                rect.size.width,
+                rect.size.height,
+                rect.rotation,
+            ));
+        }
+        let rect = LegacyRect::deserialize(value).map_err(D::Error::custom)?;
+        Ok(Geometry::from_rect(rect.x, rect.y, rect.width, rect.height, rect.rotation))
+    }
+}
+
+impl Geometry {
+    fn from_rect(x: f64, y: f64, width: f64, height: f64, rotation: f64) -> Self {
+        if rotation == 0.0 {
+            Geometry::Rect { x, y, width, height }
+        } else {
+            Geometry::RotatedRect { x, y, width, height, rotation }
+        }
+    }
+
+    // 閉じた形状の頂点列（矩形・回転矩形・多角形のみ）
+    pub fn outline(&self) -> Option<Vec<Point>> {
+        match self {
+            Geometry::Rect { x, y, width, height } => Some(vec![
+                Point { x: *x, y: *y },
+                Point { x: x + width, y: *y },
+                Point { x: x + width, y: y + height },
+                Point { x: *x, y: y + height },
+            ]),
+            Geometry::RotatedRect { x, y, width, height, rotation } => {
+                let (cx, cy) = (x + width / 2.0, y + height / 2.0);
+                let (sin, cos) = rotation.to_radians().sin_cos();
+                let corners = [(*x, *y), (x + width, *y), (x + width, y + height), (*x, y + height)];
+                Some(
+                    corners
+                        .iter()
+                        .map(|&(px, py)| {
+                            let (dx, dy) = (px - cx, py - cy);
+                            Point {
+                                x: cx + dx * cos - dy * sin,
+                                y: cy + dx * sin + dy * cos,
+                            }
+                        })
+                        .collect(),
+                )
+            }
+            Geometry::Polygon { points } => Some(points.clone()),
+            Geometry::Polyline { .. } | Geometry::Point { .. } => None,
+        }
+    }
+
+    // 壁の芯線と壁厚（折れ線、または矩形の長辺方向の中心線）
+    pub fn wall_axis(&self) -> Option<(Vec<Point>, f64)> {
+        match self {
+            Geometry::Polyline { points, thickness } => Some((points.clone(), *thickness)),
+            Geometry::Rect { width, height, .. } | Geometry::RotatedRect { width, height, .. } => {
+                let corners = self.outline()?;
+                let mid = |a: Point, b: Point| Point {
+                    x: (a.x + b.x) / 2.0,
+                    y: (a.y + b.y) / 2.0,
+                };
+                if width >= height {
+                    Some((vec![mid(corners[0], corners[3]), mid(corners[1], corners[2])], *height))
+                } else {
+                    Some((vec![mid(corners[0], corners[1]), mid(corners[3], corners[2])], *width))
+                }
+            }
+            Geometry::Polygon { .. } | Geometry::Point { .. } => None,
+        }
+    }
+
+    // 配置の基準点（点はその位置、矩形は中心）
+    pub fn anchor(&self) -> Option<Point> {
+        match self {
+            Geometry::Point { x, y, .. } => Some(Point { x: *x, y: *y }),
+            Geometry::Rect { x, y, width, height }
+            | Geometry::RotatedRect { x, y, width, height, .. } => Some(Point {
+                x: x + width / 2.0,
+                y: y + height / 2.0,
+            }),
+            Geometry::Polyline { .. } | Geometry::Polygon { .. } => None,
+        }
+    }
+
+    // 基準点が指定位置に来るように平行移動した形状
+    pub fn moved_to(&self, target: Point) -> Geometry {
+        let Some(anchor) = self.anchor() else {
+            return self.clone();
+        };
+        let (dx, dy) = (target.x - anchor.x, target.y - anchor.y);
+        let mut moved = self.clone();
+        match &mut moved {
+            Geometry::Point { x, y, .. }
+            | Geometry::Rect { x, y, .. }
+            | Geometry::RotatedRect { x, y, .. } => {
+                *x += dx;
+                *y += dy;
+            }
+            Geometry::Polyline { .. } | Geometry::Polygon { .. } => {}
+        }
+        moved
+    }
+
+    // 形状として成立しているかを検証する
+    pub fn validate(&self) -> Result<(), String> {
+        let numbers: Vec<f64> = match self {
+            Geometry::Rect { x, y, width, height } => vec![*x, *y, *width, *height],
+            Geometry::RotatedRect { x, y, width, height, rotation } => {
+                vec![*x, *y, *width, *height, *rotation]
+            }
+            Geometry::Polyline { points, thickness } => points
+                .iter()
+                .flat_map(|p| [p.x, p.y])
+                .chain([*thickness])
+                .collect(),
+            Geometry::Polygon { points } => points.iter().flat_map(|p| [p.x, p.y]).collect(),
+            Geometry::Point { x, y, width, .. } => [*x, *y].into_iter().chain(*width).collect(),
+        };
+        if numbers.iter().any(|n| !n.is_finite()) {
+            return Err("Geometry contains a non-finite number".to_string());
+        }
+
+        match self {
+            Geometry::Rect { width, height, .. } | Geometry::RotatedRect { width, height, .. } => {
+                if *width <= 0.0 || *height <= 0.0 {
+                    return Err("Rectangle width and height must be positive".to_string());
+                }
+            }
+            Geometry::Polyline { points, thickness } => {
+                if points.len() < 2 {
+                    return Err("Polyline needs at least 2 points".to_string());
+                }
+                if *thickness <= 0.0 {
+                    return Err("Polyline thickness must be positive".to_string());
+                }
+                if points.windows(2).any(|w| w[0] == w[1]) {
+                    return Err("Polyline contains a zero-length segment".to_string());
+                }
+            }
+            Geometry::Polygon { points } => {
+                if points.len() < 3 {
+                    return Err("Polygon needs at least 3 points".to_string());
+                }
+                let doubled_area: f64 = points
+                    .iter()
+                    .zip(points.iter().cycle().skip(1))
+                    .map(|(a, b)| a.x * b.y - b.x * a.y)
+                    .sum();
+                if doubled_area.abs() <= f64::EPSILON {
+                    return Err("Polygon has zero area".to_string());
+                }
+            }
+            Geometry::Point { width, .. } => {
+                if width.is_some_and(|w| w <= 0.0) {
+                    return Err("Opening width must be positive".to_string());
+                }
+            }
+        }
+
+        Ok(())
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -29,6 +279,9 @@ pub struct CommonProperties {
     pub layer: String,
     pub visible: bool,
     pub locked: bool,
+    // ロック中でも編集できるユーザー（空なら全員に適用）
+    #[serde(default)]
+    pub allowed_users: Vec<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -36,6 +289,9 @@ pub struct CommonProperties {
 pub struct FloorPlanProperties {
     pub room_type: Option<String>,
     pub area: Option<f64>,
+    // 以下は部屋の形状からサーバーで計算する
+    pub perimeter: Option<f64>,
+    pub centroid: Option<Point>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -43,6 +299,7 @@ pub struct FloorPlanProperties {
 pub struct StructuralProperties {
     pub structure_type: Option<String>,
     pub load: Option<f64>,
+    pub span: Option<f64>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -67,7 +324,7 @@ pub struct Element {
     pub id: String,
     pub project_id: String,
     pub element_type: String,
-    pub geometry: JsonValue,
+    pub geometry: Geometry,
     pub properties: JsonValue,
     pub metadata: JsonValue,
     pub version: i32,
@@ -85,6 +342,7 @@ pub struct CreateElement {
 
 #[derive(Debug, Deserialize)]
 pub struct UpdateElement {
+    pub version: Option<i32>,
     pub element_type: Option<String>,
     pub geometry: Option<Geometry>,
     pub properties: Option<Properties>,
@@ -104,7 +362,7 @@ impl Element {
             id: Uuid::new_v4().to_string(),
             project_id,
             element_type,
-            geometry: serde_json::to_value(geometry).unwrap(),
+            geometry,
             properties: serde_json::to_value(properties).unwrap(),
             metadata: serde_json::to_value(metadata).unwrap(),
             version: 1,
@@ -112,4 +370,391 @@ impl Element {
             updated_at: now,
         }
     }
-} 
\ No newline at end of file
+
+    // 部屋の面積・周長・重心を形状から計算してプロパティに設定する
+    // check_client_area が真の場合、指定済みの面積が計算値と食い違えばエラー
+    pub fn apply_room_measurements(&mut self, check_client_area: bool) -> Result<(), String> {
+        if self.element_type != ELEMENT_TYPE_ROOM {
+            return Ok(());
+        }
+        let outline = self
+            .geometry
+            .outline()
+            .ok_or_else(|| "Room geometry must be a closed shape".to_string())?;
+        let (area, perimeter, centroid) = measure_outline(&outline);
+
+        let mut properties: Properties = serde_json::from_value(self.properties.clone())
+            .map_err(|e| format!("Invalid properties format: {}", e))?;
+        let floor_plan = properties.floor_plan.get_or_insert(FloorPlanProperties {
+            room_type: None,
+            area: None,
+            perimeter: None,
+            centroid: None,
+        });
+        if let Some(client_area) = floor_plan.area.filter(|_| check_client_area) {
+            if (client_area - area).abs() > area * AREA_TOLERANCE {
+                return Err(format!(
+                    "Room area {} does not match its geometry (computed {})",
+                    client_area, area
+                ));
+            }
+        }
+        floor_plan.area = Some(area);
+        floor_plan.perimeter = Some(perimeter);
+        floor_plan.centroid = Some(centroid);
+
+        self.properties = serde_json::to_value(properties)
+            .map_err(|e| format!("Invalid properties format: {}", e))?;
+        Ok(())
+    }
+
+    pub fn layer(&self) -> Option<&str> {
+        self.properties["common"]["layer"].as_str()
+    }
+
+    // 要素のロックがユーザーの編集を妨げるか
+    pub fn lock_blocks(&self, user_id: &str) -> bool {
+        let common = &self.properties["common"];
+        if common["locked"] != JsonValue::Bool(true) {
+            return false;
+        }
+        !common["allowedUsers"]
+            .as_array()
+            .is_some_and(|users| users.iter().any(|u| u == user_id))
+    }
+
+    // バージョンやタイムスタンプを除いた内容が一致するか
+    pub fn same_content(&self, other: &Element) -> bool {
+        self.element_type == other.element_type
+            && self.geometry == other.geometry
+            && self.properties == other.properties
+            && self.metadata == other.metadata
+    }
+
+    // ロックの設定（locked・allowedUsers）以外の内容が一致するか
+    pub fn same_content_except_lock(&self, other: &Element) -> bool {
+        let without_lock = |element: &Element| {
+            let mut properties = element.properties.clone();
+            if let Some(common) = properties.get_mut("common").and_then(JsonValue::as_object_mut) {
+                common.remove("locked");
+                common.remove("allowedUsers");
+            }
+            properties
+        };
+        self.element_type == other.element_type
+            && self.geometry == other.geometry
+            && self.metadata == other.metadata
+            && without_lock(self) == without_lock(other)
+    }
+}
+
+// 多角形の面積・周長・重心
+fn measure_outline(points: &[Point]) -> (f64, f64, Point) {
+    let edges = points.iter().zip(points.iter().cycle().skip(1));
+    let mut doubled_area = 0.0;
+    let mut perimeter = 0.0;
+    let (mut cx, mut cy) = (0.0, 0.0);
+    for (a, b) in edges {
+        let cross = a.x * b.y - b.x * a.y;
+        doubled_area += cross;
+        perimeter += (b.x - a.x).hypot(b.y - a.y);
+        cx += (a.x + b.x) * cross;
+        cy += (a.y + b.y) * cross;
+    }
+    let centroid = Point {
+        x: cx / (3.0 * doubled_area),
+        y: cy / (3.0 * doubled_area),
+    };
+    (doubled_area.abs() / 2.0, perimeter, centroid)
+}
+
+// 折れ線上の最近点までの距離と、始点からの道のり
+pub fn project_onto_polyline(points: &[Point], p: Point) -> (f64, f64) {
+    let mut best = (f64::INFINITY, 0.0);
+    let mut travelled = 0.0;
+    for segment in points.windows(2) {
+        let (a, b) = (segment[0], segment[1]);
+        let (dx, dy) = (b.x - a.x, b.y - a.y);
+        let length = dx.hypot(dy);
+        let t = if length > 0.0 {
+            (((p.x - a.x) * dx + (p.y - a.y) * dy) / (length * length)).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        let distance = (a.x + dx * t - p.x).hypot(a.y + dy * t - p.y);
+        if distance < best.0 {
+            best = (distance, travelled + length * t);
+        }
+        travelled += length;
+    }
+    best
+}
+
+// 折れ線の始点から指定の道のりだけ進んだ位置（範囲外は端点に丸める）
+pub fn point_along_polyline(points: &[Point], distance: f64) -> Point {
+    let mut remaining = distance.max(0.0);
+    for segment in points.windows(2) {
+        let (a, b) = (segment[0], segment[1]);
+        let length = (b.x - a.x).hypot(b.y - a.y);
+        if remaining <= length && length > 0.0 {
+            let t = remaining / length;
+            return Point {
+                x: a.x + (b.x - a.x) * t,
+                y: a.y + (b.y - a.y) * t,
+            };
+        }
+        remaining -= length;
+    }
+    points.last().copied().unwrap_or(Point { x: 0.0, y: 0.0 })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn parse(value: JsonValue) -> Geometry {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn parses_typed_geometries() {
+        assert_eq!(
+            parse(json!({"kind": "rect", "x": 1.0, "y": 2.0, "width": 3.0, "height": 4.0})),
+            Geometry::Rect { x: 1.0, y: 2.0, width: 3.0, height: 4.0 }
+        );
+        assert_eq!(
+            parse(json!({"kind": "rotatedRect", "x": 0.0, "y": 0.0, "width": 2.0, "height": 1.0, "rotation": 30.0})),
+            Geometry::RotatedRect { x: 0.0, y: 0.0, width: 2.0, height: 1.0, rotation: 30.0 }
+        );
+        assert_eq!(
+            parse(json!({"kind": "polyline", "points": [{"x": 0.0, "y": 0.0}, {"x": 5.0, "y": 0.0}], "thickness": 0.2})),
+            Geometry::Polyline {
+                points: vec![Point { x: 0.0, y: 0.0 }, Point { x: 5.0, y: 0.0 }],
+                thickness: 0.2,
+            }
+        );
+        assert_eq!(
+            parse(json!({"kind": "point", "x": 1.0, "y": 1.0, "width": 0.9})),
+            Geometry::Point { x: 1.0, y: 1.0, width: Some(0.9) }
+        );
+        assert_eq!(
+            parse(json!({"kind": "point", "x": 1.0, "y": 1.0})),
+            Geometry::Point { x: 1.0, y: 1.0, width: None }
+        );
+        // 以前の形式の hostId は無視する
+        assert_eq!(
+            parse(json!({"kind": "point", "x": 1.0, "y": 1.0, "hostId": "w1"})),
+            Geometry::Point { x: 1.0, y: 1.0, width: None }
+        );
+    }
+
+    #[test]
+    fn parses_legacy_rectangles() {
+        assert_eq!(
+            parse(json!({"x": 1.0, "y": 2.0, "width": 3.0, "height": 4.0})),
+            Geometry::Rect { x: 1.0, y: 2.0, width: 3.0, height: 4.0 }
+        );
+        assert_eq!(
+            parse(json!({"x": 1.0, "y": 2.0, "width": 3.0, "height": 4.0, "rotation": 90.0})),
+            Geometry::RotatedRect { x: 1.0, y: 2.0, width: 3.0, height: 4.0, rotation: 90.0 }
+        );
+    }
+
+    #[test]
+    fn parses_frontend_rectangles() {
+        assert_eq!(
+            parse(json!({"position": {"x": 1.0, "y": 2.0}, "size": {"width": 3.0, "height": 4.0}})),
+            Geometry::Rect { x: 1.0, y: 2.0, width: 3.0, height: 4.0 }
+        );
+        assert_eq!(
+            parse(json!({"position": {"x": 1.0, "y": 2.0}, "size": {"width": 3.0, "height": 4.0}, "rotation": 45.0})),
+            Geometry::RotatedRect { x: 1.0, y: 2.0, width: 3.0, height: 4.0, rotation: 45.0 }
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_geometry() {
+        assert!(serde_json::from_value::<Geometry>(json!({"kind": "circle", "r": 1.0})).is_err());
+        assert!(serde_json::from_value::<Geometry>(json!({"kind": "rect", "x": 1.0})).is_err());
+        assert!(serde_json::from_value::<Geometry>(json!({"x": 1.0, "y": 2.0})).is_err());
+        assert!(serde_json::from_value::<Geometry>(json!({"position": {"x": 1.0, "y": 2.0}})).is_err());
+    }
+
+    #[test]
+    fn serializes_with_kind_tag() {
+        let geometry = Geometry::Point { x: 1.0, y: 2.0, width: None };
+        let value = serde_json::to_value(&geometry).unwrap();
+        assert_eq!(value, json!({"kind": "point", "x": 1.0, "y": 2.0, "width": null}));
+        assert_eq!(parse(value), geometry);
+    }
+
+    #[test]
+    fn validates_geometry() {
+        let square = vec![
+            Point { x: 0.0, y: 0.0 },
+            Point { x: 1.0, y: 0.0 },
+            Point { x: 1.0, y: 1.0 },
+        ];
+        assert!(Geometry::Rect { x: 0.0, y: 0.0, width: 1.0, height: 1.0 }.validate().is_ok());
+        assert!(Geometry::Polygon { points: square.clone() }.validate().is_ok());
+        assert!(Geometry::Polyline { points: square.clone(), thickness: 0.2 }.validate().is_ok());
+        assert!(Geometry::Point { x: 0.0, y: 0.0, width: Some(1.0) }.validate().is_ok());
+
+        assert!(Geometry::Rect { x: 0.0, y: 0.0, width: 0.0, height: 1.0 }.validate().is_err());
+        assert!(Geometry::Rect { x: f64::NAN, y: 0.0, width: 1.0, height: 1.0 }.validate().is_err());
+        assert!(Geometry::RotatedRect { x: 0.0, y: 0.0, width: 1.0, height: 1.0, rotation: f64::INFINITY }
+            .validate()
+            .is_err());
+        assert!(Geometry::Polyline { points: square[..1].to_vec(), thickness: 0.2 }.validate().is_err());
+        assert!(Geometry::Polyline { points: square.clone(), thickness: 0.0 }.validate().is_err());
+        assert!(Geometry::Polyline { points: vec![square[0], square[0]], thickness: 0.2 }
+            .validate()
+            .is_err());
+        assert!(Geometry::Polygon { points: square[..2].to_vec() }.validate().is_err());
+        let collinear = vec![
+            Point { x: 0.0, y: 0.0 },
+            Point { x: 1.0, y: 1.0 },
+            Point { x: 2.0, y: 2.0 },
+        ];
+        assert!(Geometry::Polygon { points: collinear }.validate().is_err());
+        assert!(Geometry::Point { x: 0.0, y: 0.0, width: Some(-1.0) }.validate().is_err());
+    }
+
+    fn points(coords: &[(f64, f64)]) -> Vec<Point> {
+        coords.iter().map(|&(x, y)| Point { x, y }).collect()
+    }
+
+    #[test]
+    fn measures_outlines() {
+        let (area, perimeter, centroid) = measure_outline(&points(&[(0.0, 0.0), (4.0, 0.0), (4.0, 2.0), (0.0, 2.0)]));
+        assert_eq!((area, perimeter), (8.0, 12.0));
+        assert_eq!(centroid, Point { x: 2.0, y: 1.0 });
+
+        // 時計回りでも面積は正、重心は同じ
+        let (area, _, centroid) = measure_outline(&points(&[(0.0, 2.0), (4.0, 2.0), (4.0, 0.0), (0.0, 0.0)]));
+        assert_eq!(area, 8.0);
+        assert_eq!(centroid, Point { x: 2.0, y: 1.0 });
+
+        // L字形（2x2 の正方形 3 つ）
+        let (area, perimeter, centroid) = measure_outline(&points(&[
+            (0.0, 0.0),
+            (4.0, 0.0),
+            (4.0, 2.0),
+            (2.0, 2.0),
+            (2.0, 4.0),
+            (0.0, 4.0),
+        ]));
+        assert_eq!((area, perimeter), (12.0, 16.0));
+        assert!((centroid.x - 5.0 / 3.0).abs() < 1e-12 && (centroid.y - 5.0 / 3.0).abs() < 1e-12);
+    }
+
+    fn room(area: Option<f64>) -> Element {
+        Element {
+            id: "r1".to_string(),
+            project_id: "p1".to_string(),
+            element_type: ELEMENT_TYPE_ROOM.to_string(),
+            geometry: Geometry::Rect { x: 0.0, y: 0.0, width: 4.0, height: 2.0 },
+            properties: json!({
+                "common": {"name": "room", "layer": "0", "visible": true, "locked": false},
+                "floorPlan": {"roomType": null, "area": area, "perimeter": null, "centroid": null},
+                "structural": null,
+                "architectural": null,
+            }),
+            metadata: json!({}),
+            version: 1,
+            created_at: OffsetDateTime::UNIX_EPOCH,
+            updated_at: OffsetDateTime::UNIX_EPOCH,
+        }
+    }
+
+    #[test]
+    fn applies_room_measurements() {
+        let mut element = room(None);
+        element.apply_room_measurements(true).unwrap();
+        assert_eq!(element.properties["floorPlan"]["area"], json!(8.0));
+        assert_eq!(element.properties["floorPlan"]["perimeter"], json!(12.0));
+        assert_eq!(element.properties["floorPlan"]["centroid"], json!({"x": 2.0, "y": 1.0}));
+
+        assert!(room(Some(8.0)).apply_room_measurements(true).is_ok());
+        assert!(room(Some(10.0)).apply_room_measurements(true).is_err());
+
+        // 検証しない場合は計算値で上書きする
+        let mut element = room(Some(10.0));
+        element.apply_room_measurements(false).unwrap();
+        assert_eq!(element.properties["floorPlan"]["area"], json!(8.0));
+    }
+
+    #[test]
+    fn compares_content_except_lock() {
+        let mut locked = room(None);
+        locked.properties["common"]["locked"] = json!(true);
+        locked.properties["common"]["allowedUsers"] = json!(["u1"]);
+        assert!(locked.lock_blocks("u2"));
+        assert!(!locked.lock_blocks("u1"));
+        assert!(room(None).same_content_except_lock(&locked));
+
+        let mut moved = room(None);
+        moved.geometry = Geometry::Rect { x: 1.0, y: 0.0, width: 4.0, height: 2.0 };
+        assert!(!moved.same_content_except_lock(&locked));
+        let mut renamed = room(None);
+        renamed.properties["common"]["name"] = json!("hall");
+        assert!(!renamed.same_content_except_lock(&locked));
+    }
+
+    #[test]
+    fn projects_onto_polylines() {
+        let axis = points(&[(0.0, 0.0), (10.0, 0.0), (10.0, 10.0)]);
+        assert_eq!(project_onto_polyline(&axis, Point { x: 4.0, y: 3.0 }), (3.0, 4.0));
+        assert_eq!(project_onto_polyline(&axis, Point { x: 12.0, y: 5.0 }), (2.0, 15.0));
+        // 範囲外は端点までの距離
+        assert_eq!(project_onto_polyline(&axis, Point { x: -3.0, y: -4.0 }), (5.0, 0.0));
+        assert_eq!(project_onto_polyline(&axis, Point { x: 10.0, y: 13.0 }), (3.0, 20.0));
+        // 長さ 0 の区間は点として扱う
+        let dot = points(&[(1.0, 1.0), (1.0, 1.0)]);
+        assert_eq!(project_onto_polyline(&dot, Point { x: 4.0, y: 5.0 }), (5.0, 0.0));
+    }
+
+    #[test]
+    fn walks_along_polylines() {
+        let axis = points(&[(0.0, 0.0), (10.0, 0.0), (10.0, 10.0)]);
+        assert_eq!(point_along_polyline(&axis, 4.0), Point { x: 4.0, y: 0.0 });
+        assert_eq!(point_along_polyline(&axis, 15.0), Point { x: 10.0, y: 5.0 });
+        assert_eq!(point_along_polyline(&axis, -1.0), Point { x: 0.0, y: 0.0 });
+        assert_eq!(point_along_polyline(&axis, 25.0), Point { x: 10.0, y: 10.0 });
+
+        // 射影した道のりを戻すと元の最近点になる
+        let (_, along) = project_onto_polyline(&axis, Point { x: 12.0, y: 5.0 });
+        assert_eq!(point_along_polyline(&axis, along), Point { x: 10.0, y: 5.0 });
+    }
+
+    #[test]
+    fn finds_wall_axes() {
+        let (axis, thickness) = Geometry::Rect { x: 0.0, y: 0.0, width: 10.0, height: 0.2 }
+            .wall_axis()
+            .unwrap();
+        assert_eq!(axis, points(&[(0.0, 0.1), (10.0, 0.1)]));
+        assert_eq!(thickness, 0.2);
+
+        let (axis, thickness) = Geometry::Rect { x: 0.0, y: 0.0, width: 0.2, height: 10.0 }
+            .wall_axis()
+            .unwrap();
+        assert_eq!(axis, points(&[(0.1, 0.0), (0.1, 10.0)]));
+        assert_eq!(thickness, 0.2);
+
+        assert!(Geometry::Polygon { points: points(&[(0.0, 0.0), (1.0, 0.0), (0.0, 1.0)]) }
+            .wall_axis()
+            .is_none());
+    }
+
+    #[test]
+    fn outlines_rotated_rectangles_about_their_centre() {
+        let outline = Geometry::RotatedRect { x: 0.0, y: 0.0, width: 2.0, height: 2.0, rotation: 90.0 }
+            .outline()
+            .unwrap();
+        let expected = [(2.0, 0.0), (2.0, 2.0), (0.0, 2.0), (0.0, 0.0)];
+        for (p, (x, y)) in outline.iter().zip(expected) {
+            assert!((p.x - x).abs() < 1e-9 && (p.y - y).abs() < 1e-9, "{:?}", outline);
+        }
+    }
+}
@@ -0,0 +1,332 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+
+// 一度に返す要素数の上限
+pub const MAX_ELEMENT_PAGE_SIZE: i64 = 1000;
+
+// 要素一覧の並び順に使える項目
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ElementSortField {
+    CreatedAt,
+    UpdatedAt,
+    ElementType,
+    Version,
+}
+
+impl ElementSortField {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "created_at" => Some(Self::CreatedAt),
+            "updated_at" => Some(Self::UpdatedAt),
+            "element_type" => Some(Self::ElementType),
+            "version" => Some(Self::Version),
+            _ => None,
+        }
+    }
+
+    pub fn column(&self) -> &'static str {
+        match self {
+            Self::CreatedAt => "created_at",
+            Self::UpdatedAt => "updated_at",
+            Self::ElementType => "element_type",
+            Self::Version => "version",
+        }
+    }
+
+    pub fn is_numeric(&self) -> bool {
+        *self == Self::Version
+    }
+}
+
+// プロパティの値に対する比較
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PredicateOp {
+    Eq,
+    Ne,
+    Lt,
+    Lte,
+    Gt,
+    Gte,
+}
+
+impl PredicateOp {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "eq" => Some(Self::Eq),
+            "ne" => Some(Self::Ne),
+            "lt" => Some(Self::Lt),
+            "lte" => Some(Self::Lte),
+            "gt" => Some(Self::Gt),
+            "gte" => Some(Self::Gte),
+            _ => None,
+        }
+    }
+
+    pub fn sql(&self) -> &'static str {
+        match self {
+            Self::Eq => "=",
+            Self::Ne => "<>",
+            Self::Lt => "<",
+            Self::Lte => "<=",
+            Self::Gt => ">",
+            Self::Gte => ">=",
+        }
+    }
+}
+
+// properties 内の値の条件（例: properties.floorPlan.area[gte]=20）
+#[derive(Debug, Clone)]
+pub struct PropertyPredicate {
+    // JSONパス（例: $.floorPlan.area）
+    pub path: String,
+    pub op: PredicateOp,
+    // 保存されている値の型に合わせて、文字列・数値・真偽値として比較する
+    pub value: String,
+}
+
+impl PropertyPredicate {
+    // 数値として保存された値と比べる場合の値（数値として解釈できなければ一致しない）
+    pub fn number(&self) -> Option<f64> {
+        self.value.parse().ok().filter(|n: &f64| n.is_finite())
+    }
+
+    // 真偽値として保存された値と比べる場合の値
+    pub fn boolean(&self) -> Option<bool> {
+        self.value.parse().ok()
+    }
+}
+
+// 前のページの最後の要素の位置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ElementCursor {
+    pub key: JsonValue,
+    pub id: String,
+}
+
+impl ElementCursor {
+    pub fn encode(&self) -> String {
+        URL_SAFE_NO_PAD.encode(serde_json::to_vec(self).unwrap_or_default())
+    }
+
+    fn decode(value: &str) -> Option<Self> {
+        let bytes = URL_SAFE_NO_PAD.decode(value).ok()?;
+        serde_json::from_slice(&bytes).ok()
+    }
+}
+
+// 要素一覧の絞り込み・並び順・ページ
+#[derive(Debug, Clone)]
+pub struct ElementQuery {
+    pub element_types: Vec<String>,
+    pub layer: Option<String>,
+    pub visible: Option<bool>,
+    pub properties: Vec<PropertyPredicate>,
+    pub sort: ElementSortField,
+    pub descending: bool,
+    // 省略時はすべての要素を返す
+    pub limit: Option<i64>,
+    pub cursor: Option<ElementCursor>,
+}
+
+impl Default for ElementQuery {
+    fn default() -> Self {
+        Self {
+            element_types: Vec::new(),
+            layer: None,
+            visible: None,
+            properties: Vec::new(),
+            sort: ElementSortField::CreatedAt,
+            descending: false,
+            limit: None,
+            cursor: None,
+        }
+    }
+}
+
+impl ElementQuery {
+    // クエリパラメータから組み立てる
+    // element_type はカンマ区切りで複数指定でき、sort は先頭に - を付けると降順
+    pub fn from_params(params: Vec<(String, String)>) -> Result<Self, String> {
+        let mut query = Self::default();
+        let mut cursor = None;
+        for (key, value) in params {
+            match key.as_str() {
+                "element_type" => query.element_types.extend(
+                    value
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|t| !t.is_empty())
+                        .map(String::from),
+                ),
+                "layer" => query.layer = Some(value),
+                "visible" => {
+                    query.visible = Some(
+                        value
+                            .parse()
+                            .map_err(|_| format!("Invalid value for visible: {}", value))?,
+                    )
+                }
+                "sort" => {
+                    let (descending, name) = match value.strip_prefix('-') {
+                        Some(name) => (true, name),
+                        None => (false, value.as_str()),
+                    };
+                    query.sort = ElementSortField::parse(name)
+                        .ok_or_else(|| format!("Unsupported sort field: {}", name))?;
+                    query.descending = descending;
+                }
+                "limit" => {
+                    let limit: i64 = value
+                        .parse()
+                        .map_err(|_| format!("Invalid value for limit: {}", value))?;
+                    if !(1..=MAX_ELEMENT_PAGE_SIZE).contains(&limit) {
+                        return Err(format!(
+                            "limit must be between 1 and {}",
+                            MAX_ELEMENT_PAGE_SIZE
+                        ));
+                    }
+                    query.limit = Some(limit);
+                }
+                "cursor" => {
+                    cursor = Some(
+                        ElementCursor::decode(&value).ok_or_else(|| "Invalid cursor".to_string())?,
+                    )
+                }
+                _ => match key.strip_prefix("properties.") {
+                    Some(path) => query.properties.push(parse_predicate(path, &value)?),
+                    None => return Err(format!("Unknown query parameter: {}", key)),
+                },
+            }
+        }
+
+        // カーソルの値は並び順の項目と同じ型である必要がある
+        if let Some(cursor) = &cursor {
+            let valid = if query.sort.is_numeric() {
+                cursor.key.is_i64()
+            } else {
+                cursor.key.is_string()
+            };
+            if !valid {
+                return Err("Cursor does not match the sort field".to_string());
+            }
+        }
+        query.cursor = cursor;
+        Ok(query)
+    }
+}
+
+// "floorPlan.area[gte]" と値から条件を作る（比較の省略時は eq）
+fn parse_predicate(key: &str, value: &str) -> Result<PropertyPredicate, String> {
+    let (path, op) = match key.strip_suffix(']').and_then(|k| k.split_once('[')) {
+        Some((path, op)) => (
+            path,
+            PredicateOp::parse(op).ok_or_else(|| format!("Unsupported operator: {}", op))?,
+        ),
+        None => (key, PredicateOp::Eq),
+    };
+
+    let valid_segment = |s: &str| !s.is_empty() && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !path.split('.').all(valid_segment) {
+        return Err(format!("Invalid property path: {}", path));
+    }
+
+    Ok(PropertyPredicate {
+        path: format!("$.{}", path),
+        op,
+        value: value.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn parse(params: &[(&str, &str)]) -> Result<ElementQuery, String> {
+        ElementQuery::from_params(
+            params
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn parses_filters_and_sort() {
+        let query = parse(&[
+            ("element_type", "wall, room,"),
+            ("element_type", "door"),
+            ("layer", "A-WALL"),
+            ("visible", "false"),
+            ("sort", "-version"),
+            ("limit", "50"),
+        ])
+        .unwrap();
+        assert_eq!(query.element_types, vec!["wall", "room", "door"]);
+        assert_eq!(query.layer.as_deref(), Some("A-WALL"));
+        assert_eq!(query.visible, Some(false));
+        assert_eq!(query.sort, ElementSortField::Version);
+        assert!(query.descending);
+        assert_eq!(query.limit, Some(50));
+
+        let query = parse(&[]).unwrap();
+        assert_eq!(query.sort, ElementSortField::CreatedAt);
+        assert!(!query.descending);
+        assert_eq!(query.limit, None);
+    }
+
+    #[test]
+    fn parses_property_predicates() {
+        let query = parse(&[
+            ("properties.floorPlan.area[gte]", "20"),
+            ("properties.common.name", "101"),
+        ])
+        .unwrap();
+        let [area, name] = &query.properties[..] else {
+            panic!("expected two predicates");
+        };
+        assert_eq!(area.path, "$.floorPlan.area");
+        assert_eq!(area.op, PredicateOp::Gte);
+        assert_eq!(area.number(), Some(20.0));
+        // 値は文字列のまま保持し、保存されている型に合わせて比較する
+        assert_eq!(name.path, "$.common.name");
+        assert_eq!(name.op, PredicateOp::Eq);
+        assert_eq!(name.value, "101");
+        assert_eq!(name.boolean(), None);
+
+        assert!(parse(&[("properties.common.name[like]", "a")]).is_err());
+        assert!(parse(&[("properties.common..name", "a")]).is_err());
+        assert!(parse(&[("properties.$.name", "a")]).is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_params() {
+        assert!(parse(&[("visible", "yes")]).is_err());
+        assert!(parse(&[("sort", "name")]).is_err());
+        assert!(parse(&[("limit", "0")]).is_err());
+        assert!(parse(&[("limit", "1001")]).is_err());
+        assert!(parse(&[("cursor", "not a cursor")]).is_err());
+        assert!(parse(&[("unknown", "1")]).is_err());
+    }
+
+    #[test]
+    fn checks_cursor_against_sort_field() {
+        let cursor = ElementCursor {
+            key: json!(3),
+            id: "e1".to_string(),
+        }
+        .encode();
+        let query = parse(&[("sort", "version"), ("cursor", &cursor)]).unwrap();
+        assert_eq!(query.cursor.map(|c| c.id).as_deref(), Some("e1"));
+        assert!(parse(&[("cursor", &cursor)]).is_err());
+
+        let cursor = ElementCursor {
+            key: json!("2024-05-01T00:00:00Z"),
+            id: "e1".to_string(),
+        }
+        .encode();
+        assert!(parse(&[("sort", "-updated_at"), ("cursor", &cursor)]).is_ok());
+        assert!(parse(&[("sort", "version"), ("cursor", &cursor)]).is_err());
+    }
+}
@@ -0,0 +1,79 @@
+use serde::Serialize;
+use serde_json::{json, Value as JsonValue};
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+// 変更対象の種別（要素・関係性は history の定数を使う）
+pub const ENTITY_VIEW: &str = "view";
+pub const ENTITY_LAYER: &str = "layer";
+pub const ENTITY_PROJECT: &str = "project";
+
+// ルールによる導出（payload.changes に導出内容を持つ）
+pub const CHANGE_PROPAGATE: &str = "propagate";
+
+// プロジェクトの変更イベント（追記のみ）
+// change_history と WebSocket の通知はこの記録から導出する
+#[derive(Debug, Clone, Serialize)]
+pub struct Event {
+    pub id: String,
+    pub project_id: String,
+    // プロジェクト内で単調増加する通し番号
+    pub seq: i64,
+    pub entity_type: String,
+    pub entity_id: String,
+    pub change_type: String,
+    pub actor: String,
+    // 変更前後の状態 {"before": ..., "after": ...}
+    pub payload: JsonValue,
+    // まとめて適用した変更のグループ
+    pub change_set_id: Option<String>,
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_at: OffsetDateTime,
+}
+
+// 記録前のイベント
+#[derive(Debug, Clone)]
+pub struct NewEvent {
+    pub id: String,
+    pub project_id: String,
+    pub entity_type: String,
+    pub entity_id: String,
+    pub change_type: String,
+    pub actor: String,
+    pub payload: JsonValue,
+}
+
+impl NewEvent {
+    pub fn new(
+        project_id: &str,
+        entity_type: &str,
+        entity_id: &str,
+        change_type: &str,
+        actor: &str,
+    ) -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            project_id: project_id.to_string(),
+            entity_type: entity_type.to_string(),
+            entity_id: entity_id.to_string(),
+            change_type: change_type.to_string(),
+            actor: actor.to_string(),
+            payload: JsonValue::Null,
+        }
+    }
+
+    pub fn with_states(mut self, before: Option<JsonValue>, after: Option<JsonValue>) -> Self {
+        self.payload = json!({ "before": before, "after": after });
+        self
+    }
+}
+
+impl Event {
+    pub fn before(&self) -> Option<&JsonValue> {
+        self.payload.get("before").filter(|v| !v.is_null())
+    }
+
+    pub fn after(&self) -> Option<&JsonValue> {
+        self.payload.get("after").filter(|v| !v.is_null())
+    }
+}
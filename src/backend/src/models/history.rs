@@ -1,38 +1,48 @@
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
 use time::OffsetDateTime;
-use uuid::Uuid;
+
+use super::{change_set::ChangeSet, element::Element};
+
+// 変更種別
+pub const CHANGE_CREATE: &str = "create";
+pub const CHANGE_UPDATE: &str = "update";
+pub const CHANGE_DELETE: &str = "delete";
+pub const CHANGE_UNDO: &str = "undo";
+pub const CHANGE_REDO: &str = "redo";
+
+// 変更対象の種別
+pub const ENTITY_ELEMENT: &str = "element";
+pub const ENTITY_RELATIONSHIP: &str = "relationship";
+
+// 取り消し状態
+pub const UNDO_STATUS_APPLIED: &str = "applied";
+pub const UNDO_STATUS_UNDONE: &str = "undone";
+pub const UNDO_STATUS_DISCARDED: &str = "discarded";
 
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
 pub struct History {
     pub id: String,
     pub project_id: String,
+    // entity_typeが"relationship"の場合は関係性のID
     pub element_id: String,
+    pub entity_type: String,
     pub change_type: String,
     pub old_value: Option<serde_json::Value>,
     pub new_value: Option<serde_json::Value>,
     pub timestamp: OffsetDateTime,
     pub user_id: String,
+    pub undo_status: String,
+    pub change_set_id: Option<String>,
 }
 
-impl History {
-    pub fn new(
-        project_id: String,
-        element_id: String,
-        change_type: String,
-        old_value: Option<serde_json::Value>,
-        new_value: Option<serde_json::Value>,
-        user_id: String,
-    ) -> Self {
-        Self {
-            id: Uuid::new_v4().to_string(),
-            project_id,
-            element_id,
-            change_type,
-            old_value,
-            new_value,
-            timestamp: OffsetDateTime::now_utc(),
-            user_id,
-        }
-    }
-} 
\ No newline at end of file
+// undo/redoの実行結果
+#[derive(Debug, Clone, Serialize)]
+pub struct UndoResult {
+    pub entry: History,
+    pub element: Option<Element>,
+    // 変更グループ単位で適用した場合のグループ
+    pub change_set: Option<ChangeSet>,
+    // 適用したすべての変更（記録順）
+    pub entries: Vec<History>,
+}
@@ -0,0 +1,43 @@
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+// レイヤーの設定（設定のないレイヤーはロックなし・表示）
+#[derive(Debug, Clone, Serialize)]
+pub struct Layer {
+    pub project_id: String,
+    pub name: String,
+    pub locked: bool,
+    pub visible: bool,
+    // ロック中でも編集できるユーザー（空なら全員に適用）
+    pub allowed_users: Vec<String>,
+    pub element_count: i64,
+    pub updated_by: Option<String>,
+    pub updated_at: Option<OffsetDateTime>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateLayer {
+    pub locked: Option<bool>,
+    pub visible: Option<bool>,
+    pub allowed_users: Option<Vec<String>>,
+}
+
+impl Layer {
+    pub fn new(project_id: String, name: String) -> Self {
+        Self {
+            project_id,
+            name,
+            locked: false,
+            visible: true,
+            allowed_users: Vec::new(),
+            element_count: 0,
+            updated_by: None,
+            updated_at: None,
+        }
+    }
+
+    // ロックがユーザーの編集を妨げるか
+    pub fn blocks(&self, user_id: &str) -> bool {
+        self.locked && !self.allowed_users.iter().any(|u| u == user_id)
+    }
+}
@@ -0,0 +1,71 @@
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProjectRole {
+    Owner,
+    Editor,
+    // 構造プロパティのみ編集できる
+    StructuralEditor,
+    Viewer,
+}
+
+// 操作に必要な権限
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Permission {
+    View,
+    Edit,
+    Manage,
+}
+
+impl ProjectRole {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ProjectRole::Owner => "owner",
+            ProjectRole::Editor => "editor",
+            ProjectRole::StructuralEditor => "structural_editor",
+            ProjectRole::Viewer => "viewer",
+        }
+    }
+
+    pub fn parse(role: &str) -> Option<Self> {
+        match role {
+            "owner" => Some(ProjectRole::Owner),
+            "editor" => Some(ProjectRole::Editor),
+            "structural_editor" => Some(ProjectRole::StructuralEditor),
+            "viewer" => Some(ProjectRole::Viewer),
+            _ => None,
+        }
+    }
+
+    pub fn allows(&self, permission: Permission) -> bool {
+        match permission {
+            Permission::View => true,
+            Permission::Edit => matches!(self, ProjectRole::Owner | ProjectRole::Editor),
+            Permission::Manage => *self == ProjectRole::Owner,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Member {
+    pub project_id: String,
+    pub user_id: String,
+    pub username: String,
+    pub role: ProjectRole,
+    pub created_at: OffsetDateTime,
+}
+
+// ユーザーIDまたはユーザー名で指定する
+#[derive(Debug, Deserialize)]
+pub struct AddMember {
+    pub user_id: Option<String>,
+    pub username: Option<String>,
+    pub role: ProjectRole,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateMember {
+    pub role: ProjectRole,
+}
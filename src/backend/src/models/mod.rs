@@ -3,9 +3,17 @@ pub mod element;
 pub mod relationship;
 pub mod view;
 pub mod history;
-
-pub use project::*;
-pub use element::*;
-pub use relationship::*;
-pub use view::*;
-pub use history::*; 
\ No newline at end of file
+pub mod snapshot;
+pub mod branch;
+pub mod diff;
+pub mod propagation;
+pub mod user;
+pub mod member;
+pub mod layer;
+pub mod presence;
+pub mod event;
+pub mod batch;
+pub mod change_set;
+pub mod element_query;
+pub mod spatial;
+pub mod clash;
@@ -0,0 +1,35 @@
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+use super::element::Point;
+
+// 接続中のユーザーが何を見ているか（接続ごと）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Presence {
+    pub connection_id: String,
+    pub user_id: String,
+    pub username: String,
+    pub cursor: Option<Point>,
+    pub selection: Vec<String>,
+    pub view_type: Option<String>,
+    #[serde(with = "time::serde::rfc3339")]
+    pub joined_at: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339")]
+    pub updated_at: OffsetDateTime,
+}
+
+impl Presence {
+    pub fn new(connection_id: String, user_id: String, username: String) -> Self {
+        let now = OffsetDateTime::now_utc();
+        Self {
+            connection_id,
+            user_id,
+            username,
+            cursor: None,
+            selection: Vec::new(),
+            view_type: None,
+            joined_at: now,
+            updated_at: now,
+        }
+    }
+}
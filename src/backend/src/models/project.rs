@@ -21,6 +21,7 @@ pub struct CreateProject {
 
 #[derive(Debug, Deserialize)]
 pub struct UpdateProject {
+    pub version: Option<i32>,
     pub name: Option<String>,
     pub description: Option<String>,
 }
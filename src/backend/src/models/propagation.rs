@@ -0,0 +1,80 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use time::OffsetDateTime;
+
+// ビュー間の伝播ルール
+// 対象要素で条件がすべて成り立ち、かつ条件の入力（または形状）が変化したときに適用する
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PropagationRule {
+    pub name: String,
+    pub element_type: Option<String>,
+    #[serde(default)]
+    pub when: Vec<RuleCondition>,
+    pub then: Vec<RuleAction>,
+}
+
+// 要素JSON上のパス（例: "properties.floorPlan.roomType"）の値が一致するか
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RuleCondition {
+    pub path: String,
+    pub equals: JsonValue,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "camelCase")]
+pub enum RuleAction {
+    // 固定値を設定する
+    Set { path: String, value: JsonValue },
+    // 形状から求めた量を設定する
+    Derive { path: String, from: Quantity },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Quantity {
+    WallLength,
+    WallThickness,
+    Area,
+    Perimeter,
+}
+
+// ルールによって導出された変更
+#[derive(Debug, Clone, Serialize)]
+pub struct DerivedChange {
+    pub rule: String,
+    pub element_id: String,
+    pub path: String,
+    pub old: Option<JsonValue>,
+    pub new: JsonValue,
+}
+
+// プロジェクトに登録されたルールセット
+#[derive(Debug, Clone, Serialize)]
+pub struct RuleSet {
+    pub project_id: String,
+    pub rules: Vec<PropagationRule>,
+    // 標準ルールを使用している場合は false
+    pub custom: bool,
+    pub version: i32,
+    pub updated_by: Option<String>,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub updated_at: Option<OffsetDateTime>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateRuleSet {
+    pub rules: Vec<PropagationRule>,
+}
+
+// 省略時は登録済みのルールで試行する
+#[derive(Debug, Default, Deserialize)]
+pub struct DryRunRequest {
+    pub rules: Option<Vec<PropagationRule>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DryRunReport {
+    pub element_count: usize,
+    pub changes: Vec<DerivedChange>,
+}
@@ -3,13 +3,18 @@ use sqlx::FromRow;
 use time::OffsetDateTime;
 use uuid::Uuid;
 
+// 壁（source）が開口部（target）を保持する関係
+pub const RELATIONSHIP_TYPE_HOSTS: &str = "hosts";
+
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
 pub struct Relationship {
     pub id: String,
+    pub project_id: String,
     pub source_id: String,
     pub target_id: String,
     pub relationship_type: String,
     pub properties: Option<serde_json::Value>,
+    pub version: i32,
     pub created_at: OffsetDateTime,
 }
 
@@ -23,12 +28,14 @@ pub struct CreateRelationship {
 
 #[derive(Debug, Deserialize)]
 pub struct UpdateRelationship {
+    pub version: Option<i32>,
     pub relationship_type: Option<String>,
     pub properties: Option<serde_json::Value>,
 }
 
 impl Relationship {
     pub fn new(
+        project_id: String,
         source_id: String,
         target_id: String,
         relationship_type: String,
@@ -36,11 +43,21 @@ impl Relationship {
     ) -> Self {
         Self {
             id: Uuid::new_v4().to_string(),
+            project_id,
             source_id,
             target_id,
             relationship_type,
             properties,
+            version: 1,
             created_at: OffsetDateTime::now_utc(),
         }
     }
+
+    // バージョンや作成日時を除いた内容が一致するか
+    pub fn same_content(&self, other: &Relationship) -> bool {
+        self.source_id == other.source_id
+            && self.target_id == other.target_id
+            && self.relationship_type == other.relationship_type
+            && self.properties == other.properties
+    }
 } 
\ No newline at end of file
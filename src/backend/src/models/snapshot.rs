@@ -0,0 +1,63 @@
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+use super::{element::Element, relationship::Relationship, view::View};
+
+// 変更履歴から復元した、ある時点のプロジェクトの状態
+#[derive(Debug, Clone, Serialize)]
+pub struct ProjectState {
+    pub project_id: String,
+    #[serde(with = "time::serde::rfc3339")]
+    pub at: OffsetDateTime,
+    pub elements: Vec<Element>,
+    pub relationships: Vec<Relationship>,
+}
+
+// 名前付きスナップショット（ベースライン）
+#[derive(Debug, Clone, Serialize)]
+pub struct Snapshot {
+    pub id: String,
+    pub project_id: String,
+    pub label: String,
+    pub description: Option<String>,
+    pub project_version: i32,
+    pub elements: Vec<Element>,
+    pub relationships: Vec<Relationship>,
+    pub views: Vec<View>,
+    pub created_by: String,
+    pub created_at: OffsetDateTime,
+}
+
+// 一覧表示用（内容を含まない）
+#[derive(Debug, Clone, Serialize)]
+pub struct SnapshotSummary {
+    pub id: String,
+    pub project_id: String,
+    pub label: String,
+    pub description: Option<String>,
+    pub project_version: i32,
+    pub element_count: i64,
+    pub relationship_count: i64,
+    pub view_count: i64,
+    pub created_by: String,
+    pub created_at: OffsetDateTime,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateSnapshot {
+    pub label: String,
+    pub description: Option<String>,
+}
+
+// スナップショット復元の結果
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RestoreSummary {
+    pub snapshot_id: String,
+    pub elements_created: usize,
+    pub elements_updated: usize,
+    pub elements_deleted: usize,
+    pub relationships_created: usize,
+    pub relationships_updated: usize,
+    pub relationships_deleted: usize,
+    pub views_restored: usize,
+}
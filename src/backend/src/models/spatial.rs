@@ -0,0 +1,193 @@
+use serde::Serialize;
+
+use super::element::{project_onto_polyline, Element, Geometry, Point};
+
+// 点が形状上にあるとみなす距離の許容誤差
+const CONTAINS_TOLERANCE: f64 = 1e-9;
+
+// 軸に平行な外接矩形
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bounds {
+    pub min_x: f64,
+    pub min_y: f64,
+    pub max_x: f64,
+    pub max_y: f64,
+}
+
+impl Bounds {
+    // 範囲として成立しているかを検証する
+    pub fn validate(&self) -> Result<(), String> {
+        let numbers = [self.min_x, self.min_y, self.max_x, self.max_y];
+        if numbers.iter().any(|n| !n.is_finite()) {
+            return Err("Bounding box contains a non-finite number".to_string());
+        }
+        if self.min_x > self.max_x || self.min_y > self.max_y {
+            return Err("Bounding box minimum must not exceed its maximum".to_string());
+        }
+        Ok(())
+    }
+
+    // 形状の外接矩形（壁厚・開口幅を含む）
+    pub fn of(geometry: &Geometry) -> Self {
+        let shape = Shape::of(geometry);
+        let mut bounds = Self {
+            min_x: f64::INFINITY,
+            min_y: f64::INFINITY,
+            max_x: f64::NEG_INFINITY,
+            max_y: f64::NEG_INFINITY,
+        };
+        for p in &shape.vertices {
+            bounds.min_x = bounds.min_x.min(p.x);
+            bounds.min_y = bounds.min_y.min(p.y);
+            bounds.max_x = bounds.max_x.max(p.x);
+            bounds.max_y = bounds.max_y.max(p.y);
+        }
+        bounds.expanded(shape.radius)
+    }
+
+    pub fn point(p: Point) -> Self {
+        Self {
+            min_x: p.x,
+            min_y: p.y,
+            max_x: p.x,
+            max_y: p.y,
+        }
+    }
+
+    pub fn expanded(&self, distance: f64) -> Self {
+        Self {
+            min_x: self.min_x - distance,
+            min_y: self.min_y - distance,
+            max_x: self.max_x + distance,
+            max_y: self.max_y + distance,
+        }
+    }
+}
+
+// 基準の要素からの距離付きの要素
+#[derive(Debug, Clone, Serialize)]
+pub struct NearbyElement {
+    pub element: Element,
+    pub distance: f64,
+}
+
+// 形状が矩形範囲と重なるか
+pub fn intersects_bounds(geometry: &Geometry, bounds: &Bounds) -> bool {
+    let area = Shape {
+        vertices: vec![
+            Point { x: bounds.min_x, y: bounds.min_y },
+            Point { x: bounds.max_x, y: bounds.min_y },
+            Point { x: bounds.max_x, y: bounds.max_y },
+            Point { x: bounds.min_x, y: bounds.max_y },
+        ],
+        closed: true,
+        radius: 0.0,
+    };
+    Shape::of(geometry).distance_to(&area) <= CONTAINS_TOLERANCE
+}
+
+// 形状が点を含むか（壁は壁厚、開口部は開口幅の範囲を含む）
+pub fn contains_point(geometry: &Geometry, p: Point) -> bool {
+    let point = Shape {
+        vertices: vec![p],
+        closed: false,
+        radius: 0.0,
+    };
+    Shape::of(geometry).distance_to(&point) <= CONTAINS_TOLERANCE
+}
+
+// 2つの形状の最短距離（重なっていれば 0）
+pub fn distance_between(a: &Geometry, b: &Geometry) -> f64 {
+    Shape::of(a).distance_to(&Shape::of(b))
+}
+
+// 距離計算用の形状（閉じた輪郭、または芯線とその太さの半分）
+struct Shape {
+    vertices: Vec<Point>,
+    closed: bool,
+    radius: f64,
+}
+
+impl Shape {
+    fn of(geometry: &Geometry) -> Self {
+        if let Some(vertices) = geometry.outline() {
+            return Self {
+                vertices,
+                closed: true,
+                radius: 0.0,
+            };
+        }
+        match geometry {
+            Geometry::Polyline { points, thickness } => Self {
+                vertices: points.clone(),
+                closed: false,
+                radius: thickness / 2.0,
+            },
+            Geometry::Point { x, y, width, .. } => Self {
+                vertices: vec![Point { x: *x, y: *y }],
+                closed: false,
+                radius: width.unwrap_or(0.0) / 2.0,
+            },
+            Geometry::Rect { .. } | Geometry::RotatedRect { .. } | Geometry::Polygon { .. } => {
+                unreachable!("closed shapes have an outline")
+            }
+        }
+    }
+
+    fn segments(&self) -> Vec<(Point, Point)> {
+        let n = self.vertices.len();
+        if n == 1 {
+            return vec![(self.vertices[0], self.vertices[0])];
+        }
+        let count = if self.closed { n } else { n - 1 };
+        (0..count)
+            .map(|i| (self.vertices[i], self.vertices[(i + 1) % n]))
+            .collect()
+    }
+
+    // 閉じた輪郭の内側にあるか（境界上の判定は線分間の距離で行う）
+    fn encloses(&self, p: Point) -> bool {
+        if !self.closed {
+            return false;
+        }
+        let mut inside = false;
+        for (a, b) in self.segments() {
+            if (a.y > p.y) != (b.y > p.y) && p.x < a.x + (p.y - a.y) / (b.y - a.y) * (b.x - a.x) {
+                inside = !inside;
+            }
+        }
+        inside
+    }
+
+    fn distance_to(&self, other: &Shape) -> f64 {
+        if other.vertices.iter().any(|&p| self.encloses(p))
+            || self.vertices.iter().any(|&p| other.encloses(p))
+        {
+            return 0.0;
+        }
+        let axis_distance = self
+            .segments()
+            .iter()
+            .flat_map(|&a| other.segments().into_iter().map(move |b| segment_distance(a, b)))
+            .fold(f64::INFINITY, f64::min);
+        (axis_distance - self.radius - other.radius).max(0.0)
+    }
+}
+
+// 2つの線分の最短距離（交差していれば 0）
+fn segment_distance((a, b): (Point, Point), (c, d): (Point, Point)) -> f64 {
+    let cross = |o: Point, p: Point, q: Point| (p.x - o.x) * (q.y - o.y) - (p.y - o.y) * (q.x - o.x);
+    let (d1, d2) = (cross(a, b, c), cross(a, b, d));
+    let (d3, d4) = (cross(c, d, a), cross(c, d, b));
+    if d1 * d2 < 0.0 && d3 * d4 < 0.0 {
+        return 0.0;
+    }
+    [
+        project_onto_polyline(&[c, d], a).0,
+        project_onto_polyline(&[c, d], b).0,
+        project_onto_polyline(&[a, b], c).0,
+        project_onto_polyline(&[a, b], d).0,
+    ]
+    .into_iter()
+    .fold(f64::INFINITY, f64::min)
+}
@@ -1,7 +1,6 @@
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
 use time::OffsetDateTime;
-use uuid::Uuid;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -17,25 +16,13 @@ pub struct View {
     pub project_id: String,
     pub view_type: String,
     pub state: serde_json::Value,
+    pub version: i32,
     pub created_at: OffsetDateTime,
     pub updated_at: OffsetDateTime,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct UpdateView {
+    pub version: Option<i32>,
     pub state: ViewState,
 }
-
-impl View {
-    pub fn new(project_id: String, view_type: String, state: ViewState) -> Self {
-        let now = OffsetDateTime::now_utc();
-        Self {
-            id: Uuid::new_v4().to_string(),
-            project_id,
-            view_type,
-            state: serde_json::to_value(state).unwrap(),
-            created_at: now,
-            updated_at: now,
-        }
-    }
-} 
\ No newline at end of file
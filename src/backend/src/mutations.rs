@@ -0,0 +1,360 @@
+use sqlx::{Sqlite, SqliteConnection, Transaction};
+use time::OffsetDateTime;
+
+use crate::{
+    access,
+    auth::AuthUser,
+    clashes,
+    db,
+    error::{AppError, Result},
+    hosting,
+    models::{
+        change_set::ChangeSet,
+        clash::Clash,
+        element::{CreateElement, Element, UpdateElement},
+        event::{Event, NewEvent, ENTITY_VIEW},
+        history::{CHANGE_CREATE, CHANGE_DELETE, CHANGE_UPDATE},
+        member::{Permission, ProjectRole},
+        relationship::{CreateRelationship, Relationship, UpdateRelationship},
+        view::{UpdateView, View},
+    },
+    propagation,
+    websocket::WebSocketMessage,
+    AppState,
+};
+
+// 要素・関係性・ビューの変更処理（REST と WebSocket で共通）
+// 変更は呼び出し側のトランザクション内でイベントとして記録し、コミット後に publish で通知する
+pub struct Mutation<'a> {
+    state: &'a AppState,
+    project_id: &'a str,
+    user_id: &'a str,
+    role: ProjectRole,
+    events: Vec<Event>,
+    description: Option<String>,
+    // 形状を変更した要素ごとの干渉（INCREMENTAL_CLASH_DETECTION の指定時のみ）
+    clashes: Vec<(String, Vec<Clash>)>,
+}
+
+impl<'a> Mutation<'a> {
+    pub async fn new(state: &'a AppState, user: &'a AuthUser, project_id: &'a str) -> Result<Self> {
+        let role = access::authorize(&state.db, project_id, user, Permission::View).await?;
+        Ok(Self {
+            state,
+            project_id,
+            user_id: &user.id,
+            role,
+            events: Vec::new(),
+            description: None,
+            clashes: Vec::new(),
+        })
+    }
+
+    pub async fn create_element(
+        &mut self,
+        conn: &mut SqliteConnection,
+        data: CreateElement,
+    ) -> Result<Element> {
+        self.require(Permission::Edit)?;
+
+        // 要素の作成と履歴の記録を同一トランザクションで行う
+        let element = db::create_element(&mut *conn, self.project_id, data, self.user_id).await?;
+        self.default_description(|| format!("Create {}", element.element_type));
+        let event = db::record_element_change(
+            &mut *conn,
+            self.project_id,
+            &element.id,
+            CHANGE_CREATE,
+            None,
+            Some(serde_json::to_value(&element)?),
+            self.user_id,
+        )
+        .await?;
+        let rules = propagation::rules_for(&mut *conn, &self.state.rules, self.project_id).await?;
+        self.events.push(event);
+        let (element, derived) =
+            propagation::propagate(&mut *conn, &rules, None, element, self.user_id).await?;
+        self.events.extend(derived);
+
+        Ok(element)
+    }
+
+    pub async fn update_element(
+        &mut self,
+        conn: &mut SqliteConnection,
+        element_id: &str,
+        data: UpdateElement,
+        expected_version: Option<i32>,
+    ) -> Result<Element> {
+        self.state
+            .ws_manager
+            .ensure_unlocked(self.project_id, element_id, self.user_id)?;
+
+        // 更新前後の要素を履歴として同一トランザクションで記録
+        let before = db::get_element(&mut *conn, self.project_id, element_id).await?;
+
+        // 構造担当は構造プロパティのみ変更できる
+        let allowed = self.role.allows(Permission::Edit)
+            || (self.role == ProjectRole::StructuralEditor
+                && access::structural_only(&before, &data)?);
+        if !allowed {
+            return Err(AppError::Forbidden(format!(
+                "Role {} is not allowed to make this change",
+                self.role.as_str()
+            )));
+        }
+
+        let element = db::update_element(
+            &mut *conn,
+            self.project_id,
+            element_id,
+            data,
+            expected_version,
+            self.user_id,
+        )
+        .await?;
+        self.default_description(|| format!("Update {}", element.element_type));
+        let event = db::record_element_change(
+            &mut *conn,
+            self.project_id,
+            element_id,
+            CHANGE_UPDATE,
+            Some(serde_json::to_value(&before)?),
+            Some(serde_json::to_value(&element)?),
+            self.user_id,
+        )
+        .await?;
+
+        self.events.push(event);
+
+        // 開口部は保持している壁の上に留まり、壁の変更には開口部が追従する
+        hosting::check_opening_on_host(&mut *conn, &element).await?;
+        let moved =
+            hosting::reposition_hosted_openings(&mut *conn, &before, &element, self.user_id)
+                .await?;
+
+        // 他のビューへ伝播する値を導出する
+        let rules = propagation::rules_for(&mut *conn, &self.state.rules, self.project_id).await?;
+        let (element, derived) =
+            propagation::propagate(&mut *conn, &rules, Some(&before), element, self.user_id)
+                .await?;
+
+        self.events.extend(moved);
+        self.events.extend(derived);
+
+        if self.state.incremental_clashes
+            && (before.geometry != element.geometry || before.element_type != element.element_type)
+        {
+            let clashes = clashes::detect_element_clashes(&mut *conn, &element).await?;
+            self.clashes.push((element.id.clone(), clashes));
+        }
+
+        Ok(element)
+    }
+
+    pub async fn delete_element(&mut self, conn: &mut SqliteConnection, element_id: &str) -> Result<()> {
+        self.require(Permission::Edit)?;
+        self.state
+            .ws_manager
+            .ensure_unlocked(self.project_id, element_id, self.user_id)?;
+
+        // 削除前の要素を履歴として同一トランザクションで記録
+        let before = db::get_element(&mut *conn, self.project_id, element_id).await?;
+
+        // 要素の削除に連動して削除される関係性も履歴に残す
+        let relationships = db::list_element_relationships(&mut *conn, self.project_id, element_id).await?;
+        for relationship in &relationships {
+            let event = db::record_relationship_change(
+                &mut *conn,
+                self.project_id,
+                &relationship.id,
+                CHANGE_DELETE,
+                Some(serde_json::to_value(relationship)?),
+                None,
+                self.user_id,
+            )
+            .await?;
+            self.events.push(event);
+        }
+
+        db::delete_element(&mut *conn, self.project_id, element_id, self.user_id).await?;
+        self.default_description(|| format!("Delete {}", before.element_type));
+        let event = db::record_element_change(
+            &mut *conn,
+            self.project_id,
+            element_id,
+            CHANGE_DELETE,
+            Some(serde_json::to_value(&before)?),
+            None,
+            self.user_id,
+        )
+        .await?;
+        self.events.push(event);
+
+        Ok(())
+    }
+
+    pub async fn create_relationship(
+        &mut self,
+        conn: &mut SqliteConnection,
+        data: CreateRelationship,
+    ) -> Result<Relationship> {
+        self.require(Permission::Edit)?;
+
+        let relationship = db::create_relationship(&mut *conn, self.project_id, data).await?;
+        self.default_description(|| format!("Create {} relationship", relationship.relationship_type));
+        hosting::check_hosting(&mut *conn, &relationship).await?;
+        let event = db::record_relationship_change(
+            &mut *conn,
+            self.project_id,
+            &relationship.id,
+            CHANGE_CREATE,
+            None,
+            Some(serde_json::to_value(&relationship)?),
+            self.user_id,
+        )
+        .await?;
+
+        self.events.push(event);
+
+        Ok(relationship)
+    }
+
+    pub async fn update_relationship(
+        &mut self,
+        conn: &mut SqliteConnection,
+        relationship_id: &str,
+        data: UpdateRelationship,
+        expected_version: Option<i32>,
+    ) -> Result<Relationship> {
+        self.require(Permission::Edit)?;
+
+        let before = db::get_relationship(&mut *conn, self.project_id, relationship_id).await?;
+        let relationship =
+            db::update_relationship(&mut *conn, self.project_id, relationship_id, data, expected_version).await?;
+        self.default_description(|| format!("Update {} relationship", relationship.relationship_type));
+        hosting::check_hosting(&mut *conn, &relationship).await?;
+        let event = db::record_relationship_change(
+            &mut *conn,
+            self.project_id,
+            relationship_id,
+            CHANGE_UPDATE,
+            Some(serde_json::to_value(&before)?),
+            Some(serde_json::to_value(&relationship)?),
+            self.user_id,
+        )
+        .await?;
+
+        self.events.push(event);
+
+        Ok(relationship)
+    }
+
+    pub async fn delete_relationship(
+        &mut self,
+        conn: &mut SqliteConnection,
+        relationship_id: &str,
+    ) -> Result<()> {
+        self.require(Permission::Edit)?;
+
+        let before = db::get_relationship(&mut *conn, self.project_id, relationship_id).await?;
+        db::delete_relationship(&mut *conn, self.project_id, relationship_id).await?;
+        self.default_description(|| format!("Delete {} relationship", before.relationship_type));
+        let event = db::record_relationship_change(
+            &mut *conn,
+            self.project_id,
+            relationship_id,
+            CHANGE_DELETE,
+            Some(serde_json::to_value(&before)?),
+            None,
+            self.user_id,
+        )
+        .await?;
+
+        self.events.push(event);
+
+        Ok(())
+    }
+
+    pub async fn update_view(
+        &mut self,
+        conn: &mut SqliteConnection,
+        view_type: &str,
+        data: UpdateView,
+        expected_version: Option<i32>,
+    ) -> Result<View> {
+        self.require(Permission::Edit)?;
+
+        let before = db::get_view(&mut *conn, self.project_id, view_type).await?;
+        let view =
+            db::update_view(&mut *conn, self.project_id, view_type, data, expected_version).await?;
+        self.default_description(|| format!("Update {} view", view_type));
+        let event = NewEvent::new(self.project_id, ENTITY_VIEW, view_type, CHANGE_UPDATE, self.user_id)
+            .with_states(
+                Some(serde_json::to_value(&before)?),
+                Some(serde_json::to_value(&view)?),
+            );
+        self.events.push(db::append_event(&mut *conn, event).await?);
+
+        Ok(view)
+    }
+
+    // 変更グループの説明（省略時は最初の操作から決める）
+    pub fn describe(&mut self, description: String) {
+        self.description = Some(description);
+    }
+
+    // 記録した変更を一つの変更グループにまとめてコミットする
+    // 伝播や開口部の追従などの副次的な変更も、操作と一緒に取り消せる
+    pub async fn commit(&mut self, mut tx: Transaction<'_, Sqlite>) -> Result<Option<ChangeSet>> {
+        self.state
+            .ws_manager
+            .ensure_events_unlocked(self.project_id, &self.events, self.user_id)?;
+        let change_set = match &self.description {
+            Some(description) if !self.events.is_empty() => Some(
+                db::create_change_set(
+                    &mut tx,
+                    self.project_id,
+                    description,
+                    self.user_id,
+                    &mut self.events,
+                )
+                .await?,
+            ),
+            _ => None,
+        };
+        tx.commit().await?;
+        Ok(change_set)
+    }
+
+    // コミット後に、記録したイベントと検出した干渉をプロジェクトの接続へ通知する
+    pub fn publish(self) {
+        self.state.ws_manager.publish(self.project_id, self.events);
+        for (element_id, clashes) in self.clashes {
+            self.state.ws_manager.broadcast(
+                self.project_id,
+                WebSocketMessage::ClashDetected {
+                    project_id: self.project_id.to_string(),
+                    element_id,
+                    clashes,
+                    timestamp: OffsetDateTime::now_utc().to_string(),
+                    user_id: self.user_id.to_string(),
+                },
+            );
+        }
+    }
+
+    fn default_description(&mut self, describe: impl FnOnce() -> String) {
+        self.description.get_or_insert_with(describe);
+    }
+
+    fn require(&self, permission: Permission) -> Result<()> {
+        if !self.role.allows(permission) {
+            return Err(AppError::Forbidden(format!(
+                "Role {} is not allowed to perform this operation",
+                self.role.as_str()
+            )));
+        }
+        Ok(())
+    }
+}
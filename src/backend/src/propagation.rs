@@ -0,0 +1,336 @@
+use std::collections::HashSet;
+
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{json, Value as JsonValue};
+use sqlx::SqliteConnection;
+
+use crate::{
+    db,
+    error::{AppError, Result},
+    models::{
+        element::{
+            ArchitecturalProperties, Element, StructuralProperties, ELEMENT_TYPE_ROOM,
+            ELEMENT_TYPE_WALL,
+        },
+        event::{Event, NewEvent, CHANGE_PROPAGATE},
+        history::{CHANGE_UPDATE, ENTITY_ELEMENT},
+        propagation::{DerivedChange, PropagationRule, Quantity, RuleAction, RuleCondition},
+    },
+};
+
+// 標準の伝播ルール
+pub fn default_rules() -> Vec<PropagationRule> {
+    let finish = |room_type: &str, finish: &str| PropagationRule {
+        name: format!("room-finish-{}", room_type),
+        element_type: Some(ELEMENT_TYPE_ROOM.to_string()),
+        when: vec![RuleCondition {
+            path: "properties.floorPlan.roomType".to_string(),
+            equals: json!(room_type),
+        }],
+        then: vec![RuleAction::Set {
+            path: "properties.architectural.finish".to_string(),
+            value: json!(finish),
+        }],
+    };
+
+    vec![
+        PropagationRule {
+            name: "wall-span".to_string(),
+            element_type: Some(ELEMENT_TYPE_WALL.to_string()),
+            when: Vec::new(),
+            then: vec![RuleAction::Derive {
+                path: "properties.structural.span".to_string(),
+                from: Quantity::WallLength,
+            }],
+        },
+        finish("浴室", "タイル"),
+        finish("トイレ", "タイル"),
+        finish("和室", "畳"),
+    ]
+}
+
+// PROPAGATION_RULES に指定されたJSONファイルからルールを読み込む（未指定なら標準ルール）
+pub fn load_rules() -> std::result::Result<Vec<PropagationRule>, Box<dyn std::error::Error>> {
+    match std::env::var("PROPAGATION_RULES") {
+        Ok(path) => {
+            let rules: Vec<PropagationRule> = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+            validate_rules(&rules).map_err(|e| e.to_string())?;
+            Ok(rules)
+        }
+        Err(_) => Ok(default_rules()),
+    }
+}
+
+// プロジェクトに登録されたルール（未登録なら標準ルール）
+pub async fn rules_for(
+    conn: &mut SqliteConnection,
+    defaults: &[PropagationRule],
+    project_id: &str,
+) -> Result<Vec<PropagationRule>> {
+    Ok(match db::get_rule_set(&mut *conn, project_id).await? {
+        Some(rule_set) => rule_set.rules,
+        None => defaults.to_vec(),
+    })
+}
+
+// ルールの書き込み先は構造・意匠のプロパティに限り、値の型も検証する
+pub fn validate_rules(rules: &[PropagationRule]) -> Result<()> {
+    let mut names = HashSet::new();
+    for rule in rules {
+        if rule.name.trim().is_empty() {
+            return Err(AppError::InvalidRequest("Rule name must not be empty".to_string()));
+        }
+        if !names.insert(rule.name.as_str()) {
+            return Err(AppError::InvalidRequest(format!(
+                "Duplicate rule name: {}",
+                rule.name
+            )));
+        }
+        if rule.then.is_empty() {
+            return Err(AppError::InvalidRequest(format!(
+                "Rule {} has no actions",
+                rule.name
+            )));
+        }
+        for condition in &rule.when {
+            if condition.path.split('.').any(str::is_empty) {
+                return Err(AppError::InvalidRequest(format!(
+                    "Rule {} has an invalid condition path: {}",
+                    rule.name, condition.path
+                )));
+            }
+        }
+        for action in &rule.then {
+            let path = action_path(action);
+            let value = match action {
+                RuleAction::Set { value, .. } => value.clone(),
+                RuleAction::Derive { .. } => json!(0.0),
+            };
+            check_target(path, &value).map_err(|message| {
+                AppError::InvalidRequest(format!("Rule {} {}: {}", rule.name, message, path))
+            })?;
+        }
+    }
+    Ok(())
+}
+
+// 変更前後の要素にルールを適用し、導出後の要素と変更内容を返す
+pub fn evaluate(
+    rules: &[PropagationRule],
+    before: Option<&Element>,
+    element: &Element,
+) -> Result<(Element, Vec<DerivedChange>)> {
+    let old = before.map(serde_json::to_value).transpose()?;
+    let mut value = serde_json::to_value(element)?;
+    let mut changes = Vec::new();
+
+    for rule in rules {
+        if !applies(rule, old.as_ref(), &value) {
+            continue;
+        }
+        for action in &rule.then {
+            let new = match action {
+                RuleAction::Set { value, .. } => value.clone(),
+                RuleAction::Derive { from, .. } => match measure(element, *from) {
+                    Some(quantity) => json!(quantity),
+                    None => continue,
+                },
+            };
+            let path = action_path(action);
+            let current = lookup(&value, path).cloned();
+            if current.as_ref() == Some(&new) {
+                continue;
+            }
+            assign(&mut value, path, new.clone());
+            changes.push(DerivedChange {
+                rule: rule.name.clone(),
+                element_id: element.id.clone(),
+                path: path.to_string(),
+                old: current.filter(|v| !v.is_null()),
+                new,
+            });
+        }
+    }
+
+    Ok((serde_json::from_value(value)?, changes))
+}
+
+// ルールを評価し、導出された変更があれば保存して履歴に記録する
+pub async fn propagate(
+    conn: &mut SqliteConnection,
+    rules: &[PropagationRule],
+    before: Option<&Element>,
+    element: Element,
+    user_id: &str,
+) -> Result<(Element, Vec<Event>)> {
+    let (derived, changes) = evaluate(rules, before, &element)?;
+    if changes.is_empty() {
+        return Ok((element, Vec::new()));
+    }
+
+    let saved = db::restore_element(&mut *conn, &derived, user_id).await?;
+    let updated = db::record_element_change(
+        &mut *conn,
+        &saved.project_id,
+        &saved.id,
+        CHANGE_UPDATE,
+        Some(serde_json::to_value(&element)?),
+        Some(serde_json::to_value(&saved)?),
+        user_id,
+    )
+    .await?;
+
+    // 導出内容そのものも通知用に記録する
+    let mut event = NewEvent::new(&saved.project_id, ENTITY_ELEMENT, &saved.id, CHANGE_PROPAGATE, user_id);
+    event.payload = json!({ "changes": changes });
+    let propagated = db::append_event(&mut *conn, event).await?;
+
+    Ok((saved, vec![updated, propagated]))
+}
+
+// 書き込み先が構造・意匠のプロパティの項目であり、値がその項目の型に合うか
+fn check_target(path: &str, value: &JsonValue) -> std::result::Result<(), &'static str> {
+    let segments: Vec<&str> = path.split('.').collect();
+    let ["properties", group, field] = segments[..] else {
+        return Err("may only write structural or architectural properties");
+    };
+    match group {
+        "structural" => check_field::<StructuralProperties>(field, value),
+        "architectural" => check_field::<ArchitecturalProperties>(field, value),
+        _ => Err("may only write structural or architectural properties"),
+    }
+}
+
+fn check_field<T: Serialize + DeserializeOwned>(
+    field: &str,
+    value: &JsonValue,
+) -> std::result::Result<(), &'static str> {
+    let empty: T = serde_json::from_value(json!({})).map_err(|_| "cannot write this property")?;
+    let mut fields = serde_json::to_value(empty).map_err(|_| "cannot write this property")?;
+    match fields.get_mut(field) {
+        Some(slot) => *slot = value.clone(),
+        None => return Err("writes an unknown property"),
+    }
+    serde_json::from_value::<T>(fields)
+        .map(|_| ())
+        .map_err(|_| "sets a value of the wrong type for")
+}
+
+fn action_path(action: &RuleAction) -> &str {
+    match action {
+        RuleAction::Set { path, .. } | RuleAction::Derive { path, .. } => path,
+    }
+}
+
+// 条件が成り立ち、かつ新規作成か入力が変化した場合に適用する
+fn applies(rule: &PropagationRule, old: Option<&JsonValue>, new: &JsonValue) -> bool {
+    if let Some(element_type) = &rule.element_type {
+        if new["element_type"] != *element_type {
+            return false;
+        }
+    }
+    if !rule.when.iter().all(|c| lookup(new, &c.path) == Some(&c.equals)) {
+        return false;
+    }
+
+    let Some(old) = old else {
+        return true;
+    };
+    let derives = rule.then.iter().any(|a| matches!(a, RuleAction::Derive { .. }));
+    old["element_type"] != new["element_type"]
+        || (derives && old["geometry"] != new["geometry"])
+        || rule.when.iter().any(|c| lookup(old, &c.path) != lookup(new, &c.path))
+}
+
+fn measure(element: &Element, quantity: Quantity) -> Option<f64> {
+    match quantity {
+        Quantity::WallLength => {
+            let (axis, _) = element.geometry.wall_axis()?;
+            Some(axis.windows(2).map(|s| (s[1].x - s[0].x).hypot(s[1].y - s[0].y)).sum())
+        }
+        Quantity::WallThickness => element.geometry.wall_axis().map(|(_, t)| t),
+        Quantity::Area | Quantity::Perimeter => {
+            let floor_plan = element.properties.get("floorPlan")?;
+            let key = if quantity == Quantity::Area { "area" } else { "perimeter" };
+            floor_plan.get(key)?.as_f64()
+        }
+    }
+}
+
+fn lookup<'a>(value: &'a JsonValue, path: &str) -> Option<&'a JsonValue> {
+    path.split('.').try_fold(value, |v, key| v.get(key))
+}
+
+// 途中のオブジェクトがなければ作成して値を設定する
+fn assign(value: &mut JsonValue, path: &str, new: JsonValue) {
+    let mut current = value;
+    for key in path.split('.') {
+        if !current.is_object() {
+            *current = json!({});
+        }
+        current = current
+            .as_object_mut()
+            .expect("object")
+            .entry(key)
+            .or_insert(JsonValue::Null);
+    }
+    *current = new;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(path: &str, value: JsonValue) -> PropagationRule {
+        PropagationRule {
+            name: "rule".to_string(),
+            element_type: None,
+            when: Vec::new(),
+            then: vec![RuleAction::Set { path: path.to_string(), value }],
+        }
+    }
+
+    #[test]
+    fn accepts_default_rules() {
+        assert!(validate_rules(&default_rules()).is_ok());
+    }
+
+    #[test]
+    fn accepts_typed_structural_and_architectural_values() {
+        assert!(validate_rules(&[rule("properties.structural.load", json!(12))]).is_ok());
+        assert!(validate_rules(&[rule("properties.structural.structureType", json!("RC"))]).is_ok());
+        assert!(validate_rules(&[rule("properties.architectural.material", json!(null))]).is_ok());
+    }
+
+    #[test]
+    fn rejects_targets_outside_structural_and_architectural() {
+        for path in [
+            "properties.common.locked",
+            "properties.common.allowedUsers",
+            "properties.floorPlan.area",
+            "properties.structural",
+            "properties.structural.span.value",
+            "geometry.x",
+        ] {
+            assert!(validate_rules(&[rule(path, json!(1))]).is_err(), "{}", path);
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_fields_and_wrong_types() {
+        assert!(validate_rules(&[rule("properties.structural.colour", json!("red"))]).is_err());
+        assert!(validate_rules(&[rule("properties.structural.load", json!("heavy"))]).is_err());
+        assert!(validate_rules(&[rule("properties.architectural.finish", json!(3))]).is_err());
+
+        let derive = PropagationRule {
+            name: "derive".to_string(),
+            element_type: None,
+            when: Vec::new(),
+            then: vec![RuleAction::Derive {
+                path: "properties.architectural.finish".to_string(),
+                from: Quantity::Area,
+            }],
+        };
+        assert!(validate_rules(&[derive]).is_err());
+    }
+}
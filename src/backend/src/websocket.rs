@@ -4,14 +4,43 @@ use std::{
     time::Duration,
 };
 use axum::{
-    extract::{Path, State, WebSocketUpgrade},
-    response::Response,
+    extract::{Path, Query, State, WebSocketUpgrade},
+    response::{IntoResponse, Response},
+};
+use futures::{
+    sink::SinkExt,
+    stream::{SplitSink, StreamExt},
 };
-use futures::{sink::SinkExt, stream::StreamExt};
 use serde::{Deserialize, Serialize};
-use tokio::{sync::broadcast, time::timeout};
+use sqlx::SqlitePool;
+use tokio::{
+    sync::{broadcast, broadcast::error::RecvError, mpsc},
+    time::timeout,
+};
 use axum::extract::ws::{Message, WebSocket};
+use time::OffsetDateTime;
 use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::{
+    access,
+    auth::AuthUser,
+    db,
+    error::{AppError, Result},
+    models::{
+        batch::MAX_BATCH_OPERATIONS,
+        clash::Clash,
+        element::{CreateElement, Point, UpdateElement},
+        event::{Event, CHANGE_PROPAGATE, ENTITY_LAYER, ENTITY_PROJECT, ENTITY_VIEW},
+        history::{ENTITY_ELEMENT, ENTITY_RELATIONSHIP},
+        member::{Permission, ProjectRole},
+        presence::Presence,
+        relationship::{CreateRelationship, UpdateRelationship},
+        view::{UpdateView, ViewState},
+    },
+    mutations::Mutation,
+    AppState,
+};
 
 // WebSocketメッセージの型定義
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -37,6 +66,142 @@ pub enum WebSocketMessage {
         timestamp: String,
         user_id: String,
     },
+    RelationshipDelete {
+        id: String,
+        project_id: String,
+        timestamp: String,
+        user_id: String,
+    },
+    // ルールによって導出された変更
+    PropagationApplied {
+        id: String,
+        project_id: String,
+        changes: serde_json::Value,
+        timestamp: String,
+        user_id: String,
+    },
+    LayerUpdate {
+        project_id: String,
+        name: String,
+        data: serde_json::Value,
+        timestamp: String,
+        user_id: String,
+    },
+    // 要素の一時ロック（ドラッグ中など）の取得・解放
+    // クライアントからは timestamp と user_id を省略できる
+    LockAcquire {
+        project_id: String,
+        element_ids: Vec<String>,
+        #[serde(default)]
+        timestamp: String,
+        #[serde(default)]
+        user_id: String,
+    },
+    LockRelease {
+        project_id: String,
+        element_ids: Vec<String>,
+        #[serde(default)]
+        timestamp: String,
+        #[serde(default)]
+        user_id: String,
+    },
+    // ロックを取得できなかった要素と保持者（要求元にのみ送信）
+    LockDenied {
+        project_id: String,
+        element_ids: Vec<String>,
+        held_by: String,
+        timestamp: String,
+        user_id: String,
+    },
+    // 在席情報（参加・退出・カーソルや選択の変更）
+    PresenceJoin {
+        project_id: String,
+        presence: Presence,
+        timestamp: String,
+        user_id: String,
+    },
+    PresenceLeave {
+        project_id: String,
+        connection_id: String,
+        timestamp: String,
+        user_id: String,
+    },
+    // クライアントからは変更する項目のみ送る（省略した項目は維持）
+    PresenceUpdate {
+        project_id: String,
+        #[serde(default)]
+        cursor: Option<Point>,
+        #[serde(default)]
+        selection: Option<Vec<String>>,
+        #[serde(default)]
+        view_type: Option<String>,
+        #[serde(default)]
+        connection_id: String,
+        #[serde(default)]
+        timestamp: String,
+        #[serde(default)]
+        user_id: String,
+    },
+    // 接続直後に送る在席者一覧（要求元にのみ送信）
+    PresenceRoster {
+        project_id: String,
+        users: Vec<Presence>,
+        timestamp: String,
+        user_id: String,
+    },
+    // クライアントから送られた変更の保存結果（要求元にのみ送信）
+    Ack {
+        request_id: Option<String>,
+        project_id: String,
+        id: String,
+        data: serde_json::Value,
+        timestamp: String,
+        user_id: String,
+    },
+    Error {
+        request_id: Option<String>,
+        project_id: String,
+        code: u16,
+        message: String,
+        // バージョン競合時のサーバー上のデータ
+        current: Option<serde_json::Value>,
+        timestamp: String,
+        user_id: String,
+    },
+    // 取りこぼしが多すぎて再送できない（REST で全体を取得し直し、latest_seq から再接続する）
+    ResyncRequired {
+        project_id: String,
+        latest_seq: i64,
+        timestamp: String,
+        user_id: String,
+    },
+    // 一括操作でまとめて適用した変更（changes は個々の変更の通知）
+    BatchApplied {
+        project_id: String,
+        change_set_id: String,
+        changes: Vec<WebSocketMessage>,
+        timestamp: String,
+        user_id: String,
+    },
+    // 更新された要素が関わる干渉（空なら干渉は解消済み）
+    ClashDetected {
+        project_id: String,
+        element_id: String,
+        clashes: Vec<Clash>,
+        timestamp: String,
+        user_id: String,
+    },
+    ProjectUpdate {
+        project_id: String,
+        data: serde_json::Value,
+        timestamp: String,
+        user_id: String,
+    },
+    ProjectDelete {
+        project_id: String,
+        timestamp: String,
+        user_id: String,
+    },
     ViewUpdate {
         project_id: String,
         view_type: String,
@@ -44,36 +209,397 @@ pub enum WebSocketMessage {
         timestamp: String,
         user_id: String,
     },
+    ViewDelete {
+        project_id: String,
+        view_type: String,
+        timestamp: String,
+        user_id: String,
+    },
 }
 
-// エラー型の定義
-#[derive(Debug, thiserror::Error)]
-pub enum WebSocketError {
-    #[error("Failed to parse message: {0}")]
-    ParseError(String),
-    #[error("Failed to send message: {0}")]
-    SendError(String),
-    #[error("Connection timeout")]
-    Timeout,
-    #[error("Connection closed")]
-    Closed,
+impl WebSocketMessage {
+    pub fn project_id(&self) -> &str {
+        match self {
+            WebSocketMessage::ElementUpdate { project_id, .. }
+            | WebSocketMessage::ElementDelete { project_id, .. }
+            | WebSocketMessage::RelationshipUpdate { project_id, .. }
+            | WebSocketMessage::RelationshipDelete { project_id, .. }
+            | WebSocketMessage::PropagationApplied { project_id, .. }
+            | WebSocketMessage::LayerUpdate { project_id, .. }
+            | WebSocketMessage::LockAcquire { project_id, .. }
+            | WebSocketMessage::LockRelease { project_id, .. }
+            | WebSocketMessage::LockDenied { project_id, .. }
+            | WebSocketMessage::PresenceJoin { project_id, .. }
+            | WebSocketMessage::PresenceLeave { project_id, .. }
+            | WebSocketMessage::PresenceUpdate { project_id, .. }
+            | WebSocketMessage::PresenceRoster { project_id, .. }
+            | WebSocketMessage::Ack { project_id, .. }
+            | WebSocketMessage::Error { project_id, .. }
+            | WebSocketMessage::ResyncRequired { project_id, .. }
+            | WebSocketMessage::BatchApplied { project_id, .. }
+            | WebSocketMessage::ClashDetected { project_id, .. }
+            | WebSocketMessage::ProjectUpdate { project_id, .. }
+            | WebSocketMessage::ProjectDelete { project_id, .. }
+            | WebSocketMessage::ViewUpdate { project_id, .. }
+            | WebSocketMessage::ViewDelete { project_id, .. } => project_id,
+        }
+    }
+
+    // サーバーのみが送信するメッセージか
+    pub fn is_server_only(&self) -> bool {
+        matches!(
+            self,
+            WebSocketMessage::PropagationApplied { .. }
+                | WebSocketMessage::LayerUpdate { .. }
+                | WebSocketMessage::LockDenied { .. }
+                | WebSocketMessage::PresenceJoin { .. }
+                | WebSocketMessage::PresenceLeave { .. }
+                | WebSocketMessage::PresenceRoster { .. }
+                | WebSocketMessage::Ack { .. }
+                | WebSocketMessage::Error { .. }
+                | WebSocketMessage::ResyncRequired { .. }
+                | WebSocketMessage::BatchApplied { .. }
+                | WebSocketMessage::ClashDetected { .. }
+                | WebSocketMessage::ProjectUpdate { .. }
+                | WebSocketMessage::ProjectDelete { .. }
+                | WebSocketMessage::ViewDelete { .. }
+        )
+    }
+
+    // イベントから通知メッセージを導出する（通知しないイベントは None）
+    pub fn from_event(event: &Event) -> Option<Self> {
+        let id = event.entity_id.clone();
+        let project_id = event.project_id.clone();
+        let timestamp = event.created_at.to_string();
+        let user_id = event.actor.clone();
+
+        let message = match (event.entity_type.as_str(), event.after()) {
+            (ENTITY_ELEMENT, _) if event.change_type == CHANGE_PROPAGATE => {
+                WebSocketMessage::PropagationApplied {
+                    id,
+                    project_id,
+                    changes: event.payload.get("changes").cloned().unwrap_or_default(),
+                    timestamp,
+                    user_id,
+                }
+            }
+            (ENTITY_ELEMENT, Some(after)) => WebSocketMessage::ElementUpdate {
+                id,
+                project_id,
+                data: after.clone(),
+                timestamp,
+                user_id,
+            },
+            (ENTITY_ELEMENT, None) => WebSocketMessage::ElementDelete {
+                id,
+                project_id,
+                timestamp,
+                user_id,
+            },
+            (ENTITY_RELATIONSHIP, Some(after)) => WebSocketMessage::RelationshipUpdate {
+                id,
+                project_id,
+                data: after.clone(),
+                timestamp,
+                user_id,
+            },
+            (ENTITY_RELATIONSHIP, None) => WebSocketMessage::RelationshipDelete {
+                id,
+                project_id,
+                timestamp,
+                user_id,
+            },
+            (ENTITY_VIEW, Some(after)) => WebSocketMessage::ViewUpdate {
+                project_id,
+                view_type: id,
+                state: after.get("state").cloned().unwrap_or_default(),
+                timestamp,
+                user_id,
+            },
+            (ENTITY_VIEW, None) => WebSocketMessage::ViewDelete {
+                project_id,
+                view_type: id,
+                timestamp,
+                user_id,
+            },
+            (ENTITY_LAYER, Some(after)) => WebSocketMessage::LayerUpdate {
+                project_id,
+                name: id,
+                data: after.clone(),
+                timestamp,
+                user_id,
+            },
+            (ENTITY_PROJECT, Some(after)) => WebSocketMessage::ProjectUpdate {
+                project_id,
+                data: after.clone(),
+                timestamp,
+                user_id,
+            },
+            (ENTITY_PROJECT, None) => WebSocketMessage::ProjectDelete {
+                project_id,
+                timestamp,
+                user_id,
+            },
+            _ => return None,
+        };
+        Some(message)
+    }
+}
+
+// 配信するメッセージ（イベントから導出したものはその通し番号 seq を持つ）
+#[derive(Debug, Clone, Serialize)]
+pub struct Envelope {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seq: Option<i64>,
+    // 複数のイベントをまとめた通知では、最初のイベントの番号（seq は最後の番号）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub first_seq: Option<i64>,
+    #[serde(flatten)]
+    pub message: WebSocketMessage,
+}
+
+impl Envelope {
+    // イベントのない通知
+    fn unsequenced(message: WebSocketMessage) -> Self {
+        Self {
+            seq: None,
+            first_seq: None,
+            message,
+        }
+    }
+
+    // イベントを通知に変換する（同じ変更グループの連続したイベントは一つにまとめる）
+    fn from_events(events: &[Event]) -> Vec<Self> {
+        let mut envelopes = Vec::new();
+        for group in events.chunk_by(|a, b| {
+            a.change_set_id.is_some() && a.change_set_id == b.change_set_id
+        }) {
+            let (first, last) = (&group[0], &group[group.len() - 1]);
+            let Some(change_set_id) = &first.change_set_id else {
+                if let Some(message) = WebSocketMessage::from_event(first) {
+                    envelopes.push(Self {
+                        seq: Some(first.seq),
+                        first_seq: None,
+                        message,
+                    });
+                }
+                continue;
+            };
+            envelopes.push(Self {
+                seq: Some(last.seq),
+                first_seq: Some(first.seq),
+                message: WebSocketMessage::BatchApplied {
+                    project_id: first.project_id.clone(),
+                    change_set_id: change_set_id.clone(),
+                    changes: group.iter().filter_map(WebSocketMessage::from_event).collect(),
+                    timestamp: last.created_at.to_string(),
+                    user_id: first.actor.clone(),
+                },
+            });
+        }
+        envelopes
+    }
+
+    // 最初に含むイベントの番号
+    fn first(&self) -> Option<i64> {
+        self.first_seq.or(self.seq)
+    }
+}
+
+// 要素ロックの保持者
+#[derive(Debug, Clone)]
+struct LockHolder {
+    user_id: String,
+    connection_id: String,
 }
 
 // プロジェクトごとの接続管理
 #[derive(Debug, Default, Clone)]
 pub struct ConnectionManager {
-    connections: Arc<Mutex<HashMap<String, broadcast::Sender<WebSocketMessage>>>>,
+    connections: Arc<Mutex<HashMap<String, broadcast::Sender<Envelope>>>>,
+    // プロジェクトID → 要素ID → 保持者
+    locks: Arc<Mutex<HashMap<String, HashMap<String, LockHolder>>>>,
+    // プロジェクトID → 接続ID → 在席情報
+    presence: Arc<Mutex<HashMap<String, HashMap<String, Presence>>>>,
 }
 
 impl ConnectionManager {
     pub fn new() -> Self {
         Self {
             connections: Arc::new(Mutex::new(HashMap::new())),
+            locks: Arc::new(Mutex::new(HashMap::new())),
+            presence: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    pub fn join(&self, project_id: &str, presence: Presence) {
+        let mut rosters = self.presence.lock().unwrap();
+        rosters
+            .entry(project_id.to_string())
+            .or_default()
+            .insert(presence.connection_id.clone(), presence);
+    }
+
+    // 指定された項目のみ更新し、更新後の在席情報を返す
+    pub fn update_presence(
+        &self,
+        project_id: &str,
+        connection_id: &str,
+        cursor: Option<Point>,
+        selection: Option<Vec<String>>,
+        view_type: Option<String>,
+    ) -> Option<Presence> {
+        let mut rosters = self.presence.lock().unwrap();
+        let presence = rosters.get_mut(project_id)?.get_mut(connection_id)?;
+        if cursor.is_some() {
+            presence.cursor = cursor;
+        }
+        if let Some(selection) = selection {
+            presence.selection = selection;
+        }
+        if view_type.is_some() {
+            presence.view_type = view_type;
+        }
+        presence.updated_at = OffsetDateTime::now_utc();
+        Some(presence.clone())
+    }
+
+    pub fn leave(&self, project_id: &str, connection_id: &str) -> Option<Presence> {
+        let mut rosters = self.presence.lock().unwrap();
+        let roster = rosters.get_mut(project_id)?;
+        let presence = roster.remove(connection_id);
+        if roster.is_empty() {
+            rosters.remove(project_id);
         }
+        presence
     }
 
-    // プロジェクトのチャンネル取得（なければ作成）
-    pub fn get_or_create_channel(&self, project_id: &str) -> broadcast::Sender<WebSocketMessage> {
+    // 接続順の在席者一覧
+    pub fn roster(&self, project_id: &str) -> Vec<Presence> {
+        let rosters = self.presence.lock().unwrap();
+        let mut users: Vec<Presence> = rosters
+            .get(project_id)
+            .map(|roster| roster.values().cloned().collect())
+            .unwrap_or_default();
+        users.sort_by_key(|p| p.joined_at);
+        users
+    }
+
+    // 要素をまとめてロックする（他のユーザーが保持している要素があれば何もせずその保持者を返す）
+    pub fn acquire_locks(
+        &self,
+        project_id: &str,
+        element_ids: &[String],
+        user_id: &str,
+        connection_id: &str,
+    ) -> std::result::Result<(), (Vec<String>, String)> {
+        let mut locks = self.locks.lock().unwrap();
+        let project_locks = locks.entry(project_id.to_string()).or_default();
+
+        let held: Vec<(&String, &LockHolder)> = element_ids
+            .iter()
+            .filter_map(|id| project_locks.get(id).map(|holder| (id, holder)))
+            .filter(|(_, holder)| holder.user_id != user_id)
+            .collect();
+        if let Some((_, holder)) = held.first() {
+            let held_by = holder.user_id.clone();
+            let ids = held.iter().map(|(id, _)| (*id).clone()).collect();
+            return Err((ids, held_by));
+        }
+
+        for id in element_ids {
+            project_locks.insert(
+                id.clone(),
+                LockHolder {
+                    user_id: user_id.to_string(),
+                    connection_id: connection_id.to_string(),
+                },
+            );
+        }
+        Ok(())
+    }
+
+    // ユーザーが保持しているロックを解放し、解放した要素IDを返す
+    pub fn release_locks(&self, project_id: &str, element_ids: &[String], user_id: &str) -> Vec<String> {
+        let mut locks = self.locks.lock().unwrap();
+        let Some(project_locks) = locks.get_mut(project_id) else {
+            return Vec::new();
+        };
+        let released: Vec<String> = element_ids
+            .iter()
+            .filter(|id| project_locks.get(*id).is_some_and(|h| h.user_id == user_id))
+            .cloned()
+            .collect();
+        for id in &released {
+            project_locks.remove(id);
+        }
+        released
+    }
+
+    // 切断された接続が保持していたロックをすべて解放する
+    pub fn release_connection(&self, project_id: &str, connection_id: &str) -> Vec<String> {
+        let mut locks = self.locks.lock().unwrap();
+        let Some(project_locks) = locks.get_mut(project_id) else {
+            return Vec::new();
+        };
+        let released: Vec<String> = project_locks
+            .iter()
+            .filter(|(_, holder)| holder.connection_id == connection_id)
+            .map(|(id, _)| id.clone())
+            .collect();
+        for id in &released {
+            project_locks.remove(id);
+        }
+        if project_locks.is_empty() {
+            locks.remove(project_id);
+        }
+        released
+    }
+
+    // 他のユーザーがロックしている要素は変更できない
+    pub fn ensure_unlocked(&self, project_id: &str, element_id: &str, user_id: &str) -> Result<()> {
+        let locks = self.locks.lock().unwrap();
+        let holder = locks
+            .get(project_id)
+            .and_then(|project_locks| project_locks.get(element_id))
+            .filter(|holder| holder.user_id != user_id);
+        match holder {
+            Some(holder) => Err(AppError::Conflict(format!(
+                "Element {} is being edited by user {}",
+                element_id, holder.user_id
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    // 記録した変更に他のユーザーがロックしている要素が含まれていれば拒否する
+    // 取り消し・復元・マージ・開口部の追従など、複数の要素をまとめて変更する操作で使う
+    pub fn ensure_events_unlocked(&self, project_id: &str, events: &[Event], user_id: &str) -> Result<()> {
+        events
+            .iter()
+            .filter(|event| event.entity_type == ENTITY_ELEMENT)
+            .try_for_each(|event| self.ensure_unlocked(project_id, &event.entity_id, user_id))
+    }
+
+    // コミット済みのイベントを通知としてプロジェクトの接続へ配信する
+    // 配信順が前後しても、受信側は通し番号の飛びを検知して記録から再送する
+    pub fn publish(&self, project_id: &str, events: Vec<Event>) {
+        if events.is_empty() {
+            return;
+        }
+        let tx = self.get_or_create_channel(project_id);
+        for envelope in Envelope::from_events(&events) {
+            let _ = tx.send(envelope);
+        }
+    }
+
+    // イベントを伴わないメッセージ（在席情報・ロック・干渉）を配信する
+    pub fn broadcast(&self, project_id: &str, message: WebSocketMessage) {
+        let tx = self.get_or_create_channel(project_id);
+        let _ = tx.send(Envelope::unsequenced(message));
+    }
+
+    fn get_or_create_channel(&self, project_id: &str) -> broadcast::Sender<Envelope> {
         let mut connections = self.connections.lock().unwrap();
         if let Some(sender) = connections.get(project_id) {
             sender.clone()
@@ -86,7 +612,7 @@ impl ConnectionManager {
     }
 
     // プロジェクトのチャンネル削除
-    pub fn remove_channel(&self, project_id: &str) {
+    fn remove_channel(&self, project_id: &str) {
         let mut connections = self.connections.lock().unwrap();
         if connections.remove(project_id).is_some() {
             info!("Removed channel for project: {}", project_id);
@@ -94,54 +620,388 @@ impl ConnectionManager {
     }
 }
 
+// 再接続時に指定する、最後に受信したメッセージの通し番号
+#[derive(Debug, Deserialize)]
+pub struct ReplayQuery {
+    pub since: Option<i64>,
+}
+
 // WebSocket接続ハンドラ
 pub async fn handler(
     ws: WebSocketUpgrade,
     Path(project_id): Path<String>,
-    State(manager): State<ConnectionManager>,
+    Query(query): Query<ReplayQuery>,
+    State(state): State<AppState>,
+    user: AuthUser,
 ) -> Response {
-    info!("New WebSocket connection request for project: {}", project_id);
-    ws.on_upgrade(|socket| handle_socket(socket, project_id, manager))
+    info!(
+        "New WebSocket connection request for project: {} (user: {})",
+        project_id, user.username
+    );
+    if let Err(e) = access::authorize(&state.db, &project_id, &user, Permission::View).await {
+        return e.into_response();
+    }
+    ws.on_upgrade(move |socket| handle_socket(socket, state, project_id, user, query.since))
+}
+
+// 接続中のクライアント1つ分の状態
+struct Session {
+    state: AppState,
+    project_id: String,
+    user: AuthUser,
+    connection_id: String,
+    direct_tx: mpsc::UnboundedSender<Message>,
+}
+
+impl Session {
+    // 接続元クライアントにのみ送信する
+    fn reply(&self, msg: &WebSocketMessage) {
+        match serde_json::to_string(msg) {
+            Ok(text) => {
+                if let Err(e) = self.direct_tx.send(Message::Text(text)) {
+                    error!("Failed to send reply: {}", e);
+                }
+            }
+            Err(e) => error!("Failed to serialize message: {}", e),
+        }
+    }
+
+    fn reply_error(&self, request_id: Option<String>, error: AppError) {
+        let (status, message) = error.status_and_message();
+        let current = match error {
+            AppError::VersionConflict { current, .. } => Some(current),
+            _ => None,
+        };
+        self.reply(&WebSocketMessage::Error {
+            request_id,
+            project_id: self.project_id.clone(),
+            code: status.as_u16(),
+            message,
+            current,
+            timestamp: OffsetDateTime::now_utc().to_string(),
+            user_id: self.user.id.clone(),
+        });
+    }
+
+    async fn handle_text(&self, text: &str) {
+        // 要求IDはメッセージの最上位に任意で付けられ、応答にそのまま返す
+        let mut value = match serde_json::from_str::<serde_json::Value>(text) {
+            Ok(value) => value,
+            Err(e) => {
+                error!("Failed to parse message: {}", e);
+                self.reply_error(
+                    None,
+                    AppError::InvalidRequest(format!("Invalid message format: {}", e)),
+                );
+                return;
+            }
+        };
+        let request_id = value
+            .as_object_mut()
+            .and_then(|object| object.remove("request_id"))
+            .and_then(|id| id.as_str().map(str::to_string));
+
+        let msg = match serde_json::from_value::<WebSocketMessage>(value) {
+            Ok(msg) => msg,
+            Err(e) => {
+                error!("Failed to parse message: {}", e);
+                self.reply_error(
+                    request_id,
+                    AppError::InvalidRequest(format!("Invalid message format: {}", e)),
+                );
+                return;
+            }
+        };
+        if msg.project_id() != self.project_id {
+            self.reply_error(
+                request_id,
+                AppError::InvalidRequest(format!(
+                    "Message is for project {}, but this connection is for project {}",
+                    msg.project_id(),
+                    self.project_id
+                )),
+            );
+            return;
+        }
+
+        match msg {
+            WebSocketMessage::LockAcquire { element_ids, .. } => {
+                if let Err(e) = self.acquire_locks(element_ids).await {
+                    self.reply_error(request_id, e);
+                }
+            }
+            WebSocketMessage::LockRelease { element_ids, .. } => self.release_locks(element_ids),
+            WebSocketMessage::PresenceUpdate {
+                cursor,
+                selection,
+                view_type,
+                ..
+            } => self.update_presence(cursor, selection, view_type),
+            msg if msg.is_server_only() => self.reply_error(
+                request_id,
+                AppError::InvalidRequest(
+                    "This message type is sent by the server only".to_string(),
+                ),
+            ),
+            msg => match self.apply(msg).await {
+                Ok((id, data)) => self.reply(&WebSocketMessage::Ack {
+                    request_id,
+                    project_id: self.project_id.clone(),
+                    id,
+                    data,
+                    timestamp: OffsetDateTime::now_utc().to_string(),
+                    user_id: self.user.id.clone(),
+                }),
+                Err(e) => {
+                    warn!("Rejected message from user {}: {}", self.user.id, e);
+                    self.reply_error(request_id, e);
+                }
+            },
+        }
+    }
+
+    // 変更メッセージを REST と同じ処理で検証・保存し、対象のIDと保存後のデータを返す
+    // ElementUpdate / RelationshipUpdate は id が空なら作成、それ以外は更新として扱う
+    async fn apply(&self, msg: WebSocketMessage) -> Result<(String, serde_json::Value)> {
+        let mut mutation = Mutation::new(&self.state, &self.user, &self.project_id).await?;
+        let mut tx = self.state.db.begin().await?;
+
+        let result = match msg {
+            WebSocketMessage::ElementUpdate { id, data, .. } if id.is_empty() => {
+                let element = mutation
+                    .create_element(&mut tx, serde_json::from_value::<CreateElement>(data)?)
+                    .await?;
+                (element.id.clone(), serde_json::to_value(&element)?)
+            }
+            WebSocketMessage::ElementUpdate { id, data, .. } => {
+                let data = serde_json::from_value::<UpdateElement>(data)?;
+                let expected_version = data.version;
+                let element = mutation
+                    .update_element(&mut tx, &id, data, expected_version)
+                    .await?;
+                (id, serde_json::to_value(&element)?)
+            }
+            WebSocketMessage::ElementDelete { id, .. } => {
+                mutation.delete_element(&mut tx, &id).await?;
+                (id, serde_json::Value::Null)
+            }
+            WebSocketMessage::RelationshipUpdate { id, data, .. } if id.is_empty() => {
+                let relationship = mutation
+                    .create_relationship(&mut tx, serde_json::from_value::<CreateRelationship>(data)?)
+                    .await?;
+                (relationship.id.clone(), serde_json::to_value(&relationship)?)
+            }
+            WebSocketMessage::RelationshipUpdate { id, data, .. } => {
+                let data = serde_json::from_value::<UpdateRelationship>(data)?;
+                let expected_version = data.version;
+                let relationship = mutation
+                    .update_relationship(&mut tx, &id, data, expected_version)
+                    .await?;
+                (id, serde_json::to_value(&relationship)?)
+            }
+            WebSocketMessage::RelationshipDelete { id, .. } => {
+                mutation.delete_relationship(&mut tx, &id).await?;
+                (id, serde_json::Value::Null)
+            }
+            WebSocketMessage::ViewUpdate { view_type, state, .. } => {
+                let data = UpdateView {
+                    version: None,
+                    state: serde_json::from_value::<ViewState>(state)?,
+                };
+                let view = mutation.update_view(&mut tx, &view_type, data, None).await?;
+                (view.id.clone(), serde_json::to_value(&view)?)
+            }
+            _ => {
+                return Err(AppError::InvalidRequest(
+                    "Unsupported message type".to_string(),
+                ))
+            }
+        };
+
+        mutation.commit(tx).await?;
+        mutation.publish();
+        Ok(result)
+    }
+
+    // 接続後にロールが変更されていることもあるため、REST と同じく要求ごとに権限を確認する
+    async fn acquire_locks(&self, element_ids: Vec<String>) -> Result<()> {
+        let role =
+            access::authorize(&self.state.db, &self.project_id, &self.user, Permission::View).await?;
+        if role == ProjectRole::Viewer {
+            return Err(AppError::Forbidden("Viewers cannot lock elements".to_string()));
+        }
+        if element_ids.len() > MAX_BATCH_OPERATIONS {
+            return Err(AppError::InvalidRequest(format!(
+                "A lock request can contain at most {} elements",
+                MAX_BATCH_OPERATIONS
+            )));
+        }
+        let missing = db::missing_element_ids(&self.state.db, &self.project_id, &element_ids).await?;
+        if !missing.is_empty() {
+            return Err(AppError::NotFound(format!(
+                "Element not found: {}",
+                missing.join(", ")
+            )));
+        }
+
+        let manager = &self.state.ws_manager;
+        let timestamp = OffsetDateTime::now_utc().to_string();
+        match manager.acquire_locks(&self.project_id, &element_ids, &self.user.id, &self.connection_id) {
+            Ok(()) => {
+                manager.broadcast(
+                    &self.project_id,
+                    WebSocketMessage::LockAcquire {
+                        project_id: self.project_id.clone(),
+                        element_ids,
+                        timestamp,
+                        user_id: self.user.id.clone(),
+                    },
+                );
+            }
+            Err((element_ids, held_by)) => self.reply(&WebSocketMessage::LockDenied {
+                project_id: self.project_id.clone(),
+                element_ids,
+                held_by,
+                timestamp,
+                user_id: self.user.id.clone(),
+            }),
+        }
+        Ok(())
+    }
+
+    fn release_locks(&self, element_ids: Vec<String>) {
+        let manager = &self.state.ws_manager;
+        let released = manager.release_locks(&self.project_id, &element_ids, &self.user.id);
+        if !released.is_empty() {
+            manager.broadcast(
+                &self.project_id,
+                WebSocketMessage::LockRelease {
+                    project_id: self.project_id.clone(),
+                    element_ids: released,
+                    timestamp: OffsetDateTime::now_utc().to_string(),
+                    user_id: self.user.id.clone(),
+                },
+            );
+        }
+    }
+
+    fn update_presence(
+        &self,
+        cursor: Option<Point>,
+        selection: Option<Vec<String>>,
+        view_type: Option<String>,
+    ) {
+        let manager = &self.state.ws_manager;
+        let updated = manager.update_presence(
+            &self.project_id,
+            &self.connection_id,
+            cursor,
+            selection,
+            view_type,
+        );
+        if let Some(presence) = updated {
+            manager.broadcast(
+                &self.project_id,
+                WebSocketMessage::PresenceUpdate {
+                    project_id: self.project_id.clone(),
+                    cursor: presence.cursor,
+                    selection: Some(presence.selection),
+                    view_type: presence.view_type,
+                    connection_id: presence.connection_id,
+                    timestamp: OffsetDateTime::now_utc().to_string(),
+                    user_id: self.user.id.clone(),
+                },
+            );
+        }
+    }
 }
 
 // WebSocket接続の処理
-async fn handle_socket(socket: WebSocket, project_id: String, manager: ConnectionManager) {
-    let (mut sender, mut receiver) = socket.split();
+async fn handle_socket(
+    socket: WebSocket,
+    state: AppState,
+    project_id: String,
+    user: AuthUser,
+    since: Option<i64>,
+) {
+    let (sender, mut receiver) = socket.split();
+    let manager = state.ws_manager.clone();
+    let user_id = user.id.clone();
+
+    // 再送の起点（指定がなければ現在の最新番号から）
+    // 購読前に読むことで、その間の通知は番号の飛びとして再送される
+    let start_seq = match since {
+        Some(since) => since,
+        None => match db::latest_event_seq(&state.db, &project_id).await {
+            Ok(seq) => seq,
+            Err(e) => {
+                error!("Failed to read event log for project {}: {}", project_id, e);
+                return;
+            }
+        },
+    };
 
     // プロジェクトのチャンネル取得
-    let tx = manager.get_or_create_channel(&project_id);
-    let mut rx = tx.subscribe();
+    let channel = manager.get_or_create_channel(&project_id);
+    let rx = channel.subscribe();
 
     info!("WebSocket connection established for project: {}", project_id);
 
+    // 接続元クライアントへの直接返信用チャンネル
+    let (direct_tx, direct_rx) = mpsc::unbounded_channel::<Message>();
+
+    // 接続ごとのID（切断時にこの接続のロックを解放するため）
+    let connection_id = Uuid::new_v4().to_string();
+
+    let replay = Replay {
+        db: state.db.clone(),
+        project_id: project_id.clone(),
+        user_id: user_id.clone(),
+    };
+    let session = Session {
+        state,
+        project_id: project_id.clone(),
+        user,
+        connection_id: connection_id.clone(),
+        direct_tx,
+    };
+
+    // 在席者一覧に加え、接続元へ一覧を、他の接続へ参加を通知する
+    let presence = Presence::new(
+        connection_id.clone(),
+        user_id.clone(),
+        session.user.username.clone(),
+    );
+    manager.join(&project_id, presence.clone());
+    session.reply(&WebSocketMessage::PresenceRoster {
+        project_id: project_id.clone(),
+        users: manager.roster(&project_id),
+        timestamp: OffsetDateTime::now_utc().to_string(),
+        user_id: user_id.clone(),
+    });
+    manager.broadcast(
+        &project_id,
+        WebSocketMessage::PresenceJoin {
+            project_id: project_id.clone(),
+            presence,
+            timestamp: OffsetDateTime::now_utc().to_string(),
+            user_id: user_id.clone(),
+        },
+    );
+
     // 受信ループ
     let mut recv_task = tokio::spawn(async move {
         while let Some(result) = receiver.next().await {
             match result {
                 Ok(msg) => {
                     match msg {
-                        Message::Text(text) => {
-                            match serde_json::from_str::<WebSocketMessage>(&text) {
-                                Ok(ws_msg) => {
-                                    if let Err(e) = tx.send(ws_msg) {
-                                        error!("Failed to broadcast message: {}", e);
-                                    }
-                                }
-                                Err(e) => {
-                                    error!("Failed to parse message: {}", e);
-                                    // エラーメッセージを送信
-                                    if let Err(e) = sender.send(Message::Text(format!("{{\"error\": \"Invalid message format: {}\"}}", e))).await {
-                                        error!("Failed to send error message: {}", e);
-                                    }
-                                }
-                            }
-                        }
+                        Message::Text(text) => session.handle_text(&text).await,
                         Message::Close(reason) => {
                             info!("Client disconnected: {:?}", reason);
                             break;
                         }
                         Message::Ping(data) => {
-                            if let Err(e) = sender.send(Message::Pong(data)).await {
+                            if let Err(e) = session.direct_tx.send(Message::Pong(data)) {
                                 error!("Failed to send pong: {}", e);
                             }
                         }
@@ -157,30 +1017,7 @@ async fn handle_socket(socket: WebSocket, project_id: String, manager: Connectio
     });
 
     // 送信ループ
-    let mut send_task = tokio::spawn(async move {
-        while let Ok(msg) = rx.recv().await {
-            match serde_json::to_string(&msg) {
-                Ok(text) => {
-                    // タイムアウト付きで送信
-                    match timeout(Duration::from_secs(5), sender.send(Message::Text(text))).await {
-                        Ok(result) => {
-                            if let Err(e) = result {
-                                error!("Failed to send message: {}", e);
-                                break;
-                            }
-                        }
-                        Err(_) => {
-                            error!("Send timeout");
-                            break;
-                        }
-                    }
-                }
-                Err(e) => {
-                    error!("Failed to serialize message: {}", e);
-                }
-            }
-        }
-    });
+    let mut send_task = tokio::spawn(send_loop(sender, rx, direct_rx, replay, start_seq));
 
     // どちらかのタスクが終了したら両方を終了
     tokio::select! {
@@ -195,9 +1032,172 @@ async fn handle_socket(socket: WebSocket, project_id: String, manager: Connectio
     };
 
     // 接続が切れた時の処理
-    if rx.receiver_count() == 0 {
+    if manager.leave(&project_id, &connection_id).is_some() {
+        manager.broadcast(
+            &project_id,
+            WebSocketMessage::PresenceLeave {
+                project_id: project_id.clone(),
+                connection_id: connection_id.clone(),
+                timestamp: OffsetDateTime::now_utc().to_string(),
+                user_id: user_id.clone(),
+            },
+        );
+    }
+    let released = manager.release_connection(&project_id, &connection_id);
+    if !released.is_empty() {
+        manager.broadcast(
+            &project_id,
+            WebSocketMessage::LockRelease {
+                project_id: project_id.clone(),
+                element_ids: released,
+                timestamp: OffsetDateTime::now_utc().to_string(),
+                user_id: user_id.clone(),
+            },
+        );
+    }
+    if channel.receiver_count() == 0 {
         manager.remove_channel(&project_id);
     }
-    
+
     info!("WebSocket connection closed for project: {}", project_id);
-} 
\ No newline at end of file
+}
+
+// 送信ループ
+// 送った通し番号を追跡し、番号の飛びや受信の遅れで取りこぼした通知は記録から再送する
+async fn send_loop(
+    mut sender: SplitSink<WebSocket, Message>,
+    mut rx: broadcast::Receiver<Envelope>,
+    mut direct_rx: mpsc::UnboundedReceiver<Message>,
+    replay: Replay,
+    mut last_seq: i64,
+) {
+    if !replay.catch_up(&mut sender, &mut last_seq).await {
+        return;
+    }
+
+    loop {
+        // 接続元への直接返信（在席者一覧など）を優先する
+        let outgoing = tokio::select! {
+            biased;
+            Some(msg) = direct_rx.recv() => msg,
+            result = rx.recv() => match result {
+                Ok(envelope) => {
+                    if let (Some(first), Some(seq)) = (envelope.first(), envelope.seq) {
+                        if first > last_seq + 1 && !replay.catch_up(&mut sender, &mut last_seq).await {
+                            break;
+                        }
+                        // 再送済み
+                        if seq <= last_seq {
+                            continue;
+                        }
+                        last_seq = seq;
+                    }
+                    match serde_json::to_string(&envelope) {
+                        Ok(text) => Message::Text(text),
+                        Err(e) => {
+                            error!("Failed to serialize message: {}", e);
+                            continue;
+                        }
+                    }
+                }
+                Err(RecvError::Lagged(skipped)) => {
+                    warn!(
+                        "Connection lagged behind by {} messages for project: {}",
+                        skipped, replay.project_id
+                    );
+                    if !replay.catch_up(&mut sender, &mut last_seq).await {
+                        break;
+                    }
+                    continue;
+                }
+                Err(RecvError::Closed) => break,
+            },
+        };
+
+        if !send_with_timeout(&mut sender, outgoing).await {
+            break;
+        }
+    }
+}
+
+// 一度に再送する通知の上限（超える場合は全体の再取得を指示する）
+const MAX_REPLAY_EVENTS: i64 = 1000;
+
+// 記録からの再送
+struct Replay {
+    db: SqlitePool,
+    project_id: String,
+    user_id: String,
+}
+
+impl Replay {
+    // last_seq より後の通知を送り、last_seq を進める（接続が切れたら false）
+    async fn catch_up(&self, sender: &mut SplitSink<WebSocket, Message>, last_seq: &mut i64) -> bool {
+        let latest = match db::latest_event_seq(&self.db, &self.project_id).await {
+            Ok(latest) => latest,
+            Err(e) => {
+                error!("Failed to read event log for project {}: {}", self.project_id, e);
+                return true;
+            }
+        };
+        if latest == *last_seq {
+            return true;
+        }
+
+        // 記録より先の番号（記録の消去後など）や、取りこぼしが多すぎる場合は全体を取得し直させる
+        if *last_seq > latest || latest - *last_seq > MAX_REPLAY_EVENTS {
+            let resync = Envelope::unsequenced(WebSocketMessage::ResyncRequired {
+                project_id: self.project_id.clone(),
+                latest_seq: latest,
+                timestamp: OffsetDateTime::now_utc().to_string(),
+                user_id: self.user_id.clone(),
+            });
+            *last_seq = latest;
+            return match serde_json::to_string(&resync) {
+                Ok(text) => send_with_timeout(sender, Message::Text(text)).await,
+                Err(e) => {
+                    error!("Failed to serialize message: {}", e);
+                    true
+                }
+            };
+        }
+
+        let events =
+            match db::list_events_since(&self.db, &self.project_id, *last_seq, MAX_REPLAY_EVENTS).await {
+                Ok(events) => events,
+                Err(e) => {
+                    error!("Failed to read event log for project {}: {}", self.project_id, e);
+                    return true;
+                }
+            };
+        if let Some(event) = events.last() {
+            *last_seq = event.seq;
+        }
+        for envelope in Envelope::from_events(&events) {
+            match serde_json::to_string(&envelope) {
+                Ok(text) => {
+                    if !send_with_timeout(sender, Message::Text(text)).await {
+                        return false;
+                    }
+                }
+                Err(e) => error!("Failed to replay event {:?}: {}", envelope.seq, e),
+            }
+        }
+        true
+    }
+}
+
+// タイムアウト付きで送信する（失敗したら false）
+async fn send_with_timeout(sender: &mut SplitSink<WebSocket, Message>, message: Message) -> bool {
+    match timeout(Duration::from_secs(5), sender.send(message)).await {
+        Ok(Ok(())) => true,
+        Ok(Err(e)) => {
+            error!("Failed to send message: {}", e);
+            false
+        }
+        Err(_) => {
+            error!("Send timeout");
+            false
+        }
+    }
+}